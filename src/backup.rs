@@ -0,0 +1,382 @@
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Local};
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+use std::fs;
+
+use crate::fs_utils::{get_fs_type, move_path, FsType};
+
+/// An original file, directory or foreign symlink that doty moved out of the way
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupEntry {
+    /// Folder name inside the store, e.g. `20251122-143015-1`
+    pub id: String,
+    /// Absolute path the original lived at
+    pub path: Utf8PathBuf,
+    pub kind: FsType,
+    /// Unix permission bits of the original (0 where not available)
+    pub mode: u32,
+    pub created: DateTime<Local>,
+}
+
+/// Per-host backup store under `.doty/backups/<hostname>/`
+/// Each backup lives in its own `<id>/` folder, `manifest.kdl` records where it came from
+#[derive(Debug)]
+pub struct BackupStore {
+    dir: Utf8PathBuf,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl BackupStore {
+    /// Load the store for a host, or start an empty one if nothing was backed up yet
+    pub fn load(backups_dir: &Utf8Path, hostname: &str) -> Result<Self> {
+        let dir = backups_dir.join(hostname);
+        let manifest_path = dir.join("manifest.kdl");
+
+        if !manifest_path.exists() {
+            return Ok(Self {
+                dir,
+                entries: Vec::new(),
+            });
+        }
+
+        let content = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read backup manifest: {}", manifest_path))?;
+        Self::from_str(&content, dir)
+    }
+
+    /// Parse a manifest from KDL string
+    fn from_str(content: &str, dir: Utf8PathBuf) -> Result<Self> {
+        let doc: KdlDocument = content
+            .parse()
+            .context("Failed to parse KDL backup manifest")?;
+
+        let mut entries = Vec::new();
+        for node in doc.nodes() {
+            if node.name().value() == "backup" {
+                entries.push(Self::parse_backup_node(node)?);
+            }
+        }
+
+        Ok(Self { dir, entries })
+    }
+
+    fn parse_backup_node(node: &KdlNode) -> Result<BackupEntry> {
+        let prop = |name: &str| {
+            node.get(name)
+                .and_then(|v| v.as_string())
+                .with_context(|| format!("Missing '{}' in backup node", name))
+        };
+
+        let kind = match prop("type")? {
+            "file" => FsType::File,
+            "directory" => FsType::Directory,
+            "symlink" => FsType::Symlink,
+            other => anyhow::bail!("Unknown backup type: {}", other),
+        };
+        let mode_str = prop("mode")?;
+        let mode = u32::from_str_radix(mode_str, 8)
+            .with_context(|| format!("Invalid mode in backup node: {}", mode_str))?;
+        let created = DateTime::parse_from_rfc3339(prop("created")?)
+            .context("Invalid 'created' timestamp in backup node")?
+            .with_timezone(&Local);
+
+        Ok(BackupEntry {
+            id: prop("id")?.to_string(),
+            path: Utf8PathBuf::from(prop("path")?),
+            kind,
+            mode,
+            created,
+        })
+    }
+
+    /// Convert the manifest to KDL format string
+    pub fn to_kdl(&self) -> String {
+        let mut doc = KdlDocument::new();
+
+        let mut version_node = KdlNode::new("manifestVersion");
+        version_node.push(KdlEntry::new(1));
+        doc.nodes_mut().push(version_node);
+
+        for entry in &self.entries {
+            let mut node = KdlNode::new("backup");
+            node.push(KdlEntry::new_prop("id", entry.id.as_str()));
+            node.push(KdlEntry::new_prop("path", entry.path.as_str()));
+            node.push(KdlEntry::new_prop("type", entry.kind.to_string()));
+            node.push(KdlEntry::new_prop("mode", format!("{:04o}", entry.mode)));
+            node.push(KdlEntry::new_prop("created", entry.created.to_rfc3339()));
+            doc.nodes_mut().push(node);
+        }
+
+        doc.to_string()
+    }
+
+    /// Write the manifest to `<store>/manifest.kdl`
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create backup directory: {}", self.dir))?;
+        let manifest_path = self.dir.join("manifest.kdl");
+        fs::write(&manifest_path, self.to_kdl())
+            .with_context(|| format!("Failed to write backup manifest: {}", manifest_path))
+    }
+
+    /// Where the stored copy of a backup lives
+    pub fn stored_path(&self, entry: &BackupEntry) -> Utf8PathBuf {
+        let name = entry.path.file_name().unwrap_or("root");
+        self.dir.join(&entry.id).join(name)
+    }
+
+    /// Move `path` into the store and record it in the manifest
+    pub fn back_up(&mut self, path: &Utf8Path) -> Result<BackupEntry> {
+        let kind = get_fs_type(path)?.with_context(|| format!("Nothing to back up at {}", path))?;
+        let metadata = fs::symlink_metadata(path)?;
+        let created = Local::now();
+
+        let stamp = created.format("%Y%m%d-%H%M%S").to_string();
+        let mut counter = 1;
+        let id = loop {
+            let id = format!("{}-{}", stamp, counter);
+            if !self.dir.join(&id).exists() && !self.entries.iter().any(|e| e.id == id) {
+                break id;
+            }
+            counter += 1;
+        };
+
+        let entry = BackupEntry {
+            id,
+            path: path.to_path_buf(),
+            kind,
+            mode: file_mode(&metadata),
+            created,
+        };
+        move_path(path, &self.stored_path(&entry))
+            .with_context(|| format!("Failed to back up {}", path))?;
+
+        self.entries.push(entry.clone());
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// Most recent backup of a path
+    pub fn latest(&self, path: &Utf8Path) -> Option<&BackupEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.path == path)
+            .max_by_key(|e| e.created)
+    }
+
+    /// Most recent backup of every path in the store, sorted by path
+    pub fn latest_per_path(&self) -> Vec<&BackupEntry> {
+        let mut paths: Vec<&Utf8PathBuf> = self.entries.iter().map(|e| &e.path).collect();
+        paths.sort();
+        paths.dedup();
+        paths.into_iter().filter_map(|p| self.latest(p)).collect()
+    }
+
+    /// Move a backup back to its original location and drop it from the store
+    /// The original location must be free
+    pub fn restore(&mut self, id: &str) -> Result<BackupEntry> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.id == id)
+            .with_context(|| format!("No backup with id {}", id))?;
+        let entry = self.entries[index].clone();
+
+        if let Some(existing) = get_fs_type(&entry.path)? {
+            anyhow::bail!(
+                "Cannot restore {}: a {} is in the way",
+                entry.path,
+                existing
+            );
+        }
+
+        move_path(&self.stored_path(&entry), &entry.path)
+            .with_context(|| format!("Failed to restore {}", entry.path))?;
+        #[cfg(unix)]
+        if entry.kind != FsType::Symlink {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&entry.path, fs::Permissions::from_mode(entry.mode))?;
+        }
+
+        self.remove_entry(index)?;
+        Ok(entry)
+    }
+
+    /// Delete old backups, keeping the `keep` most recent ones of every path
+    /// With a cutoff, only backups created before it are deleted
+    /// Returns the deleted entries (nothing is touched on dry run)
+    pub fn prune(
+        &mut self,
+        keep: usize,
+        cutoff: Option<DateTime<Local>>,
+        dry_run: bool,
+    ) -> Result<Vec<BackupEntry>> {
+        let mut doomed = Vec::new();
+        for latest in self.latest_per_path() {
+            let mut versions: Vec<&BackupEntry> = self
+                .entries
+                .iter()
+                .filter(|e| e.path == latest.path)
+                .collect();
+            versions.sort_by_key(|e| std::cmp::Reverse(e.created));
+            doomed.extend(
+                versions
+                    .into_iter()
+                    .skip(keep)
+                    .filter(|e| cutoff.is_none_or(|c| e.created < c))
+                    .cloned(),
+            );
+        }
+
+        if !dry_run {
+            for entry in &doomed {
+                let index = self.entries.iter().position(|e| e.id == entry.id).unwrap();
+                self.remove_entry(index)?;
+            }
+        }
+        Ok(doomed)
+    }
+
+    /// Delete a backup folder and its manifest record
+    fn remove_entry(&mut self, index: usize) -> Result<()> {
+        let entry = self.entries.remove(index);
+        let entry_dir = self.dir.join(&entry.id);
+        if entry_dir.exists() {
+            fs::remove_dir_all(&entry_dir)
+                .with_context(|| format!("Failed to remove backup: {}", entry_dir))?;
+        }
+        self.save()
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, Utf8PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        (temp_dir, root)
+    }
+
+    #[test]
+    fn test_back_up_and_restore_file() {
+        let (_temp, root) = setup();
+        let original = root.join("home/.zshrc");
+        fs::create_dir_all(original.parent().unwrap()).unwrap();
+        fs::write(&original, "export EDITOR=vim").unwrap();
+
+        let backups_dir = root.join(".doty/backups");
+        let mut store = BackupStore::load(&backups_dir, "test-host").unwrap();
+        let entry = store.back_up(&original).unwrap();
+
+        assert!(!original.exists());
+        assert_eq!(entry.kind, FsType::File);
+        assert!(store
+            .stored_path(&entry)
+            .starts_with(backups_dir.join("test-host")));
+        assert_eq!(
+            fs::read_to_string(store.stored_path(&entry)).unwrap(),
+            "export EDITOR=vim"
+        );
+
+        // Manifest survives a reload
+        let mut store = BackupStore::load(&backups_dir, "test-host").unwrap();
+        assert_eq!(store.entries, vec![entry.clone()]);
+
+        store.restore(&entry.id).unwrap();
+        assert_eq!(fs::read_to_string(&original).unwrap(), "export EDITOR=vim");
+        assert!(store.entries.is_empty());
+        assert!(!backups_dir.join("test-host").join(&entry.id).exists());
+    }
+
+    #[test]
+    fn test_restore_refuses_occupied_path() {
+        let (_temp, root) = setup();
+        let original = root.join("nvim");
+        fs::create_dir_all(&original).unwrap();
+
+        let mut store = BackupStore::load(&root.join(".doty/backups"), "test-host").unwrap();
+        let entry = store.back_up(&original).unwrap();
+        assert_eq!(entry.kind, FsType::Directory);
+
+        fs::write(&original, "in the way").unwrap();
+        let err = store.restore(&entry.id).unwrap_err();
+        assert!(err.to_string().contains("a file is in the way"));
+        assert_eq!(store.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let (_temp, root) = setup();
+        let mut store = BackupStore::load(&root, "test-host").unwrap();
+        store.entries.push(BackupEntry {
+            id: "20251122-143015-1".to_string(),
+            path: Utf8PathBuf::from("/home/user/.config/nvim"),
+            kind: FsType::Directory,
+            mode: 0o755,
+            created: DateTime::parse_from_rfc3339("2025-11-22T14:30:15+01:00")
+                .unwrap()
+                .with_timezone(&Local),
+        });
+
+        let kdl = store.to_kdl();
+        assert!(kdl.contains("mode=\"0755\""));
+        assert!(kdl.contains("type=directory"));
+
+        let parsed = BackupStore::from_str(&kdl, root.join("test-host")).unwrap();
+        assert_eq!(parsed.entries, store.entries);
+    }
+
+    #[test]
+    fn test_prune_keeps_latest_per_path() {
+        let (_temp, root) = setup();
+        let mut store = BackupStore::load(&root, "test-host").unwrap();
+        let now = Local::now();
+        for (id, path, days_ago) in [
+            ("a-1", "/home/user/.zshrc", 30),
+            ("a-2", "/home/user/.zshrc", 10),
+            ("a-3", "/home/user/.zshrc", 1),
+            ("b-1", "/home/user/.vimrc", 30),
+        ] {
+            store.entries.push(BackupEntry {
+                id: id.to_string(),
+                path: Utf8PathBuf::from(path),
+                kind: FsType::File,
+                mode: 0o644,
+                created: now - Duration::days(days_ago),
+            });
+        }
+
+        // Dry run reports but keeps everything
+        let pruned = store.prune(1, None, true).unwrap();
+        assert_eq!(pruned.len(), 2);
+        assert_eq!(store.entries.len(), 4);
+
+        // Only backups older than the cutoff go
+        let pruned = store
+            .prune(1, Some(now - Duration::days(20)), false)
+            .unwrap();
+        let ids: Vec<_> = pruned.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["a-1"]);
+
+        let pruned = store.prune(1, None, false).unwrap();
+        let ids: Vec<_> = pruned.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["a-2"]);
+        assert_eq!(store.latest_per_path().len(), 2);
+    }
+}
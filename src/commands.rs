@@ -1,527 +1,844 @@
 use anyhow::{Context, Result};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use colored::Colorize;
-use dialoguer::Confirm;
+use dialoguer::{Confirm, Input, Select};
 use pluralizer::pluralize;
-use std::env;
-
-use crate::config::{DotyConfig, LinkStrategy, PathResolution};
-use crate::linker::{LinkAction, Linker};
-use crate::lockfile::Lockfile;
-use crate::scanner::{DriftType, Scanner};
+use std::rc::Rc;
+
+use doty::backup::{BackupEntry, BackupStore};
+use doty::condition::HostInfo;
+use doty::config::{DotyConfig, LinkStrategy, Package};
+use doty::dotyignore::{append_to_dotyignore, DOTYIGNORE_FILE};
+use doty::error;
+use doty::fs_utils::{
+    contract_home_path, current_dir, get_fs_type, hash_file, move_path, remove_symlink,
+    resolve_target_path, unified_diff, FsType,
+};
+use doty::linker::{LinkAction, LinkHealth};
+use doty::lockfile::Lockfile;
+use doty::output::Outcome;
+use doty::reporter::{ActionCounts, Event, Reporter};
+use doty::scanner::DriftItem;
+use doty::template::TemplateContext;
+use doty::{
+    ApplyOptions, Deployment, DotyError, Generations, PackageStatus, Repo, Restored, RollbackPlan,
+};
+
+use crate::format::say;
 
 /// Execute link command
-pub fn link(config_path: Utf8PathBuf, dry_run: bool, force: bool) -> Result<()> {
-    // Get hostname
-    let hostname = hostname::get()?.to_string_lossy().to_string();
-
-    // Load config to determine the path resolution strategy
-    let config = DotyConfig::from_file(&config_path).context("Failed to load configuration")?;
-
-    // Determine repo root based on path resolution strategy
-    let config_dir_or_cwd = match config.path_resolution {
-        PathResolution::Config => {
-            // Resolve relative to config file location
-            let config_dir = config_path
-                .parent()
-                .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
-
-            // Canonicalize to get absolute path
-            let abs_path = if config_dir.as_str().is_empty() || config_dir == "." {
-                Utf8PathBuf::from_path_buf(env::current_dir()?)
-                    .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
-            } else {
-                config_dir.canonicalize_utf8()?
-            };
-
-            abs_path
-        }
-        PathResolution::Cwd => {
-            // Resolve relative to current working directory
-            Utf8PathBuf::from_path_buf(env::current_dir()?)
-                .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
-        }
+pub fn link(
+    config_path: Utf8PathBuf,
+    dry_run: bool,
+    force: bool,
+    keep_going: bool,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    say!();
+
+    let options = ApplyOptions {
+        dry_run,
+        force,
+        keep_going,
     };
+    let deployment = open_deployment(&repo, options)?;
+    let actions = deployment.plan(&repo.config)?;
+    apply_plan(deployment, actions, options, "link", reporter)
+}
 
-    println!("{:<10} {}", "Config:", config_path);
-    println!("{:<10} {}\n", "BasePath:", config_dir_or_cwd);
-
-    // Load lockfile
-    let lockfile_dir = config_dir_or_cwd.join(".doty/state");
-    let mut lockfile = Lockfile::load(&lockfile_dir, &hostname, config_dir_or_cwd.clone())
-        .context("Failed to load lockfile")?;
-
-    // Create linker
-    let linker = Linker::new(config_dir_or_cwd.clone(), config.path_resolution);
-
-    // Calculate diff using the new linker API
-    let actions = linker
-        .calculate_diff(&config, &lockfile, force)
-        .context("Failed to calculate diff")?;
-
-    // Group actions by package for output
-    let mut package_actions: std::collections::HashMap<String, Vec<&LinkAction>> =
-        std::collections::HashMap::new();
-    let mut orphaned_actions = Vec::new();
-
-    for action in &actions {
-        match action {
-            LinkAction::Created { target, .. }
-            | LinkAction::Updated { target, .. }
-            | LinkAction::Skipped { target, .. }
-            | LinkAction::Warning { target, .. }
-            | LinkAction::Pruned { target, .. } => {
-                // Find which package this target belongs to
-                let mut found_package = false;
-                for package in &config.packages {
-                    if target.starts_with(&package.target) {
-                        let package_key = format!(
-                            "{} {} → {}",
-                            match package.strategy {
-                                LinkStrategy::LinkFolder => "LinkFolder",
-                                LinkStrategy::LinkFilesRecursive => "LinkFilesRecursive",
-                            },
-                            package.source,
-                            package.target
-                        );
-                        package_actions
-                            .entry(package_key)
-                            .or_insert_with(Vec::new)
-                            .push(action);
-                        found_package = true;
-                        break;
-                    }
-                }
-                if !found_package {
-                    orphaned_actions.push(action);
-                }
-            }
-            LinkAction::Removed { target, .. } => {
-                // Check if this target belongs to any current package
-                let mut found_package = false;
-                for package in &config.packages {
-                    if target.starts_with(&package.target) {
-                        let package_key = format!(
-                            "{} {} → {}",
-                            match package.strategy {
-                                LinkStrategy::LinkFolder => "LinkFolder",
-                                LinkStrategy::LinkFilesRecursive => "LinkFilesRecursive",
-                            },
-                            package.source,
-                            package.target
-                        );
-                        package_actions
-                            .entry(package_key)
-                            .or_insert_with(Vec::new)
-                            .push(action);
-                        found_package = true;
-                        break;
-                    }
-                }
-                if !found_package {
-                    orphaned_actions.push(action);
-                }
-            }
-        }
+/// Prepare a link or rollback run, telling about an interrupted previous run
+fn open_deployment(repo: &Repo, options: ApplyOptions) -> Result<Deployment> {
+    let deployment = repo.deployment(options)?;
+    match deployment.interrupted {
+        Some(steps) if options.dry_run => say!(
+            "{} A previous run was interrupted, its {} will be rolled back first\n",
+            "[!]".yellow().bold(),
+            pluralize("step", steps as isize, true)
+        ),
+        Some(steps) => say!(
+            "{} Rolled back {} of an interrupted previous run\n",
+            "[!]".yellow().bold(),
+            pluralize("step", steps as isize, true)
+        ),
+        None => {}
     }
+    Ok(deployment)
+}
 
-    // Print actions grouped by package
-    for (package_key, actions) in package_actions {
-        // Filter out skipped actions for display
-        let display_actions: Vec<&&LinkAction> = actions
-            .iter()
-            .filter(|a| !matches!(**a, LinkAction::Skipped { .. }))
-            .collect();
-
-        if display_actions.is_empty() {
-            continue;
-        }
-
-        println!("\n{}", package_key.bold());
-        for action in display_actions {
-            match action {
-                LinkAction::Created { target, source } => {
-                    println!("  {} {} → {}", "[+]".green().bold(), target, source);
-                }
-                LinkAction::Updated {
-                    target,
-                    old_source,
-                    new_source,
-                } => {
-                    println!(
-                        "  {} {} → {} {}",
-                        "[~]".yellow().bold(),
-                        target,
-                        new_source,
-                        format!("(was: {})", old_source).dimmed()
-                    );
-                }
-                LinkAction::Skipped { .. } => {
-                    // Do not print skipped links
-                }
-                LinkAction::Removed { target, source } => {
-                    println!("  {} {} → {}", "[-]".red().bold(), target, source);
-                }
-                LinkAction::Pruned { target, source } => {
-                    // Make source path relative to basePath if possible
-                    let source_display = source
-                        .strip_prefix(&config_dir_or_cwd)
-                        .map(|p| p.to_string())
-                        .unwrap_or(source.to_string());
-                    println!("  {} {} → {}", "[x]".red().bold(), target, source_display);
-                    println!("      Pruned: Source is missing, dangling symlink removed");
-                }
-                LinkAction::Warning {
-                    target,
-                    source,
-                    message,
-                } => {
-                    println!("  {} {} → {}", "[!]".yellow().bold(), target, source);
-                    println!("      Warning: {}", message);
-                }
+/// Apply a plan and print where the lockfile went, the linker reports every action
+fn apply_plan(
+    deployment: Deployment,
+    actions: Vec<LinkAction>,
+    options: ApplyOptions,
+    command: &str,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let hostname = deployment.hostname.clone();
+    let applied = deployment.apply(actions, command)?;
+    let dry_run = options.dry_run;
+
+    if !applied.blocking_conflicts.is_empty() {
+        say!(
+            "\n{} Link would abort: {}",
+            "[DRY RUN]".yellow().bold(),
+            DotyError::Conflicts {
+                targets: applied.blocking_conflicts
             }
-        }
+        );
     }
-
-    // Print orphaned actions
-    if !orphaned_actions.is_empty() {
-        println!("\n{}", "Orphaned links:".bold());
-        for action in orphaned_actions {
-            match action {
-                LinkAction::Removed { target, source } => {
-                    println!("  {} {} → {}", "[-]".red().bold(), target, source);
-                }
-                _ => {} // Shouldn't happen for orphaned actions
-            }
+    if dry_run {
+        say!("\n{}", "[DRY RUN] No changes were made".yellow().bold());
+    } else {
+        match applied.generation {
+            Some(number) => say!(
+                "\n{} Lockfile saved to .doty/state/{}.lock.kdl (generation {})",
+                "✓".green().bold(),
+                hostname,
+                number
+            ),
+            None => say!(
+                "\n{} Lockfile saved to .doty/state/{}.lock.kdl",
+                "✓".green().bold(),
+                hostname
+            ),
         }
     }
+    reporter.event(&Event::ActionsSummary {
+        counts: applied.counts,
+        dry_run,
+    });
+
+    // With --keep-going, every failure was reported as it happened
+    if !applied.failures.is_empty() {
+        anyhow::bail!(
+            "{} failed, the lockfile records everything else that was applied",
+            pluralize("link", applied.failures.len() as isize, true)
+        );
+    }
 
-    // Execute actions and update state
-    for action in &actions {
-        linker.execute_action(action, dry_run)?;
+    if dry_run && applied.counts.has_changes() {
+        Ok(Outcome::Pending)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
 
-        // Update lockfile
-        if !dry_run {
-            match action {
-                LinkAction::Created { target, source } => {
-                    lockfile.add_link(target.clone(), source.clone());
-                }
-                LinkAction::Updated {
-                    target, new_source, ..
-                } => {
-                    lockfile.add_link(target.clone(), new_source.clone());
-                }
-                LinkAction::Removed { target, .. } => {
-                    lockfile.remove_link(target);
-                }
-                LinkAction::Pruned { target, .. } => {
-                    lockfile.remove_link(target);
-                }
-                LinkAction::Warning { .. } | LinkAction::Skipped { .. } => {
-                    // Don't modify lockfile for warnings or skipped links
-                }
-            }
-        }
+/// Execute clean command
+pub fn clean(
+    config_path: Utf8PathBuf,
+    dry_run: bool,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    say!();
+
+    let lockfile = repo.load_lockfile()?;
+    if lockfile.links.is_empty() {
+        say!("No managed links found for host: {}", repo.hostname);
+        reporter.event(&Event::ActionsSummary {
+            counts: ActionCounts::default(),
+            dry_run,
+        });
+        return Ok(Outcome::Success);
     }
 
-    // Save lockfile
+    // Clean all links, the linker reports every removal
+    say!(
+        "Removing {} managed {}...\n",
+        lockfile.links.len(),
+        pluralize("link", lockfile.links.len() as isize, false)
+    );
+    let actions = repo.clean(dry_run)?;
+    let counts = ActionCounts::of(&actions);
+
     if !dry_run {
-        lockfile
-            .save(&lockfile_dir)
-            .context("Failed to save lockfile")?;
-        println!(
-            "\n{} Lockfile saved to .doty/state/{}.lock.kdl",
+        say!(
+            "\n{} Lockfile cleared for host: {}",
             "✓".green().bold(),
-            hostname
+            repo.hostname
         );
     } else {
-        println!("\n{}", "[DRY RUN] No changes were made".yellow().bold());
+        say!("\n{}", "[DRY RUN] No changes were made".yellow().bold());
     }
+    reporter.event(&Event::ActionsSummary { counts, dry_run });
 
-    // Summary
-    let created = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Created { .. }))
-        .count();
-    let updated = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Updated { .. }))
-        .count();
-    let skipped = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Skipped { .. }))
-        .count();
-    let removed = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Removed { .. }))
-        .count();
-    let pruned = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Pruned { .. }))
-        .count();
-    let warnings = actions
-        .iter()
-        .filter(|a| matches!(a, LinkAction::Warning { .. }))
-        .count();
+    if dry_run && counts.removed > 0 {
+        Ok(Outcome::Pending)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
 
-    if created > 0 || updated > 0 || removed > 0 || pruned > 0 || warnings > 0 {
-        println!("\n{}", "Summary:".bold());
-        if created > 0 {
-            println!(
-                "  {} {} added",
-                "[+]".green().bold(),
-                pluralize("link", created as isize, true)
-            );
-        }
-        if updated > 0 {
-            println!(
-                "  {} {} updated",
-                "[~]".yellow().bold(),
-                pluralize("link", updated as isize, true)
-            );
-        }
-        if removed > 0 {
-            println!(
-                "  {} {} removed",
-                "[-]".red().bold(),
-                pluralize("link", removed as isize, true)
-            );
-        }
-        if pruned > 0 {
-            println!(
-                "  {} {} pruned",
-                "[x]".red().bold(),
-                pluralize("link", pruned as isize, true)
+/// Execute adopt command
+pub fn adopt(
+    config_path: Utf8PathBuf,
+    path: Utf8PathBuf,
+    strategy: Option<LinkStrategy>,
+    source: Option<Utf8PathBuf>,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    let Repo {
+        config,
+        config_dir_or_cwd,
+        ..
+    } = &repo;
+    say!();
+
+    // The path comes from the command line, so relative paths are relative to the cwd
+    let target_path = resolve_target_path(&path, &current_dir()?)?;
+
+    match get_fs_type(&target_path)? {
+        None => anyhow::bail!("Path not found: {}", target_path),
+        Some(FsType::Symlink) => anyhow::bail!(
+            "{} is a symlink, only real files and directories can be adopted",
+            target_path
+        ),
+        Some(_) => {}
+    }
+
+    // Refuse to adopt something doty already manages
+    if repo.load_lockfile()?.is_managed(&target_path) {
+        anyhow::bail!("{} is already managed by doty", target_path);
+    }
+
+    // Compare real locations, a path below a LinkFolder target is a file in the repo already
+    let canonical = |path: &Utf8Path| path.canonicalize_utf8().unwrap_or(path.to_path_buf());
+    let real_target = canonical(&target_path);
+    for package in &config.packages {
+        let Ok(package_target) = resolve_target_path(&package.target, config_dir_or_cwd) else {
+            continue;
+        };
+        let package_target = canonical(&package_target);
+        if real_target == package_target
+            || (package.strategy == LinkStrategy::LinkFolder
+                && real_target.starts_with(&package_target))
+        {
+            anyhow::bail!(
+                "{} is already managed by package {} {} → {}",
+                target_path,
+                package.strategy,
+                package.source,
+                package.target
             );
         }
-        if warnings > 0 {
-            println!(
-                "  {} {}",
-                "[!]".yellow().bold(),
-                pluralize("warning", warnings as isize, true)
-            );
+    }
+
+    // Choose strategy (single files are always linked individually)
+    let strategy = match strategy {
+        Some(strategy) => strategy,
+        None if !target_path.is_dir() => LinkStrategy::LinkFilesRecursive,
+        None => {
+            let selection = Select::new()
+                .with_prompt("Choose a link strategy")
+                .items(&[
+                    "LinkFolder (symlink the whole directory)",
+                    "LinkFilesRecursive (symlink each file, keep untracked files in place)",
+                    "Copy (write real copies, for apps that replace symlinks)",
+                ])
+                .default(0)
+                .interact()?;
+            match selection {
+                0 => LinkStrategy::LinkFolder,
+                1 => LinkStrategy::LinkFilesRecursive,
+                _ => LinkStrategy::Copy,
+            }
         }
-        if skipped > 0 {
-            println!(
-                "  {} {} unchanged",
-                "[·]".dimmed(),
-                pluralize("link", skipped as isize, true)
-            );
+    };
+
+    // Choose where the files go inside the repo
+    let source = match source {
+        Some(source) => source,
+        None => {
+            let default_source = target_path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Cannot adopt {}", target_path))?
+                .to_string();
+            let source: String = Input::new()
+                .with_prompt("Source path in the repo")
+                .default(default_source)
+                .interact_text()?;
+            Utf8PathBuf::from(source)
         }
-    } else if skipped > 0 {
-        println!(
-            "\n{} All {} already up to date",
-            "✓".green().bold(),
-            pluralize("link", skipped as isize, true)
+    };
+
+    if source.is_absolute() {
+        anyhow::bail!("Source path must be relative to the repo root: {}", source);
+    }
+    let source_path = config_dir_or_cwd.join(&source);
+    if get_fs_type(&source_path)?.is_some() {
+        anyhow::bail!("Source path already exists in the repo: {}", source_path);
+    }
+    let real_source = canonical(config_dir_or_cwd).join(&source);
+    if real_source.starts_with(&real_target) || real_target.starts_with(&real_source) {
+        anyhow::bail!(
+            "Source path {} and adopted path {} overlap",
+            source_path,
+            target_path
         );
     }
 
-    Ok(())
+    // Targets inside the repo stay relative, everything else is written as ~/... when possible
+    let target = target_path
+        .strip_prefix(config_dir_or_cwd)
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|_| contract_home_path(&target_path));
+    let package = Package {
+        source,
+        target,
+        strategy,
+        on_conflict: None,
+        when: None,
+        filter: Default::default(),
+        detect_ignore: Vec::new(),
+        detect: None,
+    };
+
+    // Move files into the repo
+    move_path(&target_path, &source_path)
+        .with_context(|| format!("Failed to move {} into the repo", target_path))?;
+    say!(
+        "{} Moved {} → {}",
+        "✓".green().bold(),
+        target_path,
+        source_path
+    );
+
+    // Update doty.kdl, putting the files back if that fails
+    let original_config = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read config file: {}", config_path))?;
+    if let Err(e) = DotyConfig::append_package(&config_path, &package) {
+        move_path(&source_path, &target_path).with_context(|| {
+            format!(
+                "Failed to update configuration and failed to move {} back",
+                source_path
+            )
+        })?;
+        return Err(e.context("Failed to update configuration"));
+    }
+    say!(
+        "{} Added to {}: {}",
+        "✓".green().bold(),
+        config_path,
+        package.to_kdl_string()
+    );
+
+    // Run the regular link logic to create the symlink(s), a failed link rolls back its own
+    // changes, then doty.kdl and the files are put back as they were
+    say!("\n{}", "Linking 🔗".bold());
+    link(config_path.clone(), false, false, false, reporter).or_else(|e| {
+        std::fs::write(&config_path, &original_config)
+            .with_context(|| format!("Link failed and failed to restore {}", config_path))?;
+        move_path(&source_path, &target_path).with_context(|| {
+            format!(
+                "Link failed and failed to move {} back to {}",
+                source_path, target_path
+            )
+        })?;
+        say!(
+            "{} Adopt undone: {} is back in place, {} unchanged",
+            "[!]".red().bold(),
+            target_path,
+            config_path
+        );
+        Err(e)
+    })
 }
 
-/// Execute clean command
-pub fn clean(config_path: Utf8PathBuf, dry_run: bool) -> Result<()> {
-    // Get hostname
-    let hostname = hostname::get()?.to_string_lossy().to_string();
-
-    // Load config to determine the path resolution strategy
-    let config = DotyConfig::from_file(&config_path).context("Failed to load configuration")?;
-
-    // Determine repo root based on path resolution strategy
-    let config_dir_or_cwd = match config.path_resolution {
-        PathResolution::Config => {
-            // Resolve relative to config file location
-            let config_dir = config_path
-                .parent()
-                .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
-
-            // Canonicalize to get absolute path
-            let abs_path = if config_dir.as_str().is_empty() || config_dir == "." {
-                Utf8PathBuf::from_path_buf(env::current_dir()?)
-                    .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
-            } else {
-                config_dir.canonicalize_utf8()?
-            };
+/// What interactive detect does with an untracked file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UntrackedChoice {
+    Adopt,
+    Ignore,
+    Delete,
+    Skip,
+}
 
-            abs_path
-        }
-        PathResolution::Cwd => {
-            // Resolve relative to current working directory
-            Utf8PathBuf::from_path_buf(env::current_dir()?)
-                .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
-        }
+/// Ask what to do with the untracked files of one package and do it
+/// Returns how many files were moved into the repo (they still need to be linked)
+fn review_untracked(
+    package: &Package,
+    untracked_files: &[Utf8PathBuf],
+    config_dir_or_cwd: &Utf8Path,
+    backups: &mut BackupStore,
+) -> Result<usize> {
+    let target_root = resolve_target_path(&package.target, config_dir_or_cwd)?;
+    let relative = |file: &Utf8PathBuf| {
+        file.strip_prefix(&target_root)
+            .unwrap_or(file)
+            .to_path_buf()
     };
 
-    println!("{:<10} {}", "Config:", config_path);
-    println!("{:<10} {}\n", "BasePath:", config_dir_or_cwd);
-
-    // Load lockfile
-    let lockfile_dir = config_dir_or_cwd.join(".doty/state");
-    let lockfile = Lockfile::load(&lockfile_dir, &hostname, config_dir_or_cwd.clone())
-        .context("Failed to load lockfile")?;
+    let batch = Select::new()
+        .with_prompt(format!(
+            "What should happen to these {}?",
+            pluralize("untracked file", untracked_files.len() as isize, true)
+        ))
+        .items(&[
+            "Decide for each file",
+            "Adopt all",
+            "Ignore all permanently (add to .dotyignore)",
+            "Skip all",
+        ])
+        .default(0)
+        .interact()?;
+
+    let mut decisions = Vec::new();
+    let mut remaining = match batch {
+        1 => Some(UntrackedChoice::Adopt),
+        2 => Some(UntrackedChoice::Ignore),
+        3 => Some(UntrackedChoice::Skip),
+        _ => None,
+    };
+    for file in untracked_files {
+        if let Some(choice) = remaining {
+            decisions.push((file, choice));
+            continue;
+        }
+        let choice = loop {
+            let selection = Select::new()
+                .with_prompt(relative(file).to_string())
+                .items(&[
+                    "Adopt (move into the repo and link)",
+                    "Ignore permanently (add to .dotyignore)",
+                    "Delete (moved to the backup store)",
+                    "Show contents",
+                    "Skip",
+                    "Adopt all remaining in this package",
+                    "Skip all remaining in this package",
+                ])
+                .default(0)
+                .interact()?;
+            match selection {
+                0 => break UntrackedChoice::Adopt,
+                1 => break UntrackedChoice::Ignore,
+                2 => break UntrackedChoice::Delete,
+                3 => show_contents(file),
+                4 => break UntrackedChoice::Skip,
+                5 => {
+                    remaining = Some(UntrackedChoice::Adopt);
+                    break UntrackedChoice::Adopt;
+                }
+                _ => {
+                    remaining = Some(UntrackedChoice::Skip);
+                    break UntrackedChoice::Skip;
+                }
+            }
+        };
+        decisions.push((file, choice));
+    }
 
-    if lockfile.links.is_empty() {
-        println!("No managed links found for host: {}", hostname);
-        return Ok(());
+    let mut adopted = 0;
+    let mut ignored = Vec::new();
+    for (file, choice) in decisions {
+        match choice {
+            UntrackedChoice::Adopt => {
+                match adopt_untracked_file(package, file, &target_root, config_dir_or_cwd) {
+                    Ok(source) => {
+                        say!("  {} Moved {} → {}", "✓".green().bold(), file, source);
+                        adopted += 1;
+                    }
+                    Err(e) => say!("  {} {:#}", "✗".red().bold(), e),
+                }
+            }
+            UntrackedChoice::Ignore => ignored.push(relative(file)),
+            UntrackedChoice::Delete => match backups.back_up(file) {
+                Ok(entry) => say!(
+                    "  {} Deleted {} (restore with 'doty restore {}')",
+                    "✓".green().bold(),
+                    file,
+                    entry.id
+                ),
+                Err(e) => say!("  {} {:#}", "✗".red().bold(), e),
+            },
+            UntrackedChoice::Skip => say!("  {} Skipped {}", "−".yellow().bold(), file),
+        }
     }
 
-    // Create linker
-    let linker = Linker::new(config_dir_or_cwd.clone(), config.path_resolution);
+    if !ignored.is_empty() {
+        let ignored: Vec<&Utf8Path> = ignored.iter().map(|p| p.as_path()).collect();
+        ignore_untracked(package, &ignored, config_dir_or_cwd)?;
+    }
 
-    // Clean all links
-    println!(
-        "Removing {} managed {}...\n",
-        lockfile.links.len(),
-        pluralize("link", lockfile.links.len() as isize, false)
-    );
-    let actions = linker
-        .clean(&lockfile, dry_run)
-        .context("Failed to clean links")?;
+    Ok(adopted)
+}
 
-    for action in &actions {
-        if let LinkAction::Removed { target, source } = action {
-            println!("  {} {} → {}", "[-]".red().bold(), target, source);
-        }
+/// Move an untracked target file to the matching place in the package source
+fn adopt_untracked_file(
+    package: &Package,
+    file: &Utf8Path,
+    target_root: &Utf8Path,
+    config_dir_or_cwd: &Utf8Path,
+) -> Result<Utf8PathBuf> {
+    let relative = file
+        .strip_prefix(target_root)
+        .with_context(|| format!("{} is not inside {}", file, target_root))?;
+    let source = config_dir_or_cwd.join(&package.source).join(relative);
+    if get_fs_type(&source)?.is_some() {
+        anyhow::bail!("Source path already exists in the repo: {}", source);
     }
+    move_path(file, &source).with_context(|| format!("Failed to move {} into the repo", file))?;
+    Ok(source)
+}
 
-    // Clear lockfile
-    if !dry_run {
-        let empty_lockfile = Lockfile::new(hostname.clone(), config_dir_or_cwd);
-        empty_lockfile
-            .save(&lockfile_dir)
-            .context("Failed to save lockfile")?;
-        println!(
-            "\n{} Lockfile cleared for host: {}",
-            "✓".green().bold(),
-            hostname
-        );
+/// Print the diff between the repo source (rendered for templates) and a modified target
+fn print_modified_diff(item: &DriftItem, templates: &TemplateContext) {
+    let Some(source) = &item.source_path else {
+        return;
+    };
+    if !item.target_path.is_file() || !source.is_file() {
+        return;
+    }
+    let is_template = item
+        .package
+        .as_ref()
+        .is_some_and(|p| p.strategy == LinkStrategy::Template);
+    let expected = if is_template {
+        templates.render(source).map(String::into_bytes)
     } else {
-        println!("\n{}", "[DRY RUN] No changes were made".yellow().bold());
+        std::fs::read(source).map_err(anyhow::Error::from)
+    };
+    let (Ok(expected), Ok(actual)) = (expected, std::fs::read(&item.target_path)) else {
+        return;
+    };
+
+    match unified_diff(
+        source.as_str(),
+        &expected,
+        item.target_path.as_str(),
+        &actual,
+    ) {
+        Some(diff) => {
+            for line in diff.lines() {
+                let line = if line.starts_with("+++") || line.starts_with("---") {
+                    line.bold()
+                } else if line.starts_with('+') {
+                    line.green()
+                } else if line.starts_with('-') {
+                    line.red()
+                } else if line.starts_with("@@") {
+                    line.cyan()
+                } else {
+                    line.normal()
+                };
+                say!("      {}", line);
+            }
+        }
+        None => say!("      {}", "(binary files differ)".dimmed()),
     }
+}
 
-    println!(
-        "\n{} {} {} removed",
-        "Summary:".bold(),
-        "[-]".red().bold(),
-        pluralize("link", actions.len() as isize, true)
-    );
+/// Ask what to do with a modified target: pull its content into the repo, discard it or keep it
+/// Returns true when the target has to be linked (or copied) again
+fn review_modified(item: &DriftItem, backups: &mut BackupStore) -> Result<bool> {
+    let target = &item.target_path;
+    let Some(source) = &item.source_path else {
+        return Ok(false);
+    };
+    let is_template = item
+        .package
+        .as_ref()
+        .is_some_and(|p| p.strategy == LinkStrategy::Template);
+    // Rendered output can't go back into a template, and directories are not merged
+    let can_pull = !is_template && target.is_file();
+
+    let mut items = Vec::new();
+    if can_pull {
+        items.push("Pull: copy the target's content into the repo, then relink");
+    }
+    items.push("Discard: move the target to the backup store and relink");
+    items.push("Keep: leave it as it is for now");
+    let selection = Select::new()
+        .with_prompt(format!("{} was modified", target))
+        .items(&items)
+        .default(0)
+        .interact()?;
+    let choice = if can_pull { selection } else { selection + 1 };
+
+    match choice {
+        0 => {
+            std::fs::copy(target, source)
+                .with_context(|| format!("Failed to copy {} to {}", target, source))?;
+            // Copies stay in place and get their new hash recorded, links are recreated
+            if item.symlink_target.is_some() {
+                remove_symlink(target)?;
+            } else if !matches!(
+                item.package.as_ref().map(|p| p.strategy),
+                Some(LinkStrategy::Copy)
+            ) {
+                std::fs::remove_file(target)
+                    .with_context(|| format!("Failed to remove {}", target))?;
+            }
+            say!("  {} Pulled {} → {}", "✓".green().bold(), target, source);
+            Ok(true)
+        }
+        1 => {
+            let entry = backups.back_up(target)?;
+            say!(
+                "  {} Discarded {} (restore with 'doty restore {}')",
+                "✓".green().bold(),
+                target,
+                entry.id
+            );
+            Ok(true)
+        }
+        _ => {
+            say!("  {} Kept {}", "−".yellow().bold(), target);
+            Ok(false)
+        }
+    }
+}
 
-    Ok(())
+/// Describe what is at the target of an orphaned link right now
+fn orphan_state(item: &DriftItem) -> Result<String> {
+    Ok(match get_fs_type(&item.target_path)? {
+        None => "missing".to_string(),
+        Some(FsType::Symlink) => match &item.symlink_target {
+            Some(points_to) if Some(points_to) == item.source_path.as_ref() => {
+                "still linked".to_string()
+            }
+            Some(points_to) => format!("points to {}", points_to),
+            None => "symlink".to_string(),
+        },
+        Some(FsType::File) => "regular file".to_string(),
+        Some(FsType::Directory) => "directory".to_string(),
+    })
 }
 
-/// Execute detect command
-pub fn detect(config_path: Utf8PathBuf, interactive: bool) -> Result<()> {
-    // Get hostname
-    let hostname = hostname::get()?.to_string_lossy().to_string();
-
-    // Load config to determine path resolution strategy
-    let config = DotyConfig::from_file(&config_path).context("Failed to load configuration")?;
-
-    // Determine repo root based on path resolution strategy
-    let config_dir_or_cwd = match config.path_resolution {
-        PathResolution::Config => {
-            // Resolve relative to config file location
-            let config_dir = config_path
-                .parent()
-                .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
-
-            // Canonicalize to get absolute path
-            let abs_path = if config_dir.as_str().is_empty() || config_dir == "." {
-                Utf8PathBuf::from_path_buf(env::current_dir()?)
-                    .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
-            } else {
-                config_dir.canonicalize_utf8()?
-            };
+/// Ask whether to remove orphaned links or to add a package for them back to doty.kdl
+/// Returns true when the lockfile was changed
+fn review_orphans(
+    orphaned: &[DriftItem],
+    config_path: &Utf8Path,
+    config_dir_or_cwd: &Utf8Path,
+    lockfile: &mut Lockfile,
+) -> Result<bool> {
+    let batch = if orphaned.len() > 1 {
+        Select::new()
+            .with_prompt(format!(
+                "What should happen to these {}?",
+                pluralize("orphaned link", orphaned.len() as isize, true)
+            ))
+            .items(&["Decide for each link", "Remove all", "Keep all"])
+            .default(0)
+            .interact()?
+    } else {
+        0
+    };
 
-            abs_path
-        }
-        PathResolution::Cwd => {
-            // Resolve relative to current working directory
-            Utf8PathBuf::from_path_buf(env::current_dir()?)
-                .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?
+    let mut lockfile_modified = false;
+    for item in orphaned {
+        let target = &item.target_path;
+        let selection = match batch {
+            1 => 0,
+            2 => 2,
+            _ => Select::new()
+                .with_prompt(format!("{} ({})", target, orphan_state(item)?))
+                .items(&[
+                    "Remove: delete the link and forget it",
+                    "Re-add: add a package for it to doty.kdl",
+                    "Keep for now",
+                ])
+                .default(0)
+                .interact()?,
+        };
+
+        match selection {
+            0 => {
+                // Only remove what doty put there, anything else stays where it is
+                let hash = lockfile.get_hash(target).cloned();
+                match get_fs_type(target)? {
+                    Some(FsType::Symlink) => remove_symlink(target)?,
+                    Some(FsType::File) if hash.is_some() && hash == hash_file(target).ok() => {
+                        std::fs::remove_file(target)
+                            .with_context(|| format!("Failed to remove {}", target))?
+                    }
+                    Some(_) => say!(
+                        "  {} {} was changed, leaving it in place",
+                        "!".yellow().bold(),
+                        target
+                    ),
+                    None => {}
+                }
+                lockfile.remove_link(target);
+                lockfile_modified = true;
+                say!("  {} Removed {}", "✓".green().bold(), target);
+            }
+            1 => {
+                let package = orphan_package(item, config_dir_or_cwd, lockfile)?;
+                DotyConfig::append_package(config_path, &package)
+                    .context("Failed to update configuration")?;
+                say!(
+                    "  {} Added to {}: {}",
+                    "✓".green().bold(),
+                    config_path,
+                    package.to_kdl_string()
+                );
+            }
+            _ => say!("  {} Kept {}", "−".yellow().bold(), target),
         }
-    };
+    }
 
-    println!("{:<10} {}", "Config:", config_path);
-    println!("{:<10} {}\n", "BasePath:", config_dir_or_cwd);
+    Ok(lockfile_modified)
+}
 
-    // Load lockfile
-    let lockfile_dir = config_dir_or_cwd.join(".doty/state");
-    let mut lockfile = Lockfile::load(&lockfile_dir, &hostname, config_dir_or_cwd.clone())
-        .context("Failed to load lockfile")?;
-    let mut lockfile_modified = false;
+/// A package that produces an orphaned or stray link again, like `doty adopt` would write it
+fn orphan_package(
+    item: &DriftItem,
+    config_dir_or_cwd: &Utf8Path,
+    lockfile: &Lockfile,
+) -> Result<Package> {
+    let target = &item.target_path;
+    let source_path = item
+        .source_path
+        .as_ref()
+        .with_context(|| format!("No source recorded for {}", target))?;
+    let source = source_path
+        .strip_prefix(config_dir_or_cwd)
+        .with_context(|| format!("Source {} is outside the repo", source_path))?
+        .to_path_buf();
+    let strategy = if lockfile.get_hash(target).is_some() {
+        LinkStrategy::Copy
+    } else if source_path.is_dir() {
+        LinkStrategy::LinkFolder
+    } else {
+        LinkStrategy::LinkFilesRecursive
+    };
 
-    // Create scanner
-    let scanner = Scanner::new(config_dir_or_cwd.clone());
+    Ok(Package {
+        source,
+        target: target
+            .strip_prefix(config_dir_or_cwd)
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|_| contract_home_path(target)),
+        strategy,
+        on_conflict: None,
+        when: None,
+        filter: Default::default(),
+        detect_ignore: Vec::new(),
+        detect: None,
+    })
+}
 
-    // Run drift detection
-    let drift_items = scanner
-        .scan_targets(&config, &lockfile)
-        .context("Failed to scan for drift")?;
-
-    // Group drift items by type and package
-    let mut untracked_by_package: std::collections::HashMap<String, Vec<Utf8PathBuf>> =
-        std::collections::HashMap::new();
-    let mut broken_links = Vec::new();
-
-    for item in &drift_items {
-        match item.drift_type {
-            DriftType::Untracked => {
-                if let Some(package) = &item.package {
-                    let package_key = format!(
-                        "{} {} → {}",
-                        match package.strategy {
-                            LinkStrategy::LinkFilesRecursive => "LinkFilesRecursive",
-                            LinkStrategy::LinkFolder => "LinkFolder",
-                        },
-                        package.source,
-                        package.target
-                    );
-                    untracked_by_package
-                        .entry(package_key)
-                        .or_insert_with(Vec::new)
-                        .push(item.target_path.clone());
+/// Print a file for "Show contents", binary and long files are cut short
+fn show_contents(file: &Utf8Path) {
+    const MAX_LINES: usize = 40;
+    match std::fs::read(file) {
+        Ok(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => {
+                for line in text.lines().take(MAX_LINES) {
+                    say!("  {} {}", "│".dimmed(), line);
+                }
+                let total = text.lines().count();
+                if total > MAX_LINES {
+                    say!("  {} ({} more lines)", "│".dimmed(), total - MAX_LINES);
                 }
             }
-            DriftType::Broken => {
-                broken_links.push(item.clone());
-            }
-            DriftType::Modified | DriftType::Orphaned => {
-                // These are handled elsewhere or not implemented yet
-            }
-        }
+            Err(e) => say!(
+                "  {} (binary file, {} bytes)",
+                "│".dimmed(),
+                e.as_bytes().len()
+            ),
+        },
+        Err(e) => say!("  {} Failed to read {}: {}", "✗".red().bold(), file, e),
     }
+}
 
-    // Print results
-    if untracked_by_package.is_empty() && broken_links.is_empty() {
-        println!("\n{} No drift detected", "✓".green().bold());
-        return Ok(());
+/// Append untracked files (relative to the package target) to the package's or the repo's
+/// `.dotyignore`
+fn ignore_untracked(
+    package: &Package,
+    relative_paths: &[&Utf8Path],
+    config_dir_or_cwd: &Utf8Path,
+) -> Result<()> {
+    let package_file = config_dir_or_cwd
+        .join(&package.source)
+        .join(DOTYIGNORE_FILE);
+    let repo_file = config_dir_or_cwd.join(DOTYIGNORE_FILE);
+    let selection = Select::new()
+        .with_prompt("Add to which .dotyignore?")
+        .items(&[
+            format!("{} (this package)", package_file),
+            format!("{} (all packages)", repo_file),
+        ])
+        .default(0)
+        .interact()?;
+    let file = if selection == 0 {
+        package_file
+    } else {
+        repo_file
+    };
+
+    append_to_dotyignore(&file, relative_paths)?;
+    say!(
+        "  {} Added {} to {}",
+        "✓".green().bold(),
+        pluralize("file", relative_paths.len() as isize, true),
+        file
+    );
+    Ok(())
+}
+
+/// Execute detect command
+pub fn detect(
+    config_path: Utf8PathBuf,
+    interactive: bool,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    let Repo {
+        config,
+        config_dir_or_cwd,
+        ..
+    } = &repo;
+    say!();
+
+    let mut lockfile = repo.load_lockfile()?;
+    let mut lockfile_modified = false;
+    let mut adopted_count = 0;
+
+    // Run drift detection
+    let drift = repo.detect()?;
+    if drift.is_empty() {
+        say!("\n{} No drift detected", "✓".green().bold());
+        return Ok(Outcome::Success);
     }
+    let package_key = |package: &Package| {
+        format!(
+            "{} {} → {}",
+            package.strategy, package.source, package.target
+        )
+    };
 
     // Print untracked files (only for LinkFilesRecursive packages)
-    for (package_key, untracked_files) in &untracked_by_package {
-        println!("\n{} {}:", "Untracked files in".bold(), package_key);
+    for (package, untracked_files) in &drift.untracked {
+        say!(
+            "\n{} {}:",
+            "Untracked files in".bold(),
+            package_key(package)
+        );
         for file in untracked_files {
-            println!("  {} {}", "[?]".yellow().bold(), file);
+            say!("  {} {}", "[?]".yellow().bold(), file);
         }
     }
 
     // Print broken symlinks
-    if !broken_links.is_empty() {
-        println!("\n{}", "Broken symlinks:".bold());
-        for item in &broken_links {
+    if !drift.broken.is_empty() {
+        say!("\n{}", "Broken symlinks:".bold());
+        for item in &drift.broken {
             let link = item
                 .target_path
-                .strip_prefix(&config_dir_or_cwd)
+                .strip_prefix(config_dir_or_cwd)
                 .unwrap_or(&item.target_path);
             let target_display = if let Some(target) = &item.symlink_target {
-                let relative_target = target.strip_prefix(&config_dir_or_cwd).unwrap_or(target);
+                let relative_target = target.strip_prefix(config_dir_or_cwd).unwrap_or(target);
                 format!("{} {} {}", "📄".dimmed(), "✗".red().bold(), relative_target)
             } else {
                 format!("{} {} {}", "📄".dimmed(), "✗".red().bold(), "???")
             };
 
-            println!(
+            say!(
                 "  {} {} {} → {}",
                 "[!]".yellow().bold(),
                 "🔗".cyan(),
@@ -531,58 +848,83 @@ pub fn detect(config_path: Utf8PathBuf, interactive: bool) -> Result<()> {
         }
     }
 
+    // Print copies edited since doty wrote them and links replaced by something else
+    let templates = TemplateContext::new(&config.variables)?;
+    if !drift.modified_copies.is_empty() {
+        say!("\n{}", "Modified copies:".bold());
+        for item in &drift.modified_copies {
+            say!("  {} {}", "[M]".yellow().bold(), item.target_path);
+            print_modified_diff(item, &templates);
+        }
+    }
+    if !drift.replaced_links.is_empty() {
+        say!("\n{}", "Replaced links:".bold());
+        for item in &drift.replaced_links {
+            let how = match (&item.symlink_target, get_fs_type(&item.target_path)?) {
+                (Some(points_to), _) => format!("now points to {}", points_to),
+                (None, Some(FsType::Directory)) => "replaced by a directory".to_string(),
+                _ => "replaced by a regular file".to_string(),
+            };
+            say!(
+                "  {} {} {}",
+                "[M]".yellow().bold(),
+                item.target_path,
+                format!("({})", how).dimmed()
+            );
+            print_modified_diff(item, &templates);
+        }
+    }
+    if !interactive && (!drift.modified_copies.is_empty() || !drift.replaced_links.is_empty()) {
+        say!("  Pull the changes into the repo or discard them in interactive mode");
+    }
+
+    // Print links whose package is no longer in the config
+    if !drift.orphaned.is_empty() {
+        say!("\n{}", "Orphaned links:".bold());
+        for item in &drift.orphaned {
+            say!(
+                "  {} {} {}",
+                health_icon(LinkHealth::Orphaned),
+                item.target_path,
+                format!("({})", orphan_state(item)?).dimmed()
+            );
+        }
+        say!("  'doty link' removes them, add their package back to doty.kdl to keep them");
+    }
+
     // Interactive mode handling
     if interactive {
-        println!("\n{}", "Interactive mode:".bold());
+        say!("\n{}", "Interactive mode:".bold());
 
         // Handle untracked files
-        for (package_key, untracked_files) in &untracked_by_package {
-            if !untracked_files.is_empty() {
-                println!("\n{} {}:", "Adopt untracked files for".bold(), package_key);
-
-                let should_adopt = Confirm::new()
-                    .with_prompt(format!(
-                        "Adopt these {} untracked files?",
-                        untracked_files.len()
-                    ))
-                    .default(true)
-                    .interact()?;
-
-                if should_adopt {
-                    // TODO: Implement actual file adoption in step 3.3
-                    println!(
-                        "  {} {} would be adopted",
-                        "✓".green().bold(),
-                        pluralize("file", untracked_files.len() as isize, true)
-                    );
-                    println!("  (File adoption will be implemented in step 3.3)");
-                } else {
-                    println!(
-                        "  {} Skipped {} untracked files",
-                        "−".yellow().bold(),
-                        pluralize("file", untracked_files.len() as isize, true)
-                    );
-                }
-            }
+        let mut backups = repo.backup_store()?;
+        for (package, untracked_files) in &drift.untracked {
+            say!(
+                "\n{} {}:",
+                "Untracked files in".bold(),
+                package_key(package)
+            );
+            adopted_count +=
+                review_untracked(package, untracked_files, config_dir_or_cwd, &mut backups)?;
         }
 
         // Handle broken links
-        if !broken_links.is_empty() {
-            println!("\n{}", "Remove broken symlinks?".bold());
+        if !drift.broken.is_empty() {
+            say!("\n{}", "Remove broken symlinks?".bold());
 
             let should_remove = Confirm::new()
-                .with_prompt(format!("Remove {} broken symlinks?", broken_links.len()))
+                .with_prompt(format!("Remove {} broken symlinks?", drift.broken.len()))
                 .default(true)
                 .interact()?;
 
             if should_remove {
                 // Remove broken symlinks
                 let mut removed_count = 0;
-                for item in &broken_links {
+                for item in &drift.broken {
                     let broken_link = &item.target_path;
                     // Try to remove the broken symlink
                     if let Err(e) = std::fs::remove_file(broken_link) {
-                        println!(
+                        say!(
                             "  {} Failed to remove {}: {}",
                             "✗".red().bold(),
                             broken_link,
@@ -595,9 +937,7 @@ pub fn detect(config_path: Utf8PathBuf, interactive: bool) -> Result<()> {
                             .links
                             .keys()
                             .find(|k| {
-                                if let Ok(resolved) =
-                                    crate::fs_utils::resolve_target_path(k, &config_dir_or_cwd)
-                                {
+                                if let Ok(resolved) = resolve_target_path(k, config_dir_or_cwd) {
                                     &resolved == broken_link
                                 } else {
                                     false
@@ -615,32 +955,48 @@ pub fn detect(config_path: Utf8PathBuf, interactive: bool) -> Result<()> {
                             }
                         }
 
-                        println!("  {} Removed {}", "✓".green().bold(), broken_link);
+                        say!("  {} Removed {}", "✓".green().bold(), broken_link);
                         removed_count += 1;
                     }
                 }
 
                 if removed_count > 0 {
-                    println!(
+                    say!(
                         "\n{} {} removed",
                         "✓".green().bold(),
                         pluralize("broken symlink", removed_count, true)
                     );
                 }
             } else {
-                println!(
+                say!(
                     "  {} Skipped {} broken symlinks",
                     "−".yellow().bold(),
-                    pluralize("broken symlink", broken_links.len() as isize, true)
+                    pluralize("broken symlink", drift.broken.len() as isize, true)
                 );
             }
         }
 
-        if !untracked_by_package.is_empty() || !broken_links.is_empty() {
-            println!("\n{} Interactive cleanup completed", "✓".green().bold());
+        // Handle modified copies and replaced links
+        for item in drift.modified_copies.iter().chain(&drift.replaced_links) {
+            if review_modified(item, &mut backups)? {
+                adopted_count += 1;
+            }
+        }
+
+        // Handle orphaned links
+        if !drift.orphaned.is_empty() {
+            say!("\n{}", "Orphaned links:".bold());
+            lockfile_modified |= review_orphans(
+                &drift.orphaned,
+                &config_path,
+                config_dir_or_cwd,
+                &mut lockfile,
+            )?;
         }
+
+        say!("\n{} Interactive cleanup completed", "✓".green().bold());
     } else {
-        println!(
+        say!(
             "\n{} {} to adopt or cleanup",
             "Run 'doty detect --interactive'".yellow().bold(),
             "interactive mode".yellow()
@@ -649,12 +1005,740 @@ pub fn detect(config_path: Utf8PathBuf, interactive: bool) -> Result<()> {
 
     // Save lockfile if modified
     if lockfile_modified {
-        if let Err(e) = lockfile.save(&lockfile_dir) {
-            println!("  {} Failed to save lockfile: {}", "✗".red().bold(), e);
+        if let Err(e) = lockfile.save(repo.lockfile_dir()) {
+            say!("  {} Failed to save lockfile: {}", "✗".red().bold(), e);
+        } else {
+            say!("  {} Lockfile updated", "✓".green().bold());
+        }
+    }
+
+    // Adopted and pulled files are in the repo now, discarded targets are gone: the regular
+    // link logic puts symlinks (or copies) in their place
+    if adopted_count > 0 {
+        say!("\n{}", "Linking 🔗".bold());
+        link(config_path.clone(), false, false, false, reporter)?;
+    }
+
+    // Only drift that interactive mode left behind counts, rescan quietly to find it
+    if interactive && Repo::load(&config_path)?.detect()?.is_empty() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::Drift)
+    }
+}
+
+/// Ask whether to take ownership of stray links or to remove them
+/// Returns true when the lockfile was changed
+fn review_stray(
+    stray: &[DriftItem],
+    config_path: &Utf8Path,
+    config_dir_or_cwd: &Utf8Path,
+    lockfile: &mut Lockfile,
+) -> Result<bool> {
+    let batch = if stray.len() > 1 {
+        Select::new()
+            .with_prompt(format!(
+                "What should happen to these {}?",
+                pluralize("stray link", stray.len() as isize, true)
+            ))
+            .items(&[
+                "Decide for each link",
+                "Take ownership of all",
+                "Remove all",
+                "Skip all",
+            ])
+            .default(0)
+            .interact()?
+    } else {
+        0
+    };
+
+    let mut lockfile_modified = false;
+    for item in stray {
+        let target = &item.target_path;
+        let source = item
+            .source_path
+            .as_ref()
+            .with_context(|| format!("No source for {}", target))?;
+        let selection = match batch {
+            1 => 0,
+            2 => 1,
+            3 => 2,
+            _ => Select::new()
+                .with_prompt(format!("{} → {}", target, source))
+                .items(&[
+                    if item.package.is_some() {
+                        "Take ownership: record it in the lockfile"
+                    } else {
+                        "Take ownership: add a package for it to doty.kdl"
+                    },
+                    "Remove the link",
+                    "Skip",
+                ])
+                .default(0)
+                .interact()?,
+        };
+
+        match selection {
+            0 => {
+                if item.package.is_none() {
+                    let package = orphan_package(item, config_dir_or_cwd, lockfile)?;
+                    DotyConfig::append_package(config_path, &package)
+                        .context("Failed to update configuration")?;
+                    say!(
+                        "  {} Added to {}: {}",
+                        "✓".green().bold(),
+                        config_path,
+                        package.to_kdl_string()
+                    );
+                }
+                lockfile.add_link(target.clone(), source.clone());
+                lockfile_modified = true;
+                say!("  {} Took ownership of {}", "✓".green().bold(), target);
+            }
+            1 => {
+                remove_symlink(target)?;
+                say!("  {} Removed {}", "✓".green().bold(), target);
+            }
+            _ => say!("  {} Skipped {}", "−".yellow().bold(), target),
+        }
+    }
+
+    Ok(lockfile_modified)
+}
+
+/// Execute detect command in stray mode: find symlinks into the repo that the lockfile
+/// doesn't know, e.g. after migrating from stow or losing the lockfile
+pub fn detect_stray(
+    config_path: Utf8PathBuf,
+    roots: &[Utf8PathBuf],
+    interactive: bool,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    let config_dir_or_cwd = &repo.config_dir_or_cwd;
+    for root in roots {
+        say!("{:<10} {}", "Root:", root);
+    }
+
+    let stray = repo.scan_stray(roots)?;
+    if stray.is_empty() {
+        say!("\n{} No stray symlinks found", "✓".green().bold());
+        return Ok(Outcome::Success);
+    }
+
+    let (matching, unknown): (Vec<&DriftItem>, Vec<&DriftItem>) =
+        stray.iter().partition(|item| item.package.is_some());
+    if !matching.is_empty() {
+        say!("\n{}", "Stray links matching a package:".bold());
+        for item in &matching {
+            let package = item.package.as_ref().unwrap();
+            say!(
+                "  {} {} {}",
+                "[?]".yellow().bold(),
+                item.target_path,
+                format!("({} {})", package.strategy, package.source).dimmed()
+            );
+        }
+    }
+    if !unknown.is_empty() {
+        say!("\n{}", "Stray links no package produces:".bold());
+        for item in &unknown {
+            let source = item.source_path.as_deref().unwrap_or(Utf8Path::new("???"));
+            say!(
+                "  {} {} → {}",
+                "[?]".yellow().bold(),
+                item.target_path,
+                source.strip_prefix(config_dir_or_cwd).unwrap_or(source)
+            );
+        }
+    }
+
+    if !interactive {
+        say!(
+            "\n{} {}",
+            "Run 'doty detect --stray --interactive'".yellow().bold(),
+            "to take ownership of them or remove them".yellow()
+        );
+        return Ok(Outcome::Drift);
+    }
+
+    say!("\n{}", "Interactive mode:".bold());
+    let mut lockfile = repo.load_lockfile()?;
+    if review_stray(&stray, &config_path, config_dir_or_cwd, &mut lockfile)? {
+        lockfile
+            .save(repo.lockfile_dir())
+            .context("Failed to save lockfile")?;
+        say!("  {} Lockfile updated", "✓".green().bold());
+    }
+    say!("\n{} Interactive cleanup completed", "✓".green().bold());
+
+    // Skipped links are still stray, rescan quietly to find them
+    if Repo::load(&config_path)?.scan_stray(roots)?.is_empty() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::Drift)
+    }
+}
+
+/// Execute detect command in unmapped mode: list repo files and directories no package links,
+/// with a package node to paste into doty.kdl for each
+pub fn detect_unmapped(config_path: Utf8PathBuf, reporter: &Rc<dyn Reporter>) -> Result<Outcome> {
+    let unmapped = Repo::open(&config_path, reporter)?.unmapped()?;
+    if unmapped.is_empty() {
+        say!(
+            "\n{} Every source is mapped by a package",
+            "✓".green().bold()
+        );
+        return Ok(Outcome::Success);
+    }
+
+    say!("\n{}", "Unmapped sources:".bold());
+    for package in &unmapped {
+        let suffix = if package.strategy == LinkStrategy::LinkFolder {
+            "/"
         } else {
-            println!("  {} Lockfile updated", "✓".green().bold());
+            ""
+        };
+        say!("  {} {}{}", "[?]".yellow().bold(), package.source, suffix);
+        say!("      {}", package.to_kdl_string().dimmed());
+    }
+    say!(
+        "\n{} {}",
+        "Add the nodes you want to doty.kdl".yellow().bold(),
+        "(check the guessed targets), or skip paths with a defaults ignore pattern".yellow()
+    );
+
+    Ok(Outcome::Drift)
+}
+
+/// Execute status command
+pub fn status(config_path: Utf8PathBuf, reporter: &Rc<dyn Reporter>) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    say!("{:<10} {}\n", "Host:", repo.hostname);
+
+    let status = repo.status()?;
+
+    // One row per package
+    struct Row<'a> {
+        strategy: String,
+        source: String,
+        target: String,
+        links: String,
+        package: &'a PackageStatus,
+    }
+    let rows: Vec<Row> = status
+        .packages
+        .iter()
+        .map(|package| Row {
+            strategy: package.package.strategy.to_string(),
+            source: package.source.to_string(),
+            target: package.target.to_string(),
+            links: format!(
+                "{}/{}",
+                package.links.iter().filter(|link| link.recorded).count(),
+                package.links.len()
+            ),
+            package,
+        })
+        .collect();
+
+    if rows.is_empty() {
+        say!("No packages configured in {}", config_path);
+    } else {
+        let strategy_width = rows
+            .iter()
+            .map(|r| r.strategy.len())
+            .max()
+            .unwrap_or(0)
+            .max(8);
+        let source_width = rows
+            .iter()
+            .map(|r| r.source.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let target_width = rows
+            .iter()
+            .map(|r| r.target.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let links_width = rows.iter().map(|r| r.links.len()).max().unwrap_or(0).max(5);
+
+        say!(
+            "{}",
+            format!(
+                "{:<strategy_width$}  {:<source_width$}  {:<target_width$}  {:>links_width$}  {}",
+                "Strategy", "Source", "Target", "Links", "Health"
+            )
+            .bold()
+        );
+
+        for row in &rows {
+            let unhealthy: Vec<_> = row
+                .package
+                .links
+                .iter()
+                .filter(|link| link.health != LinkHealth::InSync)
+                .collect();
+            let health = if let Some(when) = &row.package.skipped {
+                format!("skipped (when {})", when).dimmed().to_string()
+            } else if unhealthy.is_empty() {
+                format!("{} {}", "✓".green().bold(), LinkHealth::InSync)
+            } else {
+                row.package
+                    .counts()
+                    .iter()
+                    .filter(|(health, _)| **health != LinkHealth::InSync)
+                    .map(|(health, count)| format!("{} {} {}", health_icon(*health), count, health))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+
+            say!(
+                "{:<strategy_width$}  {:<source_width$}  {:<target_width$}  {:>links_width$}  {}",
+                row.strategy,
+                row.source,
+                row.target,
+                row.links,
+                health
+            );
+
+            for link in &unhealthy {
+                say!(
+                    "    {} {} {}",
+                    health_icon(link.health),
+                    link.target,
+                    format!("({})", link.health).dimmed()
+                );
+            }
+            if row.package.untracked > 0 {
+                say!(
+                    "    {} {}",
+                    "[?]".yellow().bold(),
+                    format!(
+                        "{} (run 'doty detect')",
+                        pluralize("untracked file", row.package.untracked as isize, true)
+                    )
+                    .dimmed()
+                );
+            }
         }
     }
 
+    // Links that are only in the lockfile
+    let orphaned: Vec<_> = status
+        .links
+        .iter()
+        .filter(|link| link.health == LinkHealth::Orphaned)
+        .collect();
+    if !orphaned.is_empty() {
+        say!("\n{}", "Orphaned links:".bold());
+        for link in &orphaned {
+            say!("  {} {}", health_icon(link.health), link.target);
+        }
+    }
+
+    // Summary
+    let unhealthy = status.unhealthy().count();
+    say!(
+        "\n{} {}, {} in lockfile",
+        "Summary:".bold(),
+        pluralize("package", status.packages.len() as isize, true),
+        pluralize("link", status.lockfile_links as isize, true)
+    );
+    if unhealthy == 0 {
+        say!("{} Everything is in sync", "✓".green().bold());
+    } else {
+        say!(
+            "{} {} {} attention, run 'doty link' to apply pending changes",
+            "[!]".yellow().bold(),
+            pluralize("link", unhealthy as isize, true),
+            if unhealthy == 1 { "needs" } else { "need" }
+        );
+    }
+
+    if status.drifted() {
+        Ok(Outcome::Drift)
+    } else if unhealthy > 0 {
+        Ok(Outcome::Pending)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
+
+/// Execute generations command: list the lockfile snapshots of this host
+pub fn generations(config_path: Utf8PathBuf) -> Result<()> {
+    let repo = Repo::load(&config_path)?;
+    let Generations {
+        list: generations,
+        current,
+    } = repo.generations()?;
+
+    if generations.is_empty() {
+        say!("No generations found for host: {}", repo.hostname);
+        return Ok(());
+    }
+
+    let command_width = generations
+        .iter()
+        .map(|g| g.command.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    say!(
+        "{}",
+        format!(
+            "  {:>4}  {:<16}  {:<command_width$}  {:>5}  {}",
+            "Gen", "Created", "Command", "Links", "Summary"
+        )
+        .bold()
+    );
+    for generation in generations.iter().rev() {
+        // The generation matching the current lockfile is the one deployed right now
+        let marker = if current == Some(generation.number) {
+            "*".green().bold()
+        } else {
+            " ".normal()
+        };
+        say!(
+            "{} {:>4}  {:<16}  {:<command_width$}  {:>5}  {}",
+            marker,
+            generation.number,
+            generation.created.format("%Y-%m-%d %H:%M").to_string(),
+            generation.command,
+            generation.lockfile.links.len(),
+            generation.summary
+        );
+    }
+
+    say!(
+        "\n{} {} ({} marks the current one)",
+        "Summary:".bold(),
+        pluralize("generation", generations.len() as isize, true),
+        "*".green().bold()
+    );
+
+    Ok(())
+}
+
+/// Execute rollback command: restore the link set of an earlier generation
+pub fn rollback(
+    config_path: Utf8PathBuf,
+    number: Option<u32>,
+    dry_run: bool,
+    keep_going: bool,
+    reporter: &Rc<dyn Reporter>,
+) -> Result<Outcome> {
+    let repo = Repo::open(&config_path, reporter)?;
+    say!();
+
+    let options = ApplyOptions {
+        dry_run,
+        keep_going,
+        ..Default::default()
+    };
+    let deployment = open_deployment(&repo, options)?;
+    let RollbackPlan {
+        generation,
+        actions,
+        stale_copies,
+    } = deployment.rollback_plan(&repo.config, number)?;
+    say!(
+        "{} generation {} from {} ({})",
+        "Target:".bold(),
+        generation.number,
+        generation.created.format("%Y-%m-%d %H:%M"),
+        generation.command
+    );
+
+    let command = format!("rollback {}", generation.number);
+    let outcome = apply_plan(deployment, actions, options, &command, reporter)?;
+
+    for target in stale_copies {
+        reporter.event(&Event::Warning(format!(
+            "{} has the current source content, not the content of generation {} (earlier copies are in 'doty backups list')",
+            target, generation.number
+        )));
+    }
+
+    say!(
+        "{}",
+        "doty.kdl was not changed, the next 'doty link' applies it again".dimmed()
+    );
+    Ok(outcome)
+}
+
+/// Execute restore command: put backed up originals back where they came from
+pub fn restore(config_path: Utf8PathBuf, path: Option<Utf8PathBuf>, all: bool) -> Result<()> {
+    let repo = Repo::load(&config_path)?;
+
+    // The path comes from the command line, so relative paths are relative to the cwd
+    let path = match (path, all) {
+        (_, true) => None,
+        (Some(path), false) => Some(resolve_target_path(&path, &current_dir()?)?),
+        (None, false) => anyhow::bail!("Specify a path to restore or use --all"),
+    };
+    let Restored { restored, blocked } = repo.restore(path.as_deref())?;
+
+    if restored.is_empty() && blocked.is_empty() {
+        say!("No backups found for host: {}", repo.hostname);
+        return Ok(());
+    }
+
+    for entry in &restored {
+        say!(
+            "  {} {} {}",
+            "[+]".green().bold(),
+            entry.path,
+            format!(
+                "({}, backed up {})",
+                entry.kind,
+                entry.created.format("%Y-%m-%d %H:%M")
+            )
+            .dimmed()
+        );
+    }
+    for (entry, existing) in &blocked {
+        say!("  {} {}", "[!]".yellow().bold(), entry.path);
+        say!(
+            "      Warning: existing {} is in the way, move it first",
+            existing
+        );
+    }
+
+    say!(
+        "\n{} {} restored",
+        "Summary:".bold(),
+        pluralize("backup", restored.len() as isize, true)
+    );
+    if !restored.is_empty() {
+        say!(
+            "{}",
+            "Restored targets now conflict with doty.kdl, remove their packages or set onConflict before the next 'doty link'"
+                .dimmed()
+        );
+    }
+
+    // Every blocked path was reported above, a partial restore must not look like a clean one
+    if !blocked.is_empty() {
+        anyhow::bail!(
+            "{} blocked, move what is in the way and run doty restore again",
+            pluralize("path", blocked.len() as isize, true)
+        );
+    }
     Ok(())
 }
+
+/// Execute backups list command
+pub fn backups_list(config_path: Utf8PathBuf) -> Result<()> {
+    let repo = Repo::load(&config_path)?;
+    let store = repo.backup_store()?;
+
+    if store.entries.is_empty() {
+        say!("No backups found for host: {}", repo.hostname);
+        return Ok(());
+    }
+
+    let mut entries: Vec<&BackupEntry> = store.entries.iter().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path).then(b.created.cmp(&a.created)));
+
+    let id_width = entries.iter().map(|e| e.id.len()).max().unwrap_or(0).max(2);
+    say!(
+        "{}",
+        format!(
+            "{:<id_width$}  {:<16}  {:<9}  {:<4}  {}",
+            "ID", "Created", "Type", "Mode", "Path"
+        )
+        .bold()
+    );
+    for entry in &entries {
+        say!(
+            "{:<id_width$}  {:<16}  {:<9}  {:04o}  {}",
+            entry.id,
+            entry.created.format("%Y-%m-%d %H:%M").to_string(),
+            entry.kind.to_string(),
+            entry.mode,
+            contract_home_path(&entry.path)
+        );
+    }
+
+    say!(
+        "\n{} {} of {}",
+        "Summary:".bold(),
+        pluralize("backup", entries.len() as isize, true),
+        pluralize("path", store.latest_per_path().len() as isize, true)
+    );
+
+    Ok(())
+}
+
+/// Execute config check command: parse doty.kdl and its includes and list what was ignored
+/// Only the config files are read, no link, target or lockfile is looked at
+pub fn config_check(config_path: Utf8PathBuf) -> Result<()> {
+    let config = DotyConfig::from_file(&config_path)?;
+
+    for file in &config.files {
+        say!("{:<10} {}", "File:", contract_home_path(file));
+    }
+    say!();
+    for warning in &config.warnings {
+        say!("{} {}", "[!]".yellow().bold(), warning);
+    }
+
+    let host = HostInfo::current();
+    let active = config
+        .packages
+        .iter()
+        .filter(|package| package.is_active(&host))
+        .count();
+    let packages = format!(
+        "{} ({} active on this host)",
+        pluralize("package", config.packages.len() as isize, true),
+        active
+    );
+    if config.warnings.is_empty() {
+        say!("{} Config is valid: {}", "✓".green().bold(), packages);
+    } else {
+        say!(
+            "\n{} Config is valid with {}: {}",
+            "✓".yellow().bold(),
+            pluralize("warning", config.warnings.len() as isize, true),
+            packages
+        );
+    }
+    Ok(())
+}
+
+/// Execute explain-error command: the help text of an error code, or all codes
+pub fn explain_error(code: Option<&str>) -> Result<()> {
+    let Some(code) = code else {
+        say!("{}", "Error codes:".bold());
+        for (code, title) in error::codes() {
+            say!("  {}  {}", code.yellow().bold(), title);
+        }
+        say!(
+            "\n{}",
+            "Run 'doty explain-error <code>' for the help text of one".dimmed()
+        );
+        return Ok(());
+    };
+
+    let (code, title, explanation) = error::explain(code).with_context(|| {
+        format!(
+            "Unknown error code: {}, 'doty explain-error' lists all codes",
+            code
+        )
+    })?;
+    say!("{} {}\n", code.yellow().bold(), title.bold());
+    say!("{}", explanation);
+    Ok(())
+}
+
+/// Execute backups prune command
+pub fn backups_prune(
+    config_path: Utf8PathBuf,
+    keep: usize,
+    older_than_days: Option<i64>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut store = Repo::load(&config_path)?.backup_store()?;
+
+    let cutoff = older_than_days.map(|days| chrono::Local::now() - chrono::Duration::days(days));
+    let pruned = store.prune(keep, cutoff, dry_run)?;
+
+    for entry in &pruned {
+        say!(
+            "  {} {} {}",
+            "[-]".red().bold(),
+            contract_home_path(&entry.path),
+            format!("({})", entry.id).dimmed()
+        );
+    }
+
+    let verb = if dry_run {
+        "would be removed"
+    } else {
+        "removed"
+    };
+    say!(
+        "\n{} {} {}",
+        "Summary:".bold(),
+        pluralize("backup", pruned.len() as isize, true),
+        verb
+    );
+
+    Ok(())
+}
+
+/// Icon used for a link health state (matches the icons printed by link)
+fn health_icon(health: LinkHealth) -> colored::ColoredString {
+    match health {
+        LinkHealth::InSync => "✓".green().bold(),
+        LinkHealth::PendingCreate => "[+]".green().bold(),
+        LinkHealth::PendingUpdate => "[~]".yellow().bold(),
+        LinkHealth::Conflicting => "[!]".red().bold(),
+        LinkHealth::Broken => "[x]".red().bold(),
+        LinkHealth::Orphaned => "[-]".red().bold(),
+        LinkHealth::Modified => "[M]".yellow().bold(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_adopt_untracked_file() {
+        let test_dir = Utf8PathBuf::from("tests/tmpfs/test_adopt_untracked_file");
+        let _ = fs::remove_dir_all(&test_dir); // Clean up any existing test dir
+        let repo = test_dir.join("repo");
+        let target_root = test_dir.join("target/fish");
+        fs::create_dir_all(repo.join("fish")).unwrap();
+        fs::create_dir_all(target_root.join("functions")).unwrap();
+        fs::write(target_root.join("functions/ll.fish"), "ls -l").unwrap();
+        fs::write(target_root.join("config.fish"), "local").unwrap();
+        fs::write(repo.join("fish/config.fish"), "repo").unwrap();
+
+        let package = Package {
+            source: "fish".into(),
+            target: target_root.clone(),
+            strategy: LinkStrategy::LinkFilesRecursive,
+            on_conflict: None,
+            when: None,
+            filter: Default::default(),
+            detect_ignore: Vec::new(),
+            detect: None,
+        };
+
+        let source = adopt_untracked_file(
+            &package,
+            &target_root.join("functions/ll.fish"),
+            &target_root,
+            &repo,
+        )
+        .unwrap();
+        assert_eq!(source, repo.join("fish/functions/ll.fish"));
+        assert_eq!(fs::read_to_string(&source).unwrap(), "ls -l");
+        assert!(!target_root.join("functions/ll.fish").exists());
+
+        // Never overwrite a file that is already in the repo
+        let err = adopt_untracked_file(
+            &package,
+            &target_root.join("config.fish"),
+            &target_root,
+            &repo,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(
+            fs::read_to_string(repo.join("fish/config.fish")).unwrap(),
+            "repo"
+        );
+
+        // Clean up
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+}
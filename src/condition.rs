@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use kdl::{KdlNode, KdlValue};
+
+/// The machine a config is applied on, conditions are checked against it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    pub hostname: String,
+    /// `std::env::consts::OS` ("linux", "macos", "windows", ...)
+    pub os: String,
+}
+
+impl HostInfo {
+    /// Host info of the machine doty runs on
+    pub fn current() -> Self {
+        Self {
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            os: std::env::consts::OS.to_string(),
+        }
+    }
+}
+
+/// A `when` condition on a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Hostname equals the value
+    Host(String),
+    /// Operating system equals the value
+    Os(String),
+    /// Environment variable is set and not empty (`NAME`), or has a value (`NAME=value`)
+    Env(String),
+    /// Every condition matches
+    All(Vec<Condition>),
+    /// At least one condition matches
+    Any(Vec<Condition>),
+    /// The condition does not match
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    /// Parse a `when` node: all properties and children have to match
+    ///
+    /// ```kdl
+    /// when os="linux" env="WAYLAND_DISPLAY"
+    /// when {
+    ///     any {
+    ///         host "work-laptop"
+    ///         host "work-desktop"
+    ///     }
+    ///     not { env "CI" }
+    /// }
+    /// ```
+    pub fn parse_when(node: &KdlNode) -> Result<Self> {
+        let mut conditions = Vec::new();
+
+        for entry in node.entries() {
+            let key = entry
+                .name()
+                .map(|n| n.value())
+                .context("Conditions in 'when' need a name, e.g. when os=\"linux\"")?;
+            conditions.push(Self::leaf(key, entry.value())?);
+        }
+        conditions.extend(Self::parse_children(node)?);
+
+        if conditions.is_empty() {
+            anyhow::bail!("Empty 'when' condition");
+        }
+        Ok(Self::all(conditions))
+    }
+
+    /// Parse one condition node inside `when`, `all`, `any` or `not`
+    fn parse_node(node: &KdlNode) -> Result<Self> {
+        let name = node.name().value();
+        match name {
+            "all" => Ok(Self::All(Self::parse_children(node)?)),
+            "any" => Ok(Self::Any(Self::parse_children(node)?)),
+            "not" => Ok(Self::Not(Box::new(Self::all(Self::parse_children(node)?)))),
+            _ => {
+                let value = node
+                    .entries()
+                    .first()
+                    .with_context(|| format!("Condition '{}' requires a value", name))?
+                    .value();
+                Self::leaf(name, value)
+            }
+        }
+    }
+
+    fn parse_children(node: &KdlNode) -> Result<Vec<Self>> {
+        let children: Vec<Self> = node
+            .children()
+            .map(|doc| doc.nodes().iter().map(Self::parse_node).collect())
+            .transpose()?
+            .unwrap_or_default();
+
+        let name = node.name().value();
+        if children.is_empty() && name != "when" {
+            anyhow::bail!("Condition '{}' needs at least one condition inside", name);
+        }
+        Ok(children)
+    }
+
+    fn leaf(key: &str, value: &KdlValue) -> Result<Self> {
+        let value = value
+            .as_string()
+            .with_context(|| format!("Condition '{}' requires a string value", key))?
+            .to_string();
+        match key {
+            "host" => Ok(Self::Host(value)),
+            "os" => Ok(Self::Os(value)),
+            "env" => Ok(Self::Env(value)),
+            other => anyhow::bail!(
+                "Unknown condition '{}'. Must be 'host', 'os', 'env', 'all', 'any' or 'not'",
+                other
+            ),
+        }
+    }
+
+    /// A single condition stays as is, several are combined with All
+    fn all(mut conditions: Vec<Self>) -> Self {
+        if conditions.len() == 1 {
+            conditions.remove(0)
+        } else {
+            Self::All(conditions)
+        }
+    }
+
+    /// Check the condition against a machine
+    pub fn matches(&self, host: &HostInfo) -> bool {
+        match self {
+            Self::Host(name) => host.hostname == *name,
+            Self::Os(os) => host.os == *os,
+            Self::Env(spec) => match spec.split_once('=') {
+                Some((name, value)) => std::env::var(name).is_ok_and(|v| v == value),
+                None => std::env::var(spec).is_ok_and(|v| !v.is_empty()),
+            },
+            Self::All(conditions) => conditions.iter().all(|c| c.matches(host)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.matches(host)),
+            Self::Not(condition) => !condition.matches(host),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Host(name) => write!(f, "host={}", name),
+            Self::Os(os) => write!(f, "os={}", os),
+            Self::Env(spec) => write!(f, "env={}", spec),
+            Self::All(conditions) => write!(f, "all({})", join(conditions)),
+            Self::Any(conditions) => write!(f, "any({})", join(conditions)),
+            Self::Not(condition) => write!(f, "not({})", condition),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kdl::KdlDocument;
+
+    fn parse(kdl: &str) -> Result<Condition> {
+        let doc: KdlDocument = kdl.parse().unwrap();
+        Condition::parse_when(&doc.nodes()[0])
+    }
+
+    fn host(hostname: &str, os: &str) -> HostInfo {
+        HostInfo {
+            hostname: hostname.to_string(),
+            os: os.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_inline() {
+        let condition = parse(r#"when host="work-laptop" os="linux""#).unwrap();
+        assert_eq!(
+            condition,
+            Condition::All(vec![
+                Condition::Host("work-laptop".to_string()),
+                Condition::Os("linux".to_string()),
+            ])
+        );
+        assert!(condition.matches(&host("work-laptop", "linux")));
+        assert!(!condition.matches(&host("work-laptop", "macos")));
+        assert_eq!(condition.to_string(), "all(host=work-laptop, os=linux)");
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        let condition = parse(
+            r#"
+            when {
+                any {
+                    host "work-laptop"
+                    host "work-desktop"
+                }
+                not { os "windows" }
+            }
+            "#,
+        )
+        .unwrap();
+        assert!(condition.matches(&host("work-desktop", "linux")));
+        assert!(!condition.matches(&host("work-desktop", "windows")));
+        assert!(!condition.matches(&host("home-pc", "linux")));
+    }
+
+    #[test]
+    fn test_env_condition() {
+        std::env::set_var("DOTY_TEST_CONDITION", "wayland");
+        let any_host = host("any", "linux");
+        assert!(parse(r#"when env="DOTY_TEST_CONDITION""#)
+            .unwrap()
+            .matches(&any_host));
+        assert!(parse(r#"when env="DOTY_TEST_CONDITION=wayland""#)
+            .unwrap()
+            .matches(&any_host));
+        assert!(!parse(r#"when env="DOTY_TEST_CONDITION=x11""#)
+            .unwrap()
+            .matches(&any_host));
+        assert!(!parse(r#"when env="DOTY_TEST_UNSET_VARIABLE""#)
+            .unwrap()
+            .matches(&any_host));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse(r#"when hots="work-laptop""#).unwrap_err();
+        assert!(err.to_string().contains("Unknown condition 'hots'"));
+        assert!(parse("when").is_err());
+        assert!(parse("when { any }").is_err());
+        assert!(parse("when os=1").is_err());
+    }
+}
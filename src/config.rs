@@ -1,24 +1,25 @@
 use anyhow::{Context, Result};
-use camino::Utf8PathBuf;
-use kdl::{KdlDocument, KdlNode};
+use camino::{Utf8Path, Utf8PathBuf};
+use kdl::{KdlDocument, KdlError, KdlNode, KdlValue};
+use std::collections::BTreeMap;
+
+use crate::condition::{Condition, HostInfo};
+use crate::error::DotyError;
+use crate::filter::PathFilter;
+use crate::fs_utils::expand_vars;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Path resolution strategy
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum PathResolution {
     /// Resolve paths relative to config file location (default)
+    #[default]
     Config,
     /// Resolve paths relative to current working directory
     Cwd,
 }
 
-impl Default for PathResolution {
-    fn default() -> Self {
-        PathResolution::Config
-    }
-}
-
 impl std::fmt::Display for PathResolution {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -28,11 +29,64 @@ impl std::fmt::Display for PathResolution {
     }
 }
 
+/// What to do when something that Doty does not manage already exists at a link target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Abort before changing anything (default)
+    #[default]
+    Fail,
+    /// Leave the existing file alone and don't link
+    Skip,
+    /// Move the existing file aside, then link (`overwrite` in the config means the same)
+    Backup,
+    /// Replace the source in the repo with the existing file, then link
+    Adopt,
+}
+
+impl std::fmt::Display for OnConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnConflict::Fail => write!(f, "fail"),
+            OnConflict::Skip => write!(f, "skip"),
+            OnConflict::Backup => write!(f, "backup"),
+            OnConflict::Adopt => write!(f, "adopt"),
+        }
+    }
+}
+
+impl OnConflict {
+    /// Parse an onConflict value from the config
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "fail" => OnConflict::Fail,
+            "skip" => OnConflict::Skip,
+            // Doty never deletes what it doesn't own, so overwriting keeps a backup too
+            "backup" | "overwrite" => OnConflict::Backup,
+            "adopt" => OnConflict::Adopt,
+            other => anyhow::bail!(
+                "Invalid onConflict value: {}. Must be 'fail', 'skip', 'backup', 'overwrite' or 'adopt'",
+                other
+            ),
+        })
+    }
+}
+
 /// Represents the entire Doty configuration
 #[derive(Debug, Clone, PartialEq)]
 pub struct DotyConfig {
     pub packages: Vec<Package>,
     pub path_resolution: PathResolution,
+    /// Default conflict policy for packages that don't set their own
+    pub on_conflict: OnConflict,
+    /// User variables for Template packages (`variables` block)
+    pub variables: BTreeMap<String, String>,
+    /// Default `ignore`/`include` patterns, already merged into every package
+    pub filter: PathFilter,
+    /// Files the config was read from: the main file, then every included file
+    pub files: Vec<Utf8PathBuf>,
+    /// Unknown settings and properties that were ignored, e.g.
+    /// `doty.kdl:3:25: Unknown property 'tagret' on LinkFolder "nvim", did you mean 'target'?`
+    pub warnings: Vec<String>,
 }
 
 /// A package defines a source and how it should be linked
@@ -41,50 +95,329 @@ pub struct Package {
     pub source: Utf8PathBuf,
     pub target: Utf8PathBuf,
     pub strategy: LinkStrategy,
+    /// Conflict policy for this package (falls back to defaults when None)
+    pub on_conflict: Option<OnConflict>,
+    /// Only apply the package on machines matching this condition (`when` child nodes)
+    pub when: Option<Condition>,
+    /// Which files of a directory source are managed (LinkFilesRecursive, Copy and Template)
+    pub filter: PathFilter,
+    /// Gitignore-style patterns for untracked files `doty detect` should not report
+    pub detect_ignore: Vec<String>,
+    /// How far `doty detect` looks for untracked files (see `detect_policy`)
+    pub detect: Option<DetectPolicy>,
 }
 
-/// Linking strategy for a package
+/// How far `doty detect` looks for untracked files in a package target
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectPolicy {
+    /// The whole target tree
+    Full,
+    /// Only directories that already contain managed files, without their subdirectories
+    ManagedDirsOnly,
+    /// Up to N levels below the target (1 = only files directly in it)
+    Depth(usize),
+}
+
+impl std::fmt::Display for DetectPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetectPolicy::Full => write!(f, "full"),
+            DetectPolicy::ManagedDirsOnly => write!(f, "managed-dirs-only"),
+            DetectPolicy::Depth(depth) => write!(f, "depth={}", depth),
+        }
+    }
+}
+
+impl DetectPolicy {
+    /// Parse a detect value from the config
+    fn parse(value: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid detect value: {}. Must be 'full', 'managed-dirs-only' or 'depth=N'",
+                value
+            )
+        };
+        Ok(match value {
+            "full" => DetectPolicy::Full,
+            "managed-dirs-only" => DetectPolicy::ManagedDirsOnly,
+            _ => {
+                let depth = value
+                    .strip_prefix("depth=")
+                    .and_then(|depth| depth.parse::<usize>().ok())
+                    .filter(|depth| *depth > 0)
+                    .ok_or_else(invalid)?;
+                DetectPolicy::Depth(depth)
+            }
+        })
+    }
+}
+
+/// Linking strategy for a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum LinkStrategy {
     /// Create a single symlink for the entire directory (Stow-like)
     LinkFolder,
     /// Recreate directory structure and symlink individual files (Dotter-like)
     LinkFilesRecursive,
+    /// Write real copies of the source files, for apps that replace or refuse symlinks
+    Copy,
+    /// Render the source files as templates and write the output (per-machine configs)
+    Template,
+}
+
+impl std::fmt::Display for LinkStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkStrategy::LinkFolder => write!(f, "LinkFolder"),
+            LinkStrategy::LinkFilesRecursive => write!(f, "LinkFilesRecursive"),
+            LinkStrategy::Copy => write!(f, "Copy"),
+            LinkStrategy::Template => write!(f, "Template"),
+        }
+    }
+}
+
+impl Package {
+    /// Whether the package applies to this machine (packages without conditions always do)
+    pub fn is_active(&self, host: &HostInfo) -> bool {
+        self.when.as_ref().is_none_or(|when| when.matches(host))
+    }
+
+    /// Render this package as a single-line KDL node, e.g. `LinkFolder "nvim" target="~/.config/nvim"`
+    /// Strings are always quoted to match the style used in hand-written configs
+    pub fn to_kdl_string(&self) -> String {
+        let mut node = format!(
+            "{} {} target={}",
+            self.strategy,
+            quote_kdl_string(self.source.as_str()),
+            quote_kdl_string(self.target.as_str())
+        );
+        if let Some(on_conflict) = self.on_conflict {
+            node.push_str(&format!(
+                " onConflict={}",
+                quote_kdl_string(&on_conflict.to_string())
+            ));
+        }
+        if let Some(detect) = self.detect {
+            node.push_str(&format!(
+                " detect={}",
+                quote_kdl_string(&detect.to_string())
+            ));
+        }
+        node
+    }
+
+    /// How `doty detect` looks for untracked files in the resolved target: the package's
+    /// `detect` setting, else `managed-dirs-only` for the home directory and `full` otherwise
+    pub fn detect_policy(&self, resolved_target: &Utf8Path) -> DetectPolicy {
+        self.detect.unwrap_or_else(|| {
+            let home = std::env::var("HOME").ok();
+            if home.is_some_and(|home| Utf8Path::new(&home) == resolved_target) {
+                DetectPolicy::ManagedDirsOnly
+            } else {
+                DetectPolicy::Full
+            }
+        })
+    }
+}
+
+/// Top level nodes of a config file
+const NODE_TYPES: &[&str] = &[
+    "LinkFolder",
+    "LinkFilesRecursive",
+    "Copy",
+    "Template",
+    "defaults",
+    "variables",
+    "include",
+];
+
+/// Child nodes of `defaults`
+const DEFAULTS_SETTINGS: &[&str] = &["pathResolution", "onConflict", "ignore", "include"];
+
+/// Inline properties of a package node, e.g. `target="~/.config/nvim"`
+const PACKAGE_PROPERTIES: &[&str] = &["target", "onConflict", "detect"];
+
+/// Child nodes of a package node
+const PACKAGE_SETTINGS: &[&str] = &[
+    "target",
+    "onConflict",
+    "detect",
+    "when",
+    "ignore",
+    "include",
+    "detectIgnore",
+];
+
+/// `, did you mean 'target'?` when one of the known names is close to `name`, else nothing
+fn did_you_mean(name: &str, known: &[&str]) -> String {
+    let name_lower = name.to_lowercase();
+    known
+        .iter()
+        .map(|candidate| {
+            let distance = strsim::damerau_levenshtein(&name_lower, &candidate.to_lowercase());
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= (name.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| format!(", did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+/// Quote a string for use in a KDL document
+fn quote_kdl_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
 }
 
 impl DotyConfig {
     /// Parse a KDL configuration file from a file path
+    /// `include` paths are resolved relative to the file that contains them
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read config file: {}", path.as_ref().display()))?;
-        Self::from_str(&content)
+        let path = path.as_ref();
+        let utf8_path = Utf8PathBuf::from(path.to_string_lossy().as_ref());
+        let content = fs::read_to_string(path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => DotyError::ConfigNotFound {
+                path: utf8_path.clone(),
+            },
+            _ => DotyError::io(
+                &utf8_path,
+                format!("Failed to read config file: {}", utf8_path),
+                err,
+            ),
+        })?;
+        Self::parse(&content, Some(path)).map_err(|source| {
+            DotyError::ConfigParse {
+                path: utf8_path,
+                source,
+            }
+            .into()
+        })
     }
 
-    /// Parse KDL configuration from a string
-    pub fn from_str(content: &str) -> Result<Self> {
-        let doc: KdlDocument = content.parse().context("Failed to parse KDL document")?;
+    /// Parse the main config and everything it includes, then expand package paths
+    fn parse(content: &str, file: Option<&Path>) -> Result<Self> {
+        let main_dir = match file.and_then(|f| f.parent()) {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => std::env::current_dir().context("Failed to get current directory")?,
+        };
+        let main_dir = main_dir.canonicalize().unwrap_or(main_dir);
+
+        let mut config = DotyConfig {
+            packages: Vec::new(),
+            path_resolution: PathResolution::default(),
+            on_conflict: OnConflict::default(),
+            variables: BTreeMap::new(),
+            filter: PathFilter::default(),
+            files: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut loader = IncludeLoader {
+            main_dir: main_dir.clone(),
+            display_dir: file
+                .and_then(|f| f.parent())
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            stack: file
+                .map(|f| f.canonicalize().unwrap_or_else(|_| f.to_path_buf()))
+                .into_iter()
+                .collect(),
+            packages: Vec::new(),
+        };
+        config.files.extend(
+            loader
+                .stack
+                .iter()
+                .flat_map(|f| Utf8PathBuf::from_path_buf(f.clone())),
+        );
+        let source = SourceFile {
+            name: file.map(|f| f.display().to_string()),
+            content,
+        };
+        loader.parse_document(&source, &main_dir, true, &mut config)?;
+
+        // Variables from every file and the pathResolution of the main config are known now,
+        // relative paths of included files are rewritten against the base actually in effect
+        let base_dir = match config.path_resolution {
+            PathResolution::Config => main_dir,
+            PathResolution::Cwd => {
+                let cwd = std::env::current_dir().context("Failed to get current directory")?;
+                cwd.canonicalize().unwrap_or(cwd)
+            }
+        };
+        let host = HostInfo::current();
+        for (mut package, include_dir) in loader.packages {
+            Self::expand_package_paths(&mut package, &config.variables, &host)?;
+            package.filter = package.filter.with_defaults(&config.filter)?;
+            if let Some(include_dir) = include_dir {
+                let prefix = match include_dir.strip_prefix(&base_dir) {
+                    Ok(relative) => relative.to_path_buf(),
+                    Err(_) => include_dir,
+                };
+                let prefix = Utf8PathBuf::from_path_buf(prefix)
+                    .map_err(|p| anyhow::anyhow!("Path is not valid UTF-8: {}", p.display()))?;
+                package.source = prefix_relative(&prefix, &package.source);
+                if !package.target.as_str().starts_with('~') {
+                    package.target = prefix_relative(&prefix, &package.target);
+                }
+            }
+            config.packages.push(package);
+        }
 
-        let mut packages = Vec::new();
-        let mut path_resolution = PathResolution::default();
+        Ok(config)
+    }
 
-        for node in doc.nodes() {
-            if let Some(package) = Self::parse_package(node)? {
-                packages.push(package);
-            } else if node.name().value() == "defaults" {
-                path_resolution = Self::parse_defaults(node)?;
+    /// Expand `$VAR` / `${VAR}` in a package's source and target (see fs_utils::expand_vars)
+    /// A package that doesn't apply to this machine keeps its paths if they can't be expanded here
+    fn expand_package_paths(
+        package: &mut Package,
+        variables: &BTreeMap<String, String>,
+        host: &HostInfo,
+    ) -> Result<()> {
+        let expanded = expand_vars(&package.source, variables)
+            .and_then(|source| Ok((source, expand_vars(&package.target, variables)?)));
+        match expanded {
+            Ok((source, target)) => {
+                package.source = source;
+                package.target = target;
+                Ok(())
             }
+            Err(_) if !package.is_active(host) => Ok(()),
+            Err(err) => Err(err.context(format!(
+                "Invalid path in package {} \"{}\"",
+                package.strategy, package.source
+            ))),
         }
+    }
 
-        Ok(DotyConfig {
-            packages,
-            path_resolution,
-        })
+    /// Get the effective conflict policy for a package (package setting, then defaults)
+    pub fn on_conflict_for(&self, package: &Package) -> OnConflict {
+        package.on_conflict.unwrap_or(self.on_conflict)
     }
 
-    /// Parse the defaults node
-    fn parse_defaults(node: &KdlNode) -> Result<PathResolution> {
-        let mut path_resolution = PathResolution::default();
+    /// Append a package node to a config file on disk
+    /// The new node is appended as text so existing comments and formatting are kept untouched
+    pub fn append_package<P: AsRef<Path>>(path: P, package: &Package) -> Result<()> {
+        let path = path.as_ref();
+        let mut content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+        content.push_str(&package.to_kdl_string());
+        content.push('\n');
+
+        // Make sure we never write a config that we can't read back
+        Self::parse(&content, Some(path)).context("Updated config would be invalid")?;
+
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+
+        Ok(())
+    }
 
+    /// Parse the defaults node
+    fn parse_defaults(node: &KdlNode, config: &mut DotyConfig) -> Result<()> {
         if let Some(children) = node.children() {
             for child in children.nodes() {
                 match child.name().value() {
@@ -95,7 +428,7 @@ impl DotyConfig {
                             .and_then(|e| e.value().as_string())
                             .with_context(|| "pathResolution requires a string value")?;
 
-                        path_resolution = match value {
+                        config.path_resolution = match value {
                             "config" => PathResolution::Config,
                             "cwd" => PathResolution::Cwd,
                             other => anyhow::bail!(
@@ -104,15 +437,74 @@ impl DotyConfig {
                             ),
                         };
                     }
-                    _other => {
-                        // For now, we only care about pathResolution
-                        // Other defaults can be added later
+                    "onConflict" => {
+                        let value = child
+                            .entries()
+                            .first()
+                            .and_then(|e| e.value().as_string())
+                            .with_context(|| "onConflict requires a string value")?;
+
+                        config.on_conflict = OnConflict::parse(value)?;
                     }
+                    // Unknown settings are reported by check_unknown_keys
+                    _ => {}
                 }
             }
         }
+        config.filter = Self::parse_filter(node).context("Invalid filter in defaults")?;
+
+        Ok(())
+    }
 
-        Ok(path_resolution)
+    /// Parse `ignore` and `include` pattern lists from the children of a node, e.g.
+    /// `ignore ".DS_Store" "*.swp"`, several nodes of the same name add up
+    fn parse_filter(node: &KdlNode) -> Result<PathFilter> {
+        PathFilter::new(
+            Self::get_list(node, "ignore")?,
+            Self::get_list(node, "include")?,
+        )
+    }
+
+    /// Collect the string arguments of all child nodes with the given name
+    fn get_list(node: &KdlNode, name: &str) -> Result<Vec<String>> {
+        let mut values = Vec::new();
+        for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+            if child.name().value() != name {
+                continue;
+            }
+            for entry in child.entries() {
+                let value = entry
+                    .value()
+                    .as_string()
+                    .with_context(|| format!("{} values must be strings", name))?;
+                values.push(value.to_string());
+            }
+        }
+        Ok(values)
+    }
+
+    /// Parse the variables node, e.g. `variables { email "me@example.com" }`
+    fn parse_variables(node: &KdlNode, config: &mut DotyConfig) -> Result<()> {
+        if let Some(children) = node.children() {
+            for child in children.nodes() {
+                let name = child.name().value();
+                let value = child
+                    .entries()
+                    .first()
+                    .map(|e| e.value())
+                    .and_then(|value| match value {
+                        KdlValue::String(s) => Some(s.clone()),
+                        KdlValue::Integer(i) => Some(i.to_string()),
+                        KdlValue::Float(f) => Some(f.to_string()),
+                        KdlValue::Bool(b) => Some(b.to_string()),
+                        KdlValue::Null => None,
+                    })
+                    .with_context(|| format!("Variable '{}' requires a value", name))?;
+                config.variables.insert(name.to_string(), value);
+            }
+        }
+
+        Ok(())
     }
 
     /// Parse a single package node
@@ -120,9 +512,15 @@ impl DotyConfig {
         let strategy = match node.name().value() {
             "LinkFolder" => LinkStrategy::LinkFolder,
             "LinkFilesRecursive" => LinkStrategy::LinkFilesRecursive,
-            "defaults" => return Ok(None), // Handle defaults separately
+            "Copy" => LinkStrategy::Copy,
+            "Template" => LinkStrategy::Template,
+            "defaults" | "variables" => return Ok(None), // Handled separately
             other => {
-                anyhow::bail!("Unknown node type: {}", other);
+                anyhow::bail!(
+                    "Unknown node type: {}{}",
+                    other,
+                    did_you_mean(other, NODE_TYPES)
+                );
             }
         };
 
@@ -135,49 +533,370 @@ impl DotyConfig {
             .with_context(|| format!("Missing source path for {} node", node.name().value()))?;
 
         // Get target path - either from inline property or child node
-        let target = Self::get_target(node)?;
+        let target = Self::get_setting(node, "target")
+            .with_context(|| format!("Missing target path for {} node", node.name().value()))?;
+
+        let on_conflict = Self::get_setting(node, "onConflict")
+            .map(|value| OnConflict::parse(&value))
+            .transpose()?;
+
+        // Several `when` nodes all have to match
+        let conditions = node
+            .children()
+            .map(|children| {
+                children
+                    .nodes()
+                    .iter()
+                    .filter(|child| child.name().value() == "when")
+                    .map(Condition::parse_when)
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()
+            .with_context(|| format!("Invalid 'when' on {} \"{}\"", node.name().value(), source))?
+            .unwrap_or_default();
+        let when = match conditions.len() {
+            0 => None,
+            1 => conditions.into_iter().next(),
+            _ => Some(Condition::All(conditions)),
+        };
+
+        let filter = Self::parse_filter(node)
+            .with_context(|| format!("Invalid filter on {} \"{}\"", node.name().value(), source))?;
+        let detect_ignore = Self::get_list(node, "detectIgnore")?;
+        let detect = Self::get_setting(node, "detect")
+            .map(|value| DetectPolicy::parse(&value))
+            .transpose()?;
 
         Ok(Some(Package {
             source: Utf8PathBuf::from(source),
             target: Utf8PathBuf::from(target),
             strategy,
+            on_conflict,
+            when,
+            filter,
+            detect_ignore,
+            detect,
         }))
     }
 
-    /// Extract target path from node (inline property or child node)
-    fn get_target(node: &KdlNode) -> Result<String> {
+    /// Extract a string setting from a package node (inline property or child node)
+    fn get_setting(node: &KdlNode, name: &str) -> Option<String> {
         // Try inline property first: LinkFolder "nvim" target="~/.config/nvim"
-        if let Some(target_entry) = node
+        if let Some(entry) = node
             .entries()
             .iter()
-            .find(|e| e.name().map(|n| n.value()) == Some("target"))
+            .find(|e| e.name().map(|n| n.value()) == Some(name))
         {
-            if let Some(target) = target_entry.value().as_string() {
-                return Ok(target.to_string());
+            if let Some(value) = entry.value().as_string() {
+                return Some(value.to_string());
             }
         }
 
         // Try child node: LinkFolder "nvim" { target "~/.config/nvim" }
         if let Some(children) = node.children() {
             for child in children.nodes() {
-                if child.name().value() == "target" {
-                    if let Some(target) =
-                        child.entries().first().and_then(|e| e.value().as_string())
+                if child.name().value() == name {
+                    if let Some(value) = child.entries().first().and_then(|e| e.value().as_string())
                     {
-                        return Ok(target.to_string());
+                        return Some(value.to_string());
                     }
                 }
             }
         }
 
-        anyhow::bail!("Missing target path for {} node", node.name().value())
+        None
+    }
+}
+
+/// Parse KDL configuration from a string
+/// `include` paths are resolved relative to the current directory
+impl std::str::FromStr for DotyConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(content: &str) -> Result<Self> {
+        Self::parse(content, None)
     }
 }
 
+/// A config file being parsed, to point errors and warnings at a line and column
+struct SourceFile<'a> {
+    /// Path as shown to the user, none for a config parsed from a string
+    name: Option<String>,
+    content: &'a str,
+}
+
+impl SourceFile<'_> {
+    /// `doty.kdl:3:25` for a byte offset into the file (`line 3, column 25` without a file name)
+    fn location(&self, offset: usize) -> String {
+        let before = self
+            .content
+            .get(..offset.min(self.content.len()))
+            .unwrap_or(self.content);
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+            + 1;
+        match &self.name {
+            Some(name) => format!("{}:{}:{}", name, line, column),
+            None => format!("line {}, column {}", line, column),
+        }
+    }
+
+    /// Every problem the KDL parser found, one per line with its location
+    fn syntax_error(&self, err: KdlError) -> anyhow::Error {
+        let problems: Vec<String> = err
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let mut problem = format!(
+                    "{}: {}",
+                    self.location(diagnostic.span.offset()),
+                    diagnostic.message.as_deref().unwrap_or("Unexpected error")
+                );
+                if let Some(help) = &diagnostic.help {
+                    problem.push_str(&format!(" ({})", help));
+                }
+                problem
+            })
+            .collect();
+        anyhow::anyhow!("{}", problems.join("\n")).context("Failed to parse KDL document")
+    }
+
+    /// Warn about settings of a node doty does not know, they would be ignored silently otherwise
+    fn check_unknown_keys(&self, node: &KdlNode, warnings: &mut Vec<String>) {
+        let node_name = node.name().value();
+        let first_argument = node
+            .entries()
+            .iter()
+            .find(|e| e.name().is_none())
+            .and_then(|e| e.value().as_string())
+            .unwrap_or_default();
+        match node_name {
+            "defaults" => {
+                self.check_node(node, "defaults", 0, &[], Some(DEFAULTS_SETTINGS), warnings)
+            }
+            "LinkFolder" | "LinkFilesRecursive" | "Copy" | "Template" => {
+                let what = format!("{} \"{}\"", node_name, first_argument);
+                self.check_node(
+                    node,
+                    &what,
+                    1,
+                    PACKAGE_PROPERTIES,
+                    Some(PACKAGE_SETTINGS),
+                    warnings,
+                );
+                for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+                    if child.name().value() == "when" {
+                        self.check_condition_keys(child, warnings);
+                    }
+                }
+            }
+            "include" => {
+                let what = format!("include \"{}\"", first_argument);
+                self.check_node(node, &what, 1, &[], Some(&[]), warnings);
+            }
+            // Variable names are free-form, only the block itself takes no values
+            "variables" => self.check_node(node, "variables", 0, &[], None, warnings),
+            _ => {}
+        }
+    }
+
+    /// Warn about the values inside `when` that no condition reads
+    /// Unknown conditions are errors when the package is parsed, so only their values are checked
+    fn check_condition_keys(&self, node: &KdlNode, warnings: &mut Vec<String>) {
+        for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+            let name = child.name().value();
+            let what = format!("condition '{}'", name);
+            if matches!(name, "all" | "any" | "not") {
+                self.check_node(child, &what, 0, &[], None, warnings);
+                self.check_condition_keys(child, warnings);
+            } else {
+                self.check_node(child, &what, 1, &[], Some(&[]), warnings);
+            }
+        }
+    }
+
+    /// Warn about entries and child nodes of `node` that are not read
+    /// `arguments` values are expected before any properties, child nodes are left to their
+    /// parser when `settings` is None
+    fn check_node(
+        &self,
+        node: &KdlNode,
+        what: &str,
+        arguments: usize,
+        properties: &[&str],
+        settings: Option<&[&str]>,
+        warnings: &mut Vec<String>,
+    ) {
+        let mut argument = 0;
+        for entry in node.entries() {
+            match entry.name().map(|n| n.value()) {
+                Some(name) if !properties.contains(&name) => warnings.push(format!(
+                    "{}: Unknown property '{}' on {}{}",
+                    self.location(entry.span().offset()),
+                    name,
+                    what,
+                    did_you_mean(name, properties)
+                )),
+                Some(_) => {}
+                None => {
+                    argument += 1;
+                    if argument > arguments {
+                        warnings.push(format!(
+                            "{}: Unexpected value '{}' on {}",
+                            self.location(entry.span().offset()),
+                            entry.value(),
+                            what
+                        ));
+                    }
+                }
+            }
+        }
+
+        let Some(settings) = settings else {
+            return;
+        };
+        for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+            let name = child.name().value();
+            if !settings.contains(&name) {
+                warnings.push(format!(
+                    "{}: Unknown setting '{}' in {}{}",
+                    self.location(child.span().offset()),
+                    name,
+                    what,
+                    did_you_mean(name, settings)
+                ));
+            }
+        }
+    }
+}
+
+/// Follows `include` nodes, remembering which file each package came from
+struct IncludeLoader {
+    /// Directory of the main config
+    main_dir: PathBuf,
+    /// Directory of the main config as the user gave it, included files are shown relative to it
+    display_dir: PathBuf,
+    /// Files currently being loaded (outermost first), to detect include cycles
+    stack: Vec<PathBuf>,
+    /// Packages with the directory of the included file they came from (None for the main config)
+    packages: Vec<(Package, Option<PathBuf>)>,
+}
+
+impl IncludeLoader {
+    /// Parse one config document located in `dir`, `is_main` for the main config file
+    fn parse_document(
+        &mut self,
+        source: &SourceFile,
+        dir: &Path,
+        is_main: bool,
+        config: &mut DotyConfig,
+    ) -> Result<()> {
+        let doc: KdlDocument = source
+            .content
+            .parse()
+            .map_err(|err| source.syntax_error(err))?;
+        let include_dir = (!is_main).then(|| dir.to_path_buf());
+
+        for node in doc.nodes() {
+            source.check_unknown_keys(node, &mut config.warnings);
+            let parsed = match node.name().value() {
+                "include" => self.include(node, dir, config),
+                "defaults" if !is_main => Err(anyhow::anyhow!(
+                    "'defaults' is only allowed in the main config file"
+                )),
+                "defaults" => DotyConfig::parse_defaults(node, config),
+                "variables" => DotyConfig::parse_variables(node, config),
+                _ => DotyConfig::parse_package(node).map(|package| {
+                    self.packages
+                        .extend(package.map(|p| (p, include_dir.clone())));
+                }),
+            };
+            parsed.with_context(|| {
+                format!(
+                    "In '{}' at {}",
+                    node.name().value(),
+                    source.location(node.span().offset())
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Load every file matched by an `include` node, e.g. `include "packages/*.kdl"`
+    fn include(&mut self, node: &KdlNode, dir: &Path, config: &mut DotyConfig) -> Result<()> {
+        let pattern = node
+            .entries()
+            .first()
+            .and_then(|e| e.value().as_string())
+            .context("include requires a path or glob pattern, e.g. include \"packages/*.kdl\"")?;
+        let full_pattern = dir.join(pattern);
+        let full_pattern = full_pattern.to_string_lossy();
+
+        let mut files = glob::glob(&full_pattern)
+            .with_context(|| format!("Invalid include pattern: {}", pattern))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to read include pattern: {}", pattern))?;
+        files.sort();
+        if files.is_empty() && !pattern.contains(['*', '?', '[']) {
+            anyhow::bail!("Included file not found: {}", full_pattern);
+        }
+
+        for file in files {
+            let file = file.canonicalize().unwrap_or(file);
+            if self.stack.contains(&file) {
+                let chain: Vec<String> = self
+                    .stack
+                    .iter()
+                    .chain(std::iter::once(&file))
+                    .map(|f| f.display().to_string())
+                    .collect();
+                anyhow::bail!("Include cycle: {}", chain.join(" → "));
+            }
+
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read included file: {}", file.display()))?;
+            let file_dir = file.parent().unwrap_or(dir).to_path_buf();
+            config
+                .files
+                .extend(Utf8PathBuf::from_path_buf(file.clone()));
+            let name = match file.strip_prefix(&self.main_dir) {
+                Ok(relative) => self.display_dir.join(relative),
+                Err(_) => file.clone(),
+            };
+            let source = SourceFile {
+                name: Some(name.display().to_string()),
+                content: &content,
+            };
+            self.stack.push(file.clone());
+            self.parse_document(&source, &file_dir, false, config)?;
+            self.stack.pop();
+        }
+
+        Ok(())
+    }
+}
+
+/// Make a relative path from an included file relative to the base directory instead
+fn prefix_relative(prefix: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
+    if prefix.as_str().is_empty() || path.is_absolute() {
+        return path.to_path_buf();
+    }
+    prefix
+        .join(path)
+        .components()
+        .filter(|c| *c != camino::Utf8Component::CurDir)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
+    use std::str::FromStr;
 
     #[test]
     fn test_parse_link_folder_inline() {
@@ -226,6 +945,167 @@ mod tests {
         assert_eq!(pkg.strategy, LinkStrategy::LinkFilesRecursive);
     }
 
+    #[test]
+    fn test_parse_copy() {
+        let config = r#"
+            Copy "vscode/settings.json" target="~/.config/Code/User/settings.json"
+        "#;
+
+        let result = DotyConfig::from_str(config).unwrap();
+        let pkg = &result.packages[0];
+        assert_eq!(pkg.strategy, LinkStrategy::Copy);
+        assert_eq!(pkg.source, Utf8PathBuf::from("vscode/settings.json"));
+        assert!(pkg
+            .to_kdl_string()
+            .starts_with("Copy \"vscode/settings.json\""));
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let config = DotyConfig::from_str(
+            r#"
+            defaults {
+                ignore ".DS_Store"
+            }
+            LinkFilesRecursive "zsh" target="~" {
+                ignore "*.md" "*.swp"
+                include "**/.z*"
+            }
+            "#,
+        )
+        .unwrap();
+        let expected = PathFilter::new(
+            vec![".DS_Store".into(), "*.md".into(), "*.swp".into()],
+            vec!["**/.z*".into()],
+        )
+        .unwrap();
+        assert_eq!(config.packages[0].filter, expected);
+
+        let err = DotyConfig::from_str(r#"LinkFilesRecursive "zsh" target="~" { ignore "[" }"#)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid filter on LinkFilesRecursive \"zsh\""));
+    }
+
+    #[test]
+    fn test_parse_detect_policy() {
+        let config = DotyConfig::from_str(
+            r#"
+            LinkFilesRecursive "zsh" target="~" detect="depth=2"
+            LinkFilesRecursive "fish" target="~/.config/fish" {
+                detect "full"
+            }
+            LinkFilesRecursive "bash" target="~"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.packages[0].detect, Some(DetectPolicy::Depth(2)));
+        assert_eq!(config.packages[1].detect, Some(DetectPolicy::Full));
+        assert_eq!(config.packages[2].detect, None);
+        assert_eq!(
+            config.packages[0].to_kdl_string(),
+            r#"LinkFilesRecursive "zsh" target="~" detect="depth=2""#
+        );
+
+        for invalid in ["depth=0", "depth=x", "everything"] {
+            let err = DotyConfig::from_str(&format!(
+                r#"LinkFilesRecursive "zsh" target="~" detect="{}""#,
+                invalid
+            ))
+            .unwrap_err();
+            assert!(format!("{:#}", err).contains("Invalid detect value"));
+        }
+    }
+
+    #[test]
+    fn test_parse_when() {
+        let config = r#"
+            LinkFolder "sway" target="~/.config/sway" {
+                when os="linux"
+                when env="WAYLAND_DISPLAY"
+            }
+            LinkFolder "nvim" target="~/.config/nvim"
+        "#;
+
+        let result = DotyConfig::from_str(config).unwrap();
+        assert_eq!(
+            result.packages[0].when,
+            Some(Condition::All(vec![
+                Condition::Os("linux".to_string()),
+                Condition::Env("WAYLAND_DISPLAY".to_string()),
+            ]))
+        );
+        assert_eq!(result.packages[1].when, None);
+
+        let macos = HostInfo {
+            hostname: "laptop".to_string(),
+            os: "macos".to_string(),
+        };
+        assert!(!result.packages[0].is_active(&macos));
+        assert!(result.packages[1].is_active(&macos));
+
+        let err =
+            DotyConfig::from_str(r#"LinkFolder "sway" target="~/.config/sway" { when hots="x"; }"#)
+                .unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid 'when' on LinkFolder \"sway\""));
+    }
+
+    #[test]
+    fn test_expand_package_paths() {
+        let config = r#"
+            variables {
+                dots "/srv/dots"
+            }
+
+            LinkFolder "$dots/nvim" target="${XDG_CONFIG_HOME}/nvim"
+        "#;
+
+        let result = DotyConfig::from_str(config).unwrap();
+        assert_eq!(
+            result.packages[0].source,
+            Utf8PathBuf::from("/srv/dots/nvim")
+        );
+        let xdg_config_home = std::env::var("XDG_CONFIG_HOME")
+            .unwrap_or_else(|_| format!("{}/.config", std::env::var("HOME").unwrap()));
+        assert_eq!(
+            result.packages[0].target,
+            Utf8PathBuf::from(xdg_config_home).join("nvim")
+        );
+
+        let err = DotyConfig::from_str(r#"LinkFolder "nvim" target="$DOTY_TEST_UNSET/nvim""#)
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("LinkFolder \"nvim\""));
+        assert!(message.contains("$DOTY_TEST_UNSET is not set"));
+
+        // Skipped packages may use variables that only exist where they apply
+        let skipped = r#"
+            LinkFolder "nvim" target="$DOTY_TEST_UNSET/nvim" {
+                when host="doty-test-no-such-host"
+            }
+        "#;
+        assert!(DotyConfig::from_str(skipped).is_ok());
+    }
+
+    #[test]
+    fn test_parse_template_and_variables() {
+        let config = r#"
+            variables {
+                email "me@example.com"
+                fontSize 12
+                work #true
+            }
+
+            Template "git/.gitconfig" target="~/.gitconfig"
+        "#;
+
+        let result = DotyConfig::from_str(config).unwrap();
+        assert_eq!(result.packages.len(), 1);
+        assert_eq!(result.packages[0].strategy, LinkStrategy::Template);
+        assert_eq!(result.variables["email"], "me@example.com");
+        assert_eq!(result.variables["fontSize"], "12");
+        assert_eq!(result.variables["work"], "true");
+    }
+
     #[test]
     fn test_parse_multiple_packages() {
         let config = r#"
@@ -298,6 +1178,278 @@ mod tests {
         let _ = fs::remove_dir_all(test_dir);
     }
 
+    #[test]
+    fn test_include_glob() {
+        let test_dir = "tests/tmpfs/test_include_glob";
+        let _ = fs::remove_dir_all(test_dir); // Clean up any existing test dir
+        fs::create_dir_all(format!("{}/packages", test_dir)).unwrap();
+
+        fs::write(
+            format!("{}/doty.kdl", test_dir),
+            r#"
+            include "packages/*.kdl"
+            LinkFolder "nvim" target="~/.config/nvim"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            format!("{}/packages/b-shell.kdl", test_dir),
+            r#"
+            variables { shell "zsh" }
+            LinkFilesRecursive "$shell" target="home"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            format!("{}/packages/a-editors.kdl", test_dir),
+            r#"LinkFolder "." target="~/.config/helix""#,
+        )
+        .unwrap();
+
+        let config = DotyConfig::from_file(format!("{}/doty.kdl", test_dir)).unwrap();
+        let paths: Vec<(&str, &str)> = config
+            .packages
+            .iter()
+            .map(|p| (p.source.as_str(), p.target.as_str()))
+            .collect();
+        // Sources and relative targets in included files resolve against the included file
+        assert_eq!(
+            paths,
+            vec![
+                ("packages", "~/.config/helix"),
+                ("packages/zsh", "packages/home"),
+                ("nvim", "~/.config/nvim"),
+            ]
+        );
+        let files: Vec<&str> = config
+            .files
+            .iter()
+            .map(|f| f.file_name().unwrap())
+            .collect();
+        assert_eq!(files, vec!["doty.kdl", "a-editors.kdl", "b-shell.kdl"]);
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
+    #[test]
+    fn test_include_path_resolution_cwd() {
+        let test_dir = "tests/tmpfs/test_include_path_resolution_cwd";
+        let _ = fs::remove_dir_all(test_dir); // Clean up any existing test dir
+        fs::create_dir_all(format!("{}/configs/packages", test_dir)).unwrap();
+
+        fs::write(
+            format!("{}/configs/doty.kdl", test_dir),
+            r#"
+            include "packages/*.kdl"
+            defaults { pathResolution "cwd" }
+            LinkFolder "nvim" target="~/.config/nvim"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            format!("{}/configs/packages/shell.kdl", test_dir),
+            r#"LinkFilesRecursive "zsh" target="home""#,
+        )
+        .unwrap();
+
+        // Paths of the main config stay relative to the cwd (the crate root while testing),
+        // paths of included files still resolve against the included file
+        let config = DotyConfig::from_file(format!("{}/configs/doty.kdl", test_dir)).unwrap();
+        let paths: Vec<(&str, &str)> = config
+            .packages
+            .iter()
+            .map(|p| (p.source.as_str(), p.target.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "tests/tmpfs/test_include_path_resolution_cwd/configs/packages/zsh",
+                    "tests/tmpfs/test_include_path_resolution_cwd/configs/packages/home"
+                ),
+                ("nvim", "~/.config/nvim"),
+            ]
+        );
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let test_dir = "tests/tmpfs/test_include_errors";
+        let _ = fs::remove_dir_all(test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir).unwrap();
+        let config_path = format!("{}/doty.kdl", test_dir);
+
+        // A missing literal include is an error, an empty glob is not
+        fs::write(&config_path, r#"include "missing.kdl""#).unwrap();
+        let err = DotyConfig::from_file(&config_path).unwrap_err();
+        assert!(format!("{:#}", err).contains("Included file not found"));
+        fs::write(&config_path, r#"include "missing/*.kdl""#).unwrap();
+        assert!(DotyConfig::from_file(&config_path).is_ok());
+
+        // Errors name the included file
+        fs::write(&config_path, r#"include "bad.kdl""#).unwrap();
+        fs::write(format!("{}/bad.kdl", test_dir), r#"LinkFolder "x""#).unwrap();
+        let err = format!("{:#}", DotyConfig::from_file(&config_path).unwrap_err());
+        assert!(err.contains("bad.kdl"), "{}", err);
+        assert!(err.contains("target"), "{}", err);
+
+        // Cycles are detected
+        fs::write(&config_path, r#"include "a.kdl""#).unwrap();
+        fs::write(format!("{}/a.kdl", test_dir), r#"include "b.kdl""#).unwrap();
+        fs::write(format!("{}/b.kdl", test_dir), r#"include "a.kdl""#).unwrap();
+        let err = format!("{:#}", DotyConfig::from_file(&config_path).unwrap_err());
+        assert!(err.contains("Include cycle"), "{}", err);
+        assert!(err.contains("a.kdl → "), "{}", err);
+
+        // defaults belong in the main config
+        fs::write(&config_path, r#"include "a.kdl""#).unwrap();
+        fs::write(
+            format!("{}/a.kdl", test_dir),
+            r#"defaults { pathResolution "cwd" }"#,
+        )
+        .unwrap();
+        let err = format!("{:#}", DotyConfig::from_file(&config_path).unwrap_err());
+        assert!(err.contains("only allowed in the main config"), "{}", err);
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
+    #[test]
+    fn test_unknown_keys_warn() {
+        let config = DotyConfig::from_str(
+            r#"defaults {
+    onconflict "skip"
+}
+LinkFolder "nvim" tagret="~/.config/nvim" target="~/.config/nvim" {
+    ignores "*.log"
+    colour "blue"
+}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.warnings,
+            [
+                "line 2, column 5: Unknown setting 'onconflict' in defaults, did you mean 'onConflict'?",
+                "line 4, column 19: Unknown property 'tagret' on LinkFolder \"nvim\", did you mean 'target'?",
+                "line 5, column 5: Unknown setting 'ignores' in LinkFolder \"nvim\", did you mean 'ignore'?",
+                "line 6, column 5: Unknown setting 'colour' in LinkFolder \"nvim\"",
+            ]
+        );
+        assert_eq!(config.packages.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_keys_warn_in_when_include_and_variables() {
+        let test_dir = "tests/tmpfs/test_unknown_keys_warn_in_when_include_and_variables";
+        let _ = fs::remove_dir_all(test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir).unwrap();
+        fs::write(format!("{}/shell.kdl", test_dir), "").unwrap();
+        fs::write(
+            format!("{}/doty.kdl", test_dir),
+            r#"include "shell.kdl" "more.kdl" optional=#true {
+    recursive
+}
+variables theme="dark" {
+    email "me@example.com"
+}
+LinkFolder "sway" target="~/.config/sway" {
+    when {
+        os "linux" version="24.04"
+        any "wayland" {
+            env "WAYLAND_DISPLAY" { strict }
+        }
+    }
+}"#,
+        )
+        .unwrap();
+
+        let config = DotyConfig::from_file(format!("{}/doty.kdl", test_dir)).unwrap();
+        let location = |line_column: &str| format!("{}/doty.kdl:{}", test_dir, line_column);
+        assert_eq!(
+            config.warnings,
+            [
+                format!(
+                    "{}: Unexpected value 'more.kdl' on include \"shell.kdl\"",
+                    location("1:21")
+                ),
+                format!(
+                    "{}: Unknown property 'optional' on include \"shell.kdl\"",
+                    location("1:32")
+                ),
+                format!(
+                    "{}: Unknown setting 'recursive' in include \"shell.kdl\"",
+                    location("2:5")
+                ),
+                format!(
+                    "{}: Unknown property 'theme' on variables",
+                    location("4:11")
+                ),
+                format!(
+                    "{}: Unknown property 'version' on condition 'os'",
+                    location("9:20")
+                ),
+                format!(
+                    "{}: Unexpected value 'wayland' on condition 'any'",
+                    location("10:13")
+                ),
+                format!(
+                    "{}: Unknown setting 'strict' in condition 'env'",
+                    location("11:37")
+                ),
+            ]
+        );
+        assert_eq!(config.variables["email"], "me@example.com");
+        assert_eq!(config.packages.len(), 1);
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let err =
+            DotyConfig::from_str("LinkFolder \"a\" target=\"b\"\nLinkfolder \"nvim\" target=\"c\"")
+                .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "In 'Linkfolder' at line 2, column 1: Unknown node type: Linkfolder, did you mean 'LinkFolder'?"
+        );
+
+        let err = DotyConfig::from_str("LinkFolder \"a\" target=\"b\"\nLinkFolder \"nvim\" {")
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(
+            message.starts_with("Failed to parse KDL document: line 2"),
+            "{}",
+            message
+        );
+
+        let test_dir = "tests/tmpfs/test_error_locations";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir_all(format!("{}/packages", test_dir)).unwrap();
+        let config_path = format!("{}/doty.kdl", test_dir);
+        fs::write(&config_path, "include \"packages/*.kdl\"\n").unwrap();
+        fs::write(
+            format!("{}/packages/shell.kdl", test_dir),
+            "// shell\nCopy \"zsh\" target=\"~\" onConflict=\"skip\" detct=\"full\"\n",
+        )
+        .unwrap();
+        let config = DotyConfig::from_file(&config_path).unwrap();
+        assert_eq!(
+            config.warnings,
+            [format!(
+                "{}/packages/shell.kdl:2:41: Unknown property 'detct' on Copy \"zsh\", did you mean 'detect'?",
+                test_dir
+            )]
+        );
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
     #[test]
     fn test_from_file_not_found() {
         let config_path = "tests/tmpfs/nonexistent.kdl";
@@ -385,10 +1537,128 @@ mod tests {
 
         let result = DotyConfig::from_str(config);
         assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Invalid pathResolution value"));
+        assert!(format!("{:#}", result.unwrap_err()).contains("Invalid pathResolution value"));
+    }
+
+    #[test]
+    fn test_link_strategy_display() {
+        assert_eq!(LinkStrategy::LinkFolder.to_string(), "LinkFolder");
+        assert_eq!(
+            LinkStrategy::LinkFilesRecursive.to_string(),
+            "LinkFilesRecursive"
+        );
+    }
+
+    #[test]
+    fn test_package_to_kdl_string() {
+        let package = Package {
+            source: Utf8PathBuf::from("alacritty"),
+            target: Utf8PathBuf::from("~/.config/alacritty"),
+            strategy: LinkStrategy::LinkFolder,
+            on_conflict: None,
+            when: None,
+            filter: Default::default(),
+            detect_ignore: Vec::new(),
+            detect: None,
+        };
+
+        assert_eq!(
+            package.to_kdl_string(),
+            r#"LinkFolder "alacritty" target="~/.config/alacritty""#
+        );
+
+        let quoted = Package {
+            source: Utf8PathBuf::from(r#"my "app""#),
+            target: Utf8PathBuf::from("~/app"),
+            strategy: LinkStrategy::LinkFilesRecursive,
+            on_conflict: Some(OnConflict::Backup),
+            when: None,
+            filter: Default::default(),
+            detect_ignore: Vec::new(),
+            detect: None,
+        };
+        let parsed = DotyConfig::from_str(&quoted.to_kdl_string()).unwrap();
+        assert_eq!(parsed.packages[0], quoted);
+    }
+
+    #[test]
+    fn test_append_package_keeps_comments() {
+        let test_dir = "tests/tmpfs/test_append_package_keeps_comments";
+        let _ = fs::remove_dir_all(test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir).unwrap();
+
+        let config_content = r#"// My dotfiles
+defaults {
+    pathResolution "config" // keep me
+}
+
+LinkFolder "nvim" target="~/.config/nvim""#;
+
+        let config_path = format!("{}/doty.kdl", test_dir);
+        fs::write(&config_path, config_content).unwrap();
+
+        let package = Package {
+            source: Utf8PathBuf::from("alacritty"),
+            target: Utf8PathBuf::from("~/.config/alacritty"),
+            strategy: LinkStrategy::LinkFolder,
+            on_conflict: None,
+            when: None,
+            filter: Default::default(),
+            detect_ignore: Vec::new(),
+            detect: None,
+        };
+        DotyConfig::append_package(&config_path, &package).unwrap();
+
+        let updated = fs::read_to_string(&config_path).unwrap();
+        assert!(updated.starts_with(config_content));
+        assert!(updated.contains("pathResolution \"config\" // keep me"));
+
+        let config = DotyConfig::from_file(&config_path).unwrap();
+        assert_eq!(config.packages.len(), 2);
+        assert_eq!(config.packages[1], package);
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
+
+    #[test]
+    fn test_parse_on_conflict() {
+        let config = r#"
+            defaults {
+                onConflict "backup"
+            }
+            LinkFolder "nvim" target="~/.config/nvim"
+            LinkFolder "fish" target="~/.config/fish" onConflict="overwrite"
+            LinkFilesRecursive "zsh" {
+                target "~"
+                onConflict "skip"
+            }
+        "#;
+
+        let result = DotyConfig::from_str(config).unwrap();
+        assert_eq!(result.on_conflict, OnConflict::Backup);
+        assert_eq!(result.packages[0].on_conflict, None);
+        assert_eq!(result.packages[1].on_conflict, Some(OnConflict::Backup));
+        assert_eq!(result.packages[2].on_conflict, Some(OnConflict::Skip));
+
+        assert_eq!(
+            result.on_conflict_for(&result.packages[0]),
+            OnConflict::Backup
+        );
+        assert_eq!(
+            result.on_conflict_for(&result.packages[1]),
+            OnConflict::Backup
+        );
+    }
+
+    #[test]
+    fn test_parse_on_conflict_default_and_invalid() {
+        let result = DotyConfig::from_str(r#"LinkFolder "nvim" target="~/.config/nvim""#).unwrap();
+        assert_eq!(result.on_conflict, OnConflict::Fail);
+
+        let result =
+            DotyConfig::from_str(r#"LinkFolder "nvim" target="~/.config/nvim" onConflict="nuke""#);
+        assert!(format!("{:#}", result.unwrap_err()).contains("Invalid onConflict value"));
     }
 
     #[test]
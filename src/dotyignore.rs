@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fs;
+use std::io::Write;
+
+use crate::config::Package;
+
+/// Name of the gitignore-syntax files that hide untracked files from `doty detect`
+pub const DOTYIGNORE_FILE: &str = ".dotyignore";
+
+/// Untracked files `doty detect` should not report for one package
+///
+/// Combines `.dotyignore` in the repo root (all packages), `.dotyignore` in the package
+/// source directory and the package's `detectIgnore` list. Patterns use gitignore syntax
+/// and are matched against paths relative to the package target, so `/cache/` only
+/// matches the `cache` directory directly inside the target.
+#[derive(Debug, Clone)]
+pub struct DetectIgnore {
+    matcher: Gitignore,
+}
+
+impl DetectIgnore {
+    /// Load the ignore rules for a package
+    pub fn for_package(repo_root: &Utf8Path, package: &Package) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new("");
+        let source_dir = repo_root.join(&package.source);
+        for file in [
+            repo_root.join(DOTYIGNORE_FILE),
+            source_dir.join(DOTYIGNORE_FILE),
+        ] {
+            if file.is_file() {
+                if let Some(err) = builder.add(&file) {
+                    return Err(err).with_context(|| format!("Failed to read {}", file));
+                }
+            }
+        }
+        for pattern in &package.detect_ignore {
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("Invalid detectIgnore pattern: {}", pattern))?;
+        }
+
+        Ok(Self {
+            matcher: builder
+                .build()
+                .context("Failed to build .dotyignore rules")?,
+        })
+    }
+
+    /// Whether a file (path relative to the package target) is ignored
+    pub fn is_ignored(&self, relative: &Utf8Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(relative, false)
+            .is_ignore()
+    }
+}
+
+/// Append paths (relative to the package target) to a `.dotyignore` file, anchored so
+/// they only match that exact file
+pub fn append_to_dotyignore(file: &Utf8Path, relative_paths: &[&Utf8Path]) -> Result<()> {
+    let existing = fs::read_to_string(file).unwrap_or_default();
+    let mut content = String::new();
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        content.push('\n');
+    }
+    for path in relative_paths {
+        content.push_str(&format!("/{}\n", escape_pattern(path.as_str())));
+    }
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed to write {}", file))
+}
+
+/// Escape characters that have a meaning in gitignore patterns
+fn escape_pattern(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\' | '!' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LinkStrategy;
+    use camino::Utf8PathBuf;
+
+    fn package(detect_ignore: &[&str]) -> Package {
+        Package {
+            source: "fish".into(),
+            target: "~/.config/fish".into(),
+            strategy: LinkStrategy::LinkFilesRecursive,
+            on_conflict: None,
+            when: None,
+            filter: Default::default(),
+            detect_ignore: detect_ignore.iter().map(|s| s.to_string()).collect(),
+            detect: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_ignore_sources() {
+        let repo = Utf8PathBuf::from("tests/tmpfs/test_detect_ignore_sources");
+        let _ = fs::remove_dir_all(&repo); // Clean up any existing test dir
+        fs::create_dir_all(repo.join("fish")).unwrap();
+        fs::write(repo.join(DOTYIGNORE_FILE), "# all packages\n*.log\n").unwrap();
+        fs::write(repo.join("fish").join(DOTYIGNORE_FILE), "/fish_variables\n").unwrap();
+
+        let ignore = DetectIgnore::for_package(&repo, &package(&["completions/"])).unwrap();
+        assert!(ignore.is_ignored(Utf8Path::new("debug.log")));
+        assert!(ignore.is_ignored(Utf8Path::new("fish_variables")));
+        assert!(!ignore.is_ignored(Utf8Path::new("conf.d/fish_variables")));
+        assert!(ignore.is_ignored(Utf8Path::new("completions/git.fish")));
+        assert!(!ignore.is_ignored(Utf8Path::new("functions/ls.fish")));
+
+        // Clean up
+        let _ = fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_append_to_dotyignore() {
+        let repo = Utf8PathBuf::from("tests/tmpfs/test_append_to_dotyignore");
+        let _ = fs::remove_dir_all(&repo); // Clean up any existing test dir
+        fs::create_dir_all(repo.join("fish")).unwrap();
+        let file = repo.join("fish").join(DOTYIGNORE_FILE);
+        fs::write(&file, "*.log").unwrap();
+
+        append_to_dotyignore(
+            &file,
+            &[
+                Utf8Path::new("conf.d/local.fish"),
+                Utf8Path::new("#notes[1]"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "*.log\n/conf.d/local.fish\n/\\#notes\\[1\\]\n"
+        );
+
+        let ignore = DetectIgnore::for_package(&repo, &package(&[])).unwrap();
+        assert!(ignore.is_ignored(Utf8Path::new("conf.d/local.fish")));
+        assert!(ignore.is_ignored(Utf8Path::new("#notes[1]")));
+        assert!(!ignore.is_ignored(Utf8Path::new("local.fish")));
+
+        // Clean up
+        let _ = fs::remove_dir_all(&repo);
+    }
+}
@@ -0,0 +1,513 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use pluralizer::pluralize;
+use std::fmt;
+use std::io;
+
+/// Errors with a stable code (`DOTY-Exxx`) and a suggested fix
+///
+/// `Repo` and `Deployment` return them directly. The modules underneath use `anyhow` and wrap
+/// a `DotyError` where the cause is known (a missing config, a corrupt lockfile, a permission
+/// problem), `DotyError::find` digs it out of such a chain again.
+#[derive(Debug)]
+pub enum DotyError {
+    /// DOTY-E001: The config file does not exist
+    ConfigNotFound { path: Utf8PathBuf },
+    /// DOTY-E002: The config file (or a file it includes) is not valid
+    ConfigParse {
+        path: Utf8PathBuf,
+        source: anyhow::Error,
+    },
+    /// DOTY-E010: A source or target path can't be resolved ($VAR not set, HOME missing, ...)
+    PathResolution { path: Utf8PathBuf, reason: String },
+    /// DOTY-E020: The filesystem refused access
+    PermissionDenied {
+        path: Utf8PathBuf,
+        message: String,
+        source: io::Error,
+    },
+    /// DOTY-E030: Targets are in the way and their packages have no `onConflict` policy
+    Conflicts { targets: Vec<Utf8PathBuf> },
+    /// DOTY-E031: A target became a real file or directory after the plan was computed
+    TargetOccupied {
+        target: Utf8PathBuf,
+        existing: String,
+    },
+    /// DOTY-E032: An action failed, everything the run did before it was rolled back
+    ActionFailed {
+        target: Utf8PathBuf,
+        rolled_back: usize,
+        source: anyhow::Error,
+    },
+    /// DOTY-E033: Rolling back a failed run failed too, the journal is kept to finish it later
+    RollbackFailed { source: anyhow::Error },
+    /// DOTY-E040: The lockfile of this host can't be parsed
+    LockfileCorrupt {
+        path: Utf8PathBuf,
+        source: anyhow::Error,
+    },
+    /// DOTY-E050: Reading or writing a file failed
+    Io {
+        path: Utf8PathBuf,
+        message: String,
+        source: io::Error,
+    },
+    /// Anything without a code of its own (templates, scanning, ...)
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = DotyError> = std::result::Result<T, E>;
+
+/// Every code with its title and the help text of `doty explain-error`
+const EXPLANATIONS: &[(&str, &str, &str)] = &[
+    (
+        "DOTY-E001",
+        "Config file not found",
+        "doty looks for doty.kdl in the current directory unless --config points somewhere else.
+
+Run doty from your dotfiles repo, or pass the config explicitly:
+
+    doty --config ~/dotfiles/doty.kdl link
+
+Relative source paths in the config are resolved from the directory of the config file
+(pathResolution \"config\", the default) or from the current directory (pathResolution \"cwd\").",
+    ),
+    (
+        "DOTY-E002",
+        "Invalid config file",
+        "The config file, or a file it includes, could not be parsed. The lines after the error
+name the file and the problem: a KDL syntax error, an unknown node, a package without
+target=, an invalid when or ignore pattern, an include cycle.
+
+Supported top-level nodes are defaults, variables, include and the package strategies
+LinkFolder, LinkFilesRecursive, Copy and Template:
+
+    defaults {
+        onConflict \"backup\"
+    }
+    LinkFolder \"nvim\" target=\"~/.config/nvim\"
+
+Fix the file and run the command again, nothing was changed.",
+    ),
+    (
+        "DOTY-E010",
+        "Path can't be resolved",
+        "A source or target path uses something that is not available on this machine: a $VAR
+that is neither defined in the variables block nor set in the environment, ~ without HOME,
+or a directory whose name is not valid UTF-8.
+
+Define the variable in doty.kdl:
+
+    variables {
+        DOTFILES_HOST \"work\"
+    }
+
+or export it before running doty. Packages whose when condition does not match this machine
+may use variables that are not set here, they are skipped.",
+    ),
+    (
+        "DOTY-E020",
+        "Permission denied",
+        "The filesystem refused to let doty read or change a path. Typical causes are targets in
+directories owned by another user (e.g. /etc), read-only mounts, or files created by sudo.
+
+Check the owner and mode of the path and its parent directory with ls -la. Fix them, or
+leave the path out of doty.kdl. Running doty with sudo is rarely the right fix, it leaves
+root-owned links and state behind.",
+    ),
+    (
+        "DOTY-E030",
+        "Conflicts at link targets",
+        "Files or directories doty does not manage are in the place of links it wants to create.
+Without an onConflict policy, doty stops before touching anything.
+
+Pick a policy for the package or for every package in defaults:
+
+    LinkFolder \"nvim\" target=\"~/.config/nvim\" onConflict=\"backup\"
+
+  skip: leave the existing file alone and don't link
+  backup: move it to .doty/backups and link (undo with doty restore)
+  adopt: move it into the repo, replacing the source, and link
+
+overwrite is accepted as another name for backup: doty never deletes what it does not own.
+
+doty link --dry-run shows every conflict without changing anything.",
+    ),
+    (
+        "DOTY-E031",
+        "Target is occupied",
+        "A real file or directory showed up at a target between computing the plan and executing
+it. doty never deletes what it does not own, so the action failed.
+
+Look at the path: move the file away, adopt it into the repo with doty adopt <path>,
+or set onConflict on its package, then run doty link again.",
+    ),
+    (
+        "DOTY-E032",
+        "Link failed and was rolled back",
+        "One action of a link or rollback run failed. Everything the run had changed before was
+undone from the journal, so the targets and the lockfile are as they were.
+
+The error below it says why the action failed. Fix that and run the command again, or use
+--keep-going to apply everything else and get a list of the failures at the end.",
+    ),
+    (
+        "DOTY-E033",
+        "Rollback failed",
+        "A link run failed and undoing its changes failed too. The journal in .doty/state is kept.
+
+Fix the cause shown below (usually permissions) and run doty link again: it finishes the
+rollback from the journal before doing anything else. doty link --dry-run shows how many
+steps are left.",
+    ),
+    (
+        "DOTY-E040",
+        "Lockfile is corrupt",
+        "The lockfile in .doty/state/<hostname>.lock.kdl records which targets doty manages on
+this machine and could not be parsed, usually because it was edited by hand or a merge
+left conflict markers in it.
+
+Every run also saves a generation in .doty/state/generations/<hostname>/<n>.lock.kdl.
+Copy the newest one over the lockfile to get back to the last known state:
+
+    cp .doty/state/generations/<hostname>/<n>.lock.kdl .doty/state/<hostname>.lock.kdl
+
+or fix the file by hand. Deleting it makes doty forget which links it created on this
+machine, doty clean and doty detect no longer know about them.",
+    ),
+    (
+        "DOTY-E050",
+        "File operation failed",
+        "Reading or writing a file failed for a reason other than permissions, e.g. a full disk,
+a missing directory or a path that is too long. The error below it has the details from the
+operating system.
+
+Nothing was changed by the failed operation. Run doty link again once the cause is fixed,
+a failed link run rolls back what it already did.",
+    ),
+];
+
+impl DotyError {
+    /// An I/O error at `path`, permission problems get their own code
+    pub fn io(path: &Utf8Path, message: impl Into<String>, source: io::Error) -> Self {
+        let (path, message) = (path.to_path_buf(), message.into());
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => DotyError::PermissionDenied {
+                path,
+                message,
+                source,
+            },
+            _ => DotyError::Io {
+                path,
+                message,
+                source,
+            },
+        }
+    }
+
+    /// The innermost `DotyError` of an error chain, it names the actual cause
+    pub fn find(error: &anyhow::Error) -> Option<&DotyError> {
+        error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<DotyError>())
+            .last()
+    }
+
+    /// Stable code, e.g. `DOTY-E002`, none for `Other`
+    pub fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            DotyError::ConfigNotFound { .. } => "DOTY-E001",
+            DotyError::ConfigParse { .. } => "DOTY-E002",
+            DotyError::PathResolution { .. } => "DOTY-E010",
+            DotyError::PermissionDenied { .. } => "DOTY-E020",
+            DotyError::Conflicts { .. } => "DOTY-E030",
+            DotyError::TargetOccupied { .. } => "DOTY-E031",
+            DotyError::ActionFailed { .. } => "DOTY-E032",
+            DotyError::RollbackFailed { .. } => "DOTY-E033",
+            DotyError::LockfileCorrupt { .. } => "DOTY-E040",
+            DotyError::Io { .. } => "DOTY-E050",
+            DotyError::Other(_) => return None,
+        })
+    }
+
+    /// The path or package the error is about
+    pub fn path(&self) -> Option<&Utf8Path> {
+        match self {
+            DotyError::ConfigNotFound { path }
+            | DotyError::ConfigParse { path, .. }
+            | DotyError::PathResolution { path, .. }
+            | DotyError::PermissionDenied { path, .. }
+            | DotyError::LockfileCorrupt { path, .. }
+            | DotyError::Io { path, .. } => Some(path),
+            DotyError::TargetOccupied { target, .. } | DotyError::ActionFailed { target, .. } => {
+                Some(target)
+            }
+            DotyError::Conflicts { targets } => targets.first().map(|t| t.as_path()),
+            DotyError::RollbackFailed { .. } | DotyError::Other(_) => None,
+        }
+    }
+
+    /// Suggested fix, one line
+    pub fn hint(&self) -> Option<String> {
+        Some(match self {
+            DotyError::ConfigNotFound { path } => {
+                format!("Create {} or point --config to your doty.kdl", path)
+            }
+            DotyError::ConfigParse { path, .. } => {
+                format!("Fix the error in {}, nothing was changed", path)
+            }
+            // Only a missing variable can be fixed in doty.kdl
+            DotyError::PathResolution { reason, .. } if reason.starts_with("Variable ") => {
+                "Define the variable in the variables block of doty.kdl or in the environment"
+                    .to_string()
+            }
+            DotyError::PathResolution { reason, .. } if reason.starts_with("HOME ") => {
+                "Set HOME in the environment, ~ expands to it".to_string()
+            }
+            DotyError::PathResolution { .. } => {
+                "Run 'doty explain-error DOTY-E010' to see what paths can depend on".to_string()
+            }
+            DotyError::PermissionDenied { path, .. } => format!(
+                "Check the owner and permissions of {} and its parent directory",
+                path
+            ),
+            DotyError::Conflicts { targets } => format!(
+                "Set onConflict on the package or in defaults (first conflict: {})",
+                targets.first().map(|t| t.as_str()).unwrap_or("?")
+            ),
+            DotyError::TargetOccupied { target, .. } => format!(
+                "Move {} away or adopt it with 'doty adopt', then link again",
+                target
+            ),
+            DotyError::ActionFailed { .. } => {
+                "Fix the cause above and link again, or use --keep-going".to_string()
+            }
+            DotyError::RollbackFailed { .. } => {
+                "Fix the cause above and run 'doty link' to finish the rollback".to_string()
+            }
+            DotyError::LockfileCorrupt { .. } => {
+                "Copy the newest file of .doty/state/generations over it, or fix it by hand"
+                    .to_string()
+            }
+            DotyError::Io { path, .. } => {
+                format!("Check that {} is accessible and the disk is not full", path)
+            }
+            DotyError::Other(_) => return None,
+        })
+    }
+}
+
+/// Title and help text of an error code, `E012` and `doty-e012` work too
+pub fn explain(code: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    let code = code.trim().to_uppercase();
+    let code = match code.strip_prefix("DOTY-") {
+        Some(_) => code,
+        None => format!("DOTY-{}", code),
+    };
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _, _)| *known == code)
+        .copied()
+}
+
+/// Every error code with its title, for `doty explain-error` without a code
+pub fn codes() -> impl Iterator<Item = (&'static str, &'static str)> {
+    EXPLANATIONS.iter().map(|(code, title, _)| (*code, *title))
+}
+
+impl fmt::Display for DotyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DotyError::ConfigNotFound { path } => write!(f, "Config file not found: {}", path),
+            DotyError::ConfigParse { path, .. } => write!(f, "Invalid config file: {}", path),
+            DotyError::PathResolution { path, reason } => {
+                write!(f, "Cannot resolve path {}: {}", path, reason)
+            }
+            DotyError::PermissionDenied { message, .. } | DotyError::Io { message, .. } => {
+                write!(f, "{}", message)
+            }
+            DotyError::Conflicts { targets } => write!(
+                f,
+                "{} at link targets. Set onConflict to 'skip', 'backup', 'overwrite' or 'adopt' on the package or in defaults to resolve, nothing was changed",
+                pluralize("conflict", targets.len() as isize, true)
+            ),
+            DotyError::TargetOccupied { target, existing } => write!(
+                f,
+                "Refusing to replace {}: existing {} is not managed by doty",
+                target, existing
+            ),
+            DotyError::ActionFailed {
+                target,
+                rolled_back,
+                ..
+            } => write!(
+                f,
+                "Link failed at {}, rolled back {}. Use --keep-going to apply everything else anyway",
+                target,
+                pluralize("change", *rolled_back as isize, true)
+            ),
+            DotyError::RollbackFailed { .. } => write!(
+                f,
+                "Rollback failed, run 'doty link' again to finish it from the journal"
+            ),
+            DotyError::LockfileCorrupt { path, .. } => write!(f, "Corrupt lockfile: {}", path),
+            DotyError::Other(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for DotyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DotyError::ConfigParse { source, .. }
+            | DotyError::ActionFailed { source, .. }
+            | DotyError::RollbackFailed { source }
+            | DotyError::LockfileCorrupt { source, .. } => Some(source.as_ref()),
+            DotyError::PermissionDenied { source, .. } | DotyError::Io { source, .. } => {
+                Some(source)
+            }
+            // Displayed as the wrapped error itself, continue with its cause
+            DotyError::Other(error) => error.source(),
+            DotyError::ConfigNotFound { .. }
+            | DotyError::PathResolution { .. }
+            | DotyError::Conflicts { .. }
+            | DotyError::TargetOccupied { .. } => None,
+        }
+    }
+}
+
+/// Keeps a `DotyError` wrapped in the chain (context is dropped), anything else becomes `Other`
+impl From<anyhow::Error> for DotyError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<DotyError>() {
+            Ok(error) => error,
+            Err(error) => DotyError::Other(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_chain() {
+        let error = DotyError::ConfigParse {
+            path: "doty.kdl".into(),
+            source: anyhow::anyhow!("Unknown node type: foo"),
+        };
+        let chain: Vec<String> = anyhow::Error::from(error)
+            .chain()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            chain,
+            ["Invalid config file: doty.kdl", "Unknown node type: foo"]
+        );
+
+        // Wrapped anyhow errors are not repeated in the chain
+        let io: anyhow::Result<()> = Err(io::Error::other("disk full").into());
+        let error = DotyError::from(io.context("Failed to save lockfile").unwrap_err());
+        assert!(matches!(error, DotyError::Other(_)));
+        assert_eq!(error.code(), None);
+        let chain: Vec<String> = anyhow::Error::from(error)
+            .chain()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(chain, ["Failed to save lockfile", "disk full"]);
+    }
+
+    #[test]
+    fn test_find_in_chain() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let cause = DotyError::io("/etc/zshrc".into(), "Failed to create symlink", denied);
+        let failed = DotyError::ActionFailed {
+            target: "/etc/zshrc".into(),
+            rolled_back: 2,
+            source: anyhow::Error::from(cause).context("while linking"),
+        };
+        let error = anyhow::Error::from(failed).context("Link failed");
+
+        // The innermost error names the cause
+        let found = DotyError::find(&error).unwrap();
+        assert_eq!(found.code(), Some("DOTY-E020"));
+        assert_eq!(found.path(), Some(Utf8Path::new("/etc/zshrc")));
+        assert!(found.hint().unwrap().contains("/etc/zshrc"));
+
+        // Converting keeps the typed error, not the context around it
+        let typed = DotyError::from(anyhow::Error::from(DotyError::Conflicts {
+            targets: vec!["a".into(), "b".into()],
+        }))
+        .to_string();
+        assert!(
+            typed.starts_with("2 conflicts at link targets"),
+            "{}",
+            typed
+        );
+    }
+
+    #[test]
+    fn test_path_resolution_hint() {
+        let error = |reason: &str| DotyError::PathResolution {
+            path: "$CFG/nvim".into(),
+            reason: reason.to_string(),
+        };
+        assert!(error("Variable $CFG is not set")
+            .hint()
+            .unwrap()
+            .contains("variables block"));
+        assert!(error("HOME environment variable not set")
+            .hint()
+            .unwrap()
+            .starts_with("Set HOME"));
+        assert!(!error("Current directory path is not valid UTF-8")
+            .hint()
+            .unwrap()
+            .contains("variable"));
+    }
+
+    #[test]
+    fn test_explain() {
+        let errors = [
+            DotyError::ConfigNotFound { path: "x".into() },
+            DotyError::ConfigParse {
+                path: "x".into(),
+                source: anyhow::anyhow!("x"),
+            },
+            DotyError::PathResolution {
+                path: "x".into(),
+                reason: "x".into(),
+            },
+            DotyError::io("x".into(), "x", io::ErrorKind::PermissionDenied.into()),
+            DotyError::Conflicts { targets: vec![] },
+            DotyError::TargetOccupied {
+                target: "x".into(),
+                existing: "file".into(),
+            },
+            DotyError::ActionFailed {
+                target: "x".into(),
+                rolled_back: 0,
+                source: anyhow::anyhow!("x"),
+            },
+            DotyError::RollbackFailed {
+                source: anyhow::anyhow!("x"),
+            },
+            DotyError::LockfileCorrupt {
+                path: "x".into(),
+                source: anyhow::anyhow!("x"),
+            },
+            DotyError::io("x".into(), "x", io::ErrorKind::NotFound.into()),
+        ];
+        // Every code is explained, and only once
+        for error in &errors {
+            let code = error.code().unwrap();
+            assert_eq!(explain(code).unwrap().0, code);
+            assert!(error.hint().is_some());
+        }
+        assert_eq!(codes().count(), errors.len());
+
+        assert_eq!(explain("e030").unwrap().0, "DOTY-E030");
+        assert_eq!(explain(" doty-e002 ").unwrap().1, "Invalid config file");
+        assert!(explain("DOTY-E999").is_none());
+    }
+}
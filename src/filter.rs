@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// `ignore` and `include` glob lists deciding which files of a package source are managed
+///
+/// Patterns are matched against the path relative to the package source, `*` doesn't cross
+/// `/` but `**` does. A pattern without `/` matches any single path component, so
+/// `ignore ".git"` also skips everything inside `.git/`.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    ignore: Vec<String>,
+    include: Vec<String>,
+    ignore_set: Patterns,
+    include_set: Patterns,
+}
+
+/// Compiled patterns, split by whether they match a path component or the whole path
+#[derive(Debug, Clone, Default)]
+struct Patterns {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Patterns {
+    fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+            let glob = GlobBuilder::new(trimmed)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+            if trimmed.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        Ok(Self {
+            names: names.build()?,
+            paths: paths.build()?,
+        })
+    }
+}
+
+impl PathFilter {
+    /// Compile the pattern lists, an invalid glob is an error
+    pub fn new(ignore: Vec<String>, include: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            ignore_set: Patterns::new(&ignore)?,
+            include_set: Patterns::new(&include)?,
+            ignore,
+            include,
+        })
+    }
+
+    /// Combine the `defaults` filter with a package's own: ignore patterns add up, a package
+    /// `include` list replaces the default one
+    pub fn with_defaults(&self, defaults: &PathFilter) -> Result<Self> {
+        let mut ignore = defaults.ignore.clone();
+        ignore.extend(self.ignore.iter().cloned());
+        let include = if self.include.is_empty() {
+            defaults.include.clone()
+        } else {
+            self.include.clone()
+        };
+        Self::new(ignore, include)
+    }
+
+    /// Whether a file (path relative to the package source) belongs to the package
+    pub fn is_included(&self, relative: &Utf8Path) -> bool {
+        if relative
+            .components()
+            .any(|c| self.ignore_set.names.is_match(c.as_str()))
+        {
+            return false;
+        }
+        if relative
+            .ancestors()
+            .filter(|a| !a.as_str().is_empty())
+            .any(|a| self.ignore_set.paths.is_match(a.as_str()))
+        {
+            return false;
+        }
+
+        self.include.is_empty()
+            || self.include_set.paths.is_match(relative.as_str())
+            || relative
+                .file_name()
+                .is_some_and(|name| self.include_set.names.is_match(name))
+    }
+}
+
+impl PartialEq for PathFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.ignore == other.ignore && self.include == other.include
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(ignore: &[&str], include: &[&str]) -> PathFilter {
+        PathFilter::new(
+            ignore.iter().map(|s| s.to_string()).collect(),
+            include.iter().map(|s| s.to_string()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ignore() {
+        let filter = filter(&[".DS_Store", "*.swp", "docs/*.md", ".git"], &[]);
+        assert!(filter.is_included(Utf8Path::new("init.lua")));
+        assert!(filter.is_included(Utf8Path::new("README.md")));
+        assert!(!filter.is_included(Utf8Path::new(".DS_Store")));
+        assert!(!filter.is_included(Utf8Path::new("lua/.DS_Store")));
+        assert!(!filter.is_included(Utf8Path::new("lua/.init.lua.swp")));
+        assert!(!filter.is_included(Utf8Path::new("docs/usage.md")));
+        assert!(filter.is_included(Utf8Path::new("docs/deep/usage.md")));
+        assert!(!filter.is_included(Utf8Path::new(".git/config")));
+    }
+
+    #[test]
+    fn test_include() {
+        let filter = filter(&["test_*"], &["*.lua", "after/**"]);
+        assert!(filter.is_included(Utf8Path::new("init.lua")));
+        assert!(filter.is_included(Utf8Path::new("lua/plugins.lua")));
+        assert!(filter.is_included(Utf8Path::new("after/ftplugin/rust.vim")));
+        assert!(!filter.is_included(Utf8Path::new("README.md")));
+        assert!(!filter.is_included(Utf8Path::new("lua/test_plugins.lua")));
+    }
+
+    #[test]
+    fn test_with_defaults() {
+        let defaults = filter(&[".DS_Store"], &["*"]);
+        let merged = filter(&["*.md"], &[]).with_defaults(&defaults).unwrap();
+        assert_eq!(merged, filter(&[".DS_Store", "*.md"], &["*"]));
+        let merged = filter(&[], &["*.lua"]).with_defaults(&defaults).unwrap();
+        assert_eq!(merged, filter(&[".DS_Store"], &["*.lua"]));
+        assert!(PathFilter::new(vec!["[".to_string()], vec![]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::OnceLock;
+
+/// How commands report what they do (`--output`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text for people
+    #[default]
+    Text,
+    /// One JSON document when the command is done
+    Json,
+    /// One JSON object per line, written while the command runs
+    Ndjson,
+    /// Nothing but errors, the exit code tells what happened
+    Quiet,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Choose the output format, once at startup
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Whether commands print text (no `--output json`, `ndjson` or `quiet`)
+pub fn is_text() -> bool {
+    FORMAT.get().copied().unwrap_or_default() == OutputFormat::Text
+}
+
+/// `println!` that only prints in text mode, json/ndjson write what a `JsonReporter` collected
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::format::is_text() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use say;
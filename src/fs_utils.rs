@@ -1,6 +1,11 @@
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
+use ignore::{WalkBuilder, WalkState};
+use std::collections::BTreeMap;
 use std::fs;
+use std::sync::Mutex;
+
+use crate::error::DotyError;
 
 /// Filesystem type detection
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,36 +15,195 @@ pub enum FsType {
     Symlink,
 }
 
-/// Scan directory recursively and return all files
-pub fn scan_directory_recursive(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
-    let mut files = Vec::new();
+impl std::fmt::Display for FsType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsType::File => write!(f, "file"),
+            FsType::Directory => write!(f, "directory"),
+            FsType::Symlink => write!(f, "symlink"),
+        }
+    }
+}
+
+/// How `walk_files` traverses a directory tree
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Descend into symlinked directories instead of listing the link itself
+    /// Loops are detected by device and inode and reported as warnings
+    pub follow_symlinks: bool,
+    /// Only list entries up to this many levels below the root (1 = direct children)
+    pub max_depth: Option<usize>,
+    /// Directories that are not entered
+    pub skip: Vec<Utf8PathBuf>,
+}
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let entry_path = Utf8PathBuf::from_path_buf(entry.path())
-            .map_err(|_| anyhow::anyhow!("Path contains invalid UTF-8"))?;
+/// Files found by `walk_files`
+#[derive(Debug, Default)]
+pub struct WalkResult {
+    /// Files and symlinks (unless followed) below the root, sorted
+    pub files: Vec<Utf8PathBuf>,
+    /// Directories that couldn't be read and symlink loops, the walk went on without them
+    pub warnings: Vec<String>,
+}
 
-        if entry_path.is_dir() {
-            files.extend(scan_directory_recursive(&entry_path)?);
+/// List all files below a directory, walking subdirectories in parallel
+/// A root that doesn't exist has no files
+pub fn walk_files(root: &Utf8Path, options: WalkOptions) -> WalkResult {
+    if fs::symlink_metadata(root).is_err() {
+        return WalkResult::default();
+    }
+
+    let files = Mutex::new(Vec::new());
+    let warnings = Mutex::new(Vec::new());
+    let skip = options.skip;
+    WalkBuilder::new(root)
+        .standard_filters(false)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth)
+        .filter_entry(move |entry| !skip.iter().any(|dir| entry.path() == dir))
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                match entry {
+                    Ok(entry)
+                        if entry.depth() > 0 && !entry.file_type().is_some_and(|t| t.is_dir()) =>
+                    {
+                        match Utf8PathBuf::from_path_buf(entry.into_path()) {
+                            Ok(path) => files.lock().unwrap().push(path),
+                            Err(path) => warnings
+                                .lock()
+                                .unwrap()
+                                .push(format!("Path is not valid UTF-8: {}", path.display())),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => warnings.lock().unwrap().push(err.to_string()),
+                }
+                WalkState::Continue
+            })
+        });
+
+    let mut files = files.into_inner().unwrap();
+    let mut warnings = warnings.into_inner().unwrap();
+    files.sort();
+    warnings.sort();
+    WalkResult { files, warnings }
+}
+
+/// Find all symlinks below a directory without following them
+/// Directories in `skip` are not entered
+pub fn find_symlinks(dir: &Utf8Path, skip: &[Utf8PathBuf]) -> WalkResult {
+    let mut walk = walk_files(
+        dir,
+        WalkOptions {
+            skip: skip.to_vec(),
+            ..Default::default()
+        },
+    );
+    walk.files.retain(|path| path.is_symlink());
+    walk
+}
+
+/// Expand `$VAR` and `${VAR}` in a path (`$$` is a literal `$`)
+/// Lookup order: `variables` (from doty.kdl), the environment, then the built-in defaults
+/// of the XDG base directories (e.g. `$XDG_CONFIG_HOME` falls back to `$HOME/.config`)
+pub fn expand_vars(path: &Utf8Path, variables: &BTreeMap<String, String>) -> Result<Utf8PathBuf> {
+    let input = path.as_str();
+    if !input.contains('$') {
+        return Ok(path.to_path_buf());
+    }
+
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut expanded = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        let name = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("Unterminated '${{' in path: {}", input))?;
+            rest = &braced[end + 1..];
+            &braced[..end]
+        } else if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
         } else {
-            files.push(entry_path);
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            name
+        };
+
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            anyhow::bail!("Invalid variable name '{}' in path: {}", name, input);
         }
+        expanded.push_str(&lookup_var(name, variables).ok_or_else(|| {
+            DotyError::PathResolution {
+                path: path.to_path_buf(),
+                reason: format!(
+                    "Variable ${} is not set (define it in the variables block or the environment)",
+                    name
+                ),
+            }
+        })?);
     }
+    expanded.push_str(rest);
 
-    Ok(files)
+    Ok(Utf8PathBuf::from(expanded))
+}
+
+/// Value of a path variable, see expand_vars
+fn lookup_var(name: &str, variables: &BTreeMap<String, String>) -> Option<String> {
+    if let Some(value) = variables.get(name) {
+        return Some(value.clone());
+    }
+    if let Some(value) = std::env::var(name).ok().filter(|v| !v.is_empty()) {
+        return Some(value);
+    }
+
+    let home = std::env::var("HOME").ok()?;
+    let default = match name {
+        "XDG_CONFIG_HOME" => ".config",
+        "XDG_DATA_HOME" => ".local/share",
+        "XDG_STATE_HOME" => ".local/state",
+        "XDG_CACHE_HOME" => ".cache",
+        "XDG_BIN_HOME" => ".local/bin",
+        _ => return None,
+    };
+    Some(format!("{}/{}", home, default))
+}
+
+/// Get the current working directory as a UTF-8 path
+pub fn current_dir() -> Result<Utf8PathBuf> {
+    let dir = std::env::current_dir().context("Failed to get current directory")?;
+    Utf8PathBuf::from_path_buf(dir).map_err(|dir| {
+        DotyError::PathResolution {
+            path: Utf8PathBuf::from(dir.to_string_lossy().as_ref()),
+            reason: "Current directory path is not valid UTF-8".to_string(),
+        }
+        .into()
+    })
 }
 
 /// Resolve a target path (handle ~ expansion, absolute paths, and relative paths)
+/// Variables are not expanded here, package paths are expanded once when the config is loaded
 pub fn resolve_target_path(target: &Utf8Path, base_path: &Utf8Path) -> Result<Utf8PathBuf> {
     let path_str = target.as_str();
 
     // Handle ~ expansion (relative to HOME)
+    let home_dir = || {
+        std::env::var("HOME").map_err(|_| DotyError::PathResolution {
+            path: target.to_path_buf(),
+            reason: "HOME environment variable not set".to_string(),
+        })
+    };
     if let Some(stripped) = path_str.strip_prefix("~/") {
-        let home_dir = std::env::var("HOME").context("HOME environment variable not set")?;
-        return Ok(Utf8PathBuf::from(home_dir).join(stripped));
+        return Ok(Utf8PathBuf::from(home_dir()?).join(stripped));
     } else if path_str == "~" {
-        let home_dir = std::env::var("HOME").context("HOME environment variable not set")?;
-        return Ok(Utf8PathBuf::from(home_dir));
+        return Ok(Utf8PathBuf::from(home_dir()?));
     }
 
     // Handle absolute paths
@@ -51,6 +215,110 @@ pub fn resolve_target_path(target: &Utf8Path, base_path: &Utf8Path) -> Result<Ut
     Ok(base_path.join(target))
 }
 
+/// Contract a path under HOME back to `~/...` (inverse of resolve_target_path)
+/// Used when writing paths into doty.kdl so configs stay portable between machines
+pub fn contract_home_path(path: &Utf8Path) -> Utf8PathBuf {
+    if let Ok(home_dir) = std::env::var("HOME") {
+        if let Ok(stripped) = path.strip_prefix(&home_dir) {
+            if stripped.as_str().is_empty() {
+                return Utf8PathBuf::from("~");
+            }
+            return Utf8PathBuf::from("~").join(stripped);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Move a file or directory to a new location
+/// Falls back to copy + delete when a plain rename is not possible (e.g. across filesystems)
+pub fn move_path(from: &Utf8Path, to: &Utf8Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            DotyError::io(
+                parent,
+                format!("Failed to create directory: {}", parent),
+                err,
+            )
+        })?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_recursive(from, to).with_context(|| format!("Failed to copy {} to {}", from, to))?;
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::remove_dir_all(from)?;
+    } else {
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Remove a symlink itself (directory symlinks on Windows need remove_dir)
+pub fn remove_symlink(path: &Utf8Path) -> Result<()> {
+    fs::remove_file(path)
+        .or_else(|_| fs::remove_dir(path))
+        .map_err(|err| {
+            DotyError::io(path, format!("Failed to remove symlink: {}", path), err).into()
+        })
+}
+
+/// Content hash of a file, as stored in the lockfile for copied files (`sha256:<hex>`)
+pub fn hash_file(path: &Utf8Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {}", path))?;
+    Ok(format_hash(hasher.finalize().as_slice()))
+}
+
+/// Content hash of in-memory content (e.g. a rendered template), same format as hash_file
+pub fn hash_bytes(content: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    format_hash(Sha256::digest(content).as_slice())
+}
+
+fn format_hash(digest: &[u8]) -> String {
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
+
+/// Copy a file, symlink or directory tree (symlinks are recreated, not followed)
+fn copy_recursive(from: &Utf8Path, to: &Utf8Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+
+    if metadata.is_symlink() {
+        let link_target = fs::read_link(from)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&link_target, to)?;
+        #[cfg(windows)]
+        if from.is_dir() {
+            std::os::windows::fs::symlink_dir(&link_target, to)?;
+        } else {
+            std::os::windows::fs::symlink_file(&link_target, to)?;
+        }
+    } else if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let entry_path = Utf8PathBuf::from_path_buf(entry.path())
+                .map_err(|_| anyhow::anyhow!("Path contains invalid UTF-8"))?;
+            let file_name = entry_path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Path has no file name: {}", entry_path))?;
+            copy_recursive(&entry_path, &to.join(file_name))?;
+        }
+        fs::set_permissions(to, metadata.permissions())?;
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
 /// Get filesystem type for a given path
 pub fn get_fs_type(path: &Utf8Path) -> Result<Option<FsType>> {
     if let Ok(metadata) = fs::symlink_metadata(path) {
@@ -69,15 +337,32 @@ pub fn get_fs_type(path: &Utf8Path) -> Result<Option<FsType>> {
 /// Read where a symlink points to (canonical path)
 /// Returns None if not a symlink or broken
 pub fn read_symlink_target(path: &Utf8Path) -> Result<Option<Utf8PathBuf>> {
-    if let Ok(target) = fs::read_link(path) {
-        if let Ok(canonical) = target.canonicalize() {
-            Ok(Some(Utf8PathBuf::from_path_buf(canonical).unwrap_or_default()))
-        } else {
-            Ok(None) // Broken symlink
-        }
-    } else {
-        Ok(None) // Not a symlink
-    }
+    // Relative links (as stow makes them) resolve against the link's directory
+    Ok(resolve_symlink(path).filter(|target| target.exists()))
+}
+
+/// Absolute path a symlink points to, relative to the link's directory for relative links
+/// Canonical when the destination exists, None if not a symlink
+pub fn resolve_symlink(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let target = Utf8PathBuf::from_path_buf(fs::read_link(path).ok()?).ok()?;
+    let absolute = match path.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target,
+    };
+    Some(absolute.canonicalize_utf8().unwrap_or(absolute))
+}
+
+/// Unified diff between two file contents, `None` when either side is not UTF-8 text
+pub fn unified_diff(old_name: &str, old: &[u8], new_name: &str, new: &[u8]) -> Option<String> {
+    let old = std::str::from_utf8(old).ok()?;
+    let new = std::str::from_utf8(new).ok()?;
+    Some(
+        similar::TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(old_name, new_name)
+            .to_string(),
+    )
 }
 
 /// Check if path is a symlink that points nowhere
@@ -85,12 +370,8 @@ pub fn is_broken_symlink(path: &Utf8Path) -> Result<bool> {
     if let Ok(metadata) = fs::symlink_metadata(path) {
         if metadata.is_symlink() {
             // It's a symlink, check if it's broken
-            if let Ok(target) = fs::read_link(path) {
-                // Try to canonicalize - if it fails, symlink is broken
-                Ok(target.canonicalize().is_err())
-            } else {
-                Ok(true) // Can't read link target, assume broken
-            }
+            // Following the link fails if it is broken
+            Ok(fs::metadata(path).is_err())
         } else {
             Ok(false) // Not a symlink
         }
@@ -110,7 +391,7 @@ mod tests {
     }
 
     #[test]
-    fn test_scan_directory_recursive() {
+    fn test_walk_files() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
 
@@ -120,12 +401,79 @@ mod tests {
         fs::write(temp_path.join("level1/file2.txt"), "content2").unwrap();
         fs::write(temp_path.join("level1/level2/file3.txt"), "content3").unwrap();
 
-        let files = scan_directory_recursive(&temp_path).unwrap();
+        let walk = walk_files(&temp_path, WalkOptions::default());
+        assert_eq!(
+            walk.files,
+            vec![
+                temp_path.join("file1.txt"),
+                temp_path.join("level1/file2.txt"),
+                temp_path.join("level1/level2/file3.txt"),
+            ]
+        );
+        assert!(walk.warnings.is_empty());
+
+        let walk = walk_files(
+            &temp_path,
+            WalkOptions {
+                max_depth: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(walk.files.len(), 2);
+        assert!(
+            walk_files(&temp_path.join("missing"), WalkOptions::default())
+                .files
+                .is_empty()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_walk_files_symlink_loop() {
+        let temp_dir = setup_test_dir();
+        let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(temp_path.join("a")).unwrap();
+        fs::write(temp_path.join("a/file.txt"), "content").unwrap();
+        std::os::unix::fs::symlink(&temp_path, temp_path.join("a/loop")).unwrap();
+
+        // Not followed, the link is listed like a file
+        let walk = walk_files(&temp_path, WalkOptions::default());
+        assert_eq!(
+            walk.files,
+            vec![temp_path.join("a/file.txt"), temp_path.join("a/loop")]
+        );
+
+        let walk = walk_files(
+            &temp_path,
+            WalkOptions {
+                follow_symlinks: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(walk.files, vec![temp_path.join("a/file.txt")]);
+        assert_eq!(walk.warnings.len(), 1, "{:?}", walk.warnings);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_walk_files_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
 
-        assert_eq!(files.len(), 3);
-        assert!(files.iter().any(|f| f.ends_with("file1.txt")));
-        assert!(files.iter().any(|f| f.ends_with("file2.txt")));
-        assert!(files.iter().any(|f| f.ends_with("file3.txt")));
+        let temp_dir = setup_test_dir();
+        let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(temp_path.join("locked")).unwrap();
+        fs::write(temp_path.join("locked/secret.txt"), "secret").unwrap();
+        fs::write(temp_path.join("open.txt"), "open").unwrap();
+        fs::set_permissions(temp_path.join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        let walk = walk_files(&temp_path, WalkOptions::default());
+        fs::set_permissions(temp_path.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Root can read anything, so there's only a warning when running as a regular user
+        if walk.files.len() == 1 {
+            assert_eq!(walk.files, vec![temp_path.join("open.txt")]);
+            assert_eq!(walk.warnings.len(), 1);
+        }
     }
 
     #[test]
@@ -133,11 +481,11 @@ mod tests {
         // Test ~ expansion
         let target = Utf8PathBuf::from("~/test/file.txt");
         let base_path = Utf8PathBuf::from("/some/base");
-        
+
         // We can't easily test HOME expansion without mocking env vars,
         // but we can test that it doesn't panic
         let result = resolve_target_path(&target, &base_path);
-        
+
         // Should succeed if HOME is set
         if std::env::var("HOME").is_ok() {
             assert!(result.is_ok());
@@ -150,7 +498,7 @@ mod tests {
     fn test_resolve_target_path_absolute() {
         let target = Utf8PathBuf::from("/absolute/path/file.txt");
         let base_path = Utf8PathBuf::from("/some/base");
-        
+
         let resolved = resolve_target_path(&target, &base_path).unwrap();
         assert_eq!(resolved, Utf8PathBuf::from("/absolute/path/file.txt"));
     }
@@ -159,19 +507,170 @@ mod tests {
     fn test_resolve_target_path_relative() {
         let target = Utf8PathBuf::from("relative/path/file.txt");
         let base_path = Utf8PathBuf::from("/some/base");
-        
+
         let resolved = resolve_target_path(&target, &base_path).unwrap();
-        assert_eq!(resolved, Utf8PathBuf::from("/some/base/relative/path/file.txt"));
+        assert_eq!(
+            resolved,
+            Utf8PathBuf::from("/some/base/relative/path/file.txt")
+        );
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let home = std::env::var("HOME").unwrap();
+        let variables = BTreeMap::from([("dots".to_string(), "/srv/dots".to_string())]);
+        let expand = |path: &str| expand_vars(Utf8Path::new(path), &variables);
+
+        assert_eq!(expand("$dots/nvim").unwrap(), "/srv/dots/nvim");
+        assert_eq!(expand("${dots}-extra/x").unwrap(), "/srv/dots-extra/x");
+        assert_eq!(expand("$HOME/.zshrc").unwrap(), format!("{}/.zshrc", home));
+        assert_eq!(expand("cost$$/file").unwrap(), "cost$/file");
+        assert_eq!(expand("~/plain").unwrap(), "~/plain");
+
+        // XDG variables fall back to their spec defaults when not set
+        if std::env::var("XDG_STATE_HOME").is_err() {
+            assert_eq!(
+                expand("$XDG_STATE_HOME/app").unwrap(),
+                format!("{}/.local/state/app", home)
+            );
+        }
+
+        let err = expand("$DOTY_TEST_UNSET_VARIABLE/x").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("$DOTY_TEST_UNSET_VARIABLE is not set"));
+        assert!(expand("${dots/x").is_err());
+        assert!(expand("${}/x").is_err());
+    }
+
+    #[test]
+    fn test_resolve_target_path_keeps_dollar() {
+        // Paths are already expanded, a `$` left in them is part of the file name
+        let base_path = Utf8PathBuf::from("/some/base");
+        let resolved = resolve_target_path(Utf8Path::new("cost$/${app}"), &base_path).unwrap();
+        assert_eq!(resolved, Utf8PathBuf::from("/some/base/cost$/${app}"));
+    }
+
+    #[test]
+    fn test_contract_home_path() {
+        if let Ok(home_dir) = std::env::var("HOME") {
+            let inside = Utf8PathBuf::from(&home_dir).join(".config/alacritty");
+            assert_eq!(
+                contract_home_path(&inside),
+                Utf8PathBuf::from("~/.config/alacritty")
+            );
+            assert_eq!(
+                contract_home_path(&Utf8PathBuf::from(&home_dir)),
+                Utf8PathBuf::from("~")
+            );
+        }
+
+        let outside = Utf8PathBuf::from("/nonexistent-root/file.txt");
+        assert_eq!(contract_home_path(&outside), outside);
+    }
+
+    #[test]
+    fn test_move_path_directory() {
+        let temp_dir = setup_test_dir();
+        let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+
+        let from = temp_path.join("target/app");
+        fs::create_dir_all(from.join("nested")).unwrap();
+        fs::write(from.join("config.toml"), "a = 1").unwrap();
+        fs::write(from.join("nested/theme.toml"), "b = 2").unwrap();
+
+        let to = temp_path.join("repo/app");
+        move_path(&from, &to).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(to.join("config.toml")).unwrap(), "a = 1");
+        assert_eq!(
+            fs::read_to_string(to.join("nested/theme.toml")).unwrap(),
+            "b = 2"
+        );
+    }
+
+    #[test]
+    fn test_copy_recursive_keeps_symlinks() {
+        let temp_dir = setup_test_dir();
+        let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+
+        let from = temp_path.join("from");
+        fs::create_dir_all(&from).unwrap();
+        fs::write(from.join("file.txt"), "content").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("file.txt", from.join("link.txt")).unwrap();
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file("file.txt", from.join("link.txt")).unwrap();
+
+        let to = temp_path.join("to");
+        copy_recursive(&from, &to).unwrap();
+
+        assert_eq!(
+            get_fs_type(&to.join("file.txt")).unwrap(),
+            Some(FsType::File)
+        );
+        assert_eq!(
+            get_fs_type(&to.join("link.txt")).unwrap(),
+            Some(FsType::Symlink)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_symlinks() {
+        let temp_dir = setup_test_dir();
+        let root = Utf8Path::from_path(temp_dir.path()).unwrap();
+        fs::create_dir_all(root.join("repo/nvim")).unwrap();
+        fs::create_dir_all(root.join("home/.config")).unwrap();
+        std::os::unix::fs::symlink("../../repo/nvim", root.join("home/.config/nvim")).unwrap();
+        std::os::unix::fs::symlink(root.join("home"), root.join("repo/home")).unwrap();
+
+        let links = find_symlinks(root, &[root.join("repo")]).files;
+        assert_eq!(links, vec![root.join("home/.config/nvim")]);
+        let repo = root.join("repo/nvim").canonicalize_utf8().unwrap();
+        assert_eq!(resolve_symlink(&links[0]), Some(repo));
+        assert_eq!(resolve_symlink(&root.join("home")), None);
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff("a/config", b"one\ntwo\n", "b/config", b"one\n2\n").unwrap();
+        assert_eq!(
+            diff,
+            "--- a/config\n+++ b/config\n@@ -1,2 +1,2 @@\n one\n-two\n+2\n"
+        );
+        assert!(unified_diff("a", b"text", "b", &[0xff, 0xfe]).is_none());
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let temp_dir = setup_test_dir();
+        let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+
+        let file = temp_path.join("hello.txt");
+        fs::write(&file, "hello").unwrap();
+        assert_eq!(
+            hash_file(&file).unwrap(),
+            "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(hash_bytes(b"hello"), hash_file(&file).unwrap());
+
+        fs::write(&file, "hello!").unwrap();
+        assert_ne!(
+            hash_file(&file).unwrap(),
+            "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
     }
 
     #[test]
     fn test_get_fs_type_file() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         let file_path = temp_path.join("test.txt");
         fs::write(&file_path, "content").unwrap();
-        
+
         let fs_type = get_fs_type(&file_path).unwrap();
         assert_eq!(fs_type, Some(FsType::File));
     }
@@ -180,7 +679,7 @@ mod tests {
     fn test_get_fs_type_directory() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         let fs_type = get_fs_type(&temp_path).unwrap();
         assert_eq!(fs_type, Some(FsType::Directory));
     }
@@ -188,7 +687,7 @@ mod tests {
     #[test]
     fn test_get_fs_type_nonexistent() {
         let nonexistent = Utf8PathBuf::from("/nonexistent/path");
-        
+
         let fs_type = get_fs_type(&nonexistent).unwrap();
         assert_eq!(fs_type, None);
     }
@@ -197,18 +696,18 @@ mod tests {
     fn test_get_fs_type_symlink() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create source file
         let source_path = temp_path.join("source.txt");
         fs::write(&source_path, "content").unwrap();
-        
+
         // Create symlink
         let link_path = temp_path.join("link.txt");
         #[cfg(unix)]
         std::os::unix::fs::symlink(&source_path, &link_path).unwrap();
         #[cfg(windows)]
         std::os::windows::fs::symlink_file(&source_path, &link_path).unwrap();
-        
+
         let fs_type = get_fs_type(&link_path).unwrap();
         assert_eq!(fs_type, Some(FsType::Symlink));
     }
@@ -217,18 +716,18 @@ mod tests {
     fn test_read_symlink_target_valid() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create source file
         let source_path = temp_path.join("source.txt");
         fs::write(&source_path, "content").unwrap();
-        
+
         // Create symlink
         let link_path = temp_path.join("link.txt");
         #[cfg(unix)]
         std::os::unix::fs::symlink(&source_path, &link_path).unwrap();
         #[cfg(windows)]
         std::os::windows::fs::symlink_file(&source_path, &link_path).unwrap();
-        
+
         let target = read_symlink_target(&link_path).unwrap();
         assert!(target.is_some());
         assert_eq!(target.unwrap(), source_path.canonicalize().unwrap());
@@ -238,7 +737,7 @@ mod tests {
     fn test_read_symlink_target_broken() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create symlink to non-existent file
         let nonexistent_source = temp_path.join("nonexistent.txt");
         let link_path = temp_path.join("broken_link.txt");
@@ -246,7 +745,7 @@ mod tests {
         std::os::unix::fs::symlink(&nonexistent_source, &link_path).unwrap();
         #[cfg(windows)]
         std::os::windows::fs::symlink_file(&nonexistent_source, &link_path).unwrap();
-        
+
         let target = read_symlink_target(&link_path).unwrap();
         assert_eq!(target, None);
     }
@@ -255,11 +754,11 @@ mod tests {
     fn test_read_symlink_target_not_symlink() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create regular file
         let file_path = temp_path.join("regular.txt");
         fs::write(&file_path, "content").unwrap();
-        
+
         let target = read_symlink_target(&file_path).unwrap();
         assert_eq!(target, None);
     }
@@ -268,18 +767,18 @@ mod tests {
     fn test_is_broken_symlink_valid() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create source file
         let source_path = temp_path.join("source.txt");
         fs::write(&source_path, "content").unwrap();
-        
+
         // Create symlink
         let link_path = temp_path.join("link.txt");
         #[cfg(unix)]
         std::os::unix::fs::symlink(&source_path, &link_path).unwrap();
         #[cfg(windows)]
         std::os::windows::fs::symlink_file(&source_path, &link_path).unwrap();
-        
+
         let is_broken = is_broken_symlink(&link_path).unwrap();
         assert!(!is_broken);
     }
@@ -288,7 +787,7 @@ mod tests {
     fn test_is_broken_symlink_broken() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create symlink to non-existent file
         let nonexistent_source = temp_path.join("nonexistent.txt");
         let link_path = temp_path.join("broken_link.txt");
@@ -296,7 +795,7 @@ mod tests {
         std::os::unix::fs::symlink(&nonexistent_source, &link_path).unwrap();
         #[cfg(windows)]
         std::os::windows::fs::symlink_file(&nonexistent_source, &link_path).unwrap();
-        
+
         let is_broken = is_broken_symlink(&link_path).unwrap();
         assert!(is_broken);
     }
@@ -305,11 +804,11 @@ mod tests {
     fn test_is_broken_symlink_not_symlink() {
         let temp_dir = setup_test_dir();
         let temp_path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
-        
+
         // Create regular file
         let file_path = temp_path.join("regular.txt");
         fs::write(&file_path, "content").unwrap();
-        
+
         let is_broken = is_broken_symlink(&file_path).unwrap();
         assert!(!is_broken);
     }
@@ -317,8 +816,8 @@ mod tests {
     #[test]
     fn test_is_broken_symlink_nonexistent() {
         let nonexistent = Utf8PathBuf::from("/nonexistent/path");
-        
+
         let is_broken = is_broken_symlink(&nonexistent).unwrap();
         assert!(!is_broken);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+use std::fs;
+use std::io::Write;
+
+use crate::backup::BackupStore;
+use crate::fs_utils::{get_fs_type, move_path, remove_symlink, FsType};
+
+/// A filesystem change made while applying links, with enough detail to undo it
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalStep {
+    /// A directory was created for a link target
+    DirCreated { path: Utf8PathBuf },
+    /// A symlink was created
+    LinkCreated { target: Utf8PathBuf },
+    /// A symlink was removed (`source` is what it pointed to, as stored in the link)
+    LinkRemoved {
+        target: Utf8PathBuf,
+        source: Utf8PathBuf,
+    },
+    /// An original was moved into the backup store
+    BackedUp { path: Utf8PathBuf, id: String },
+    /// A file or directory was moved (e.g. adopted into the repo)
+    Moved { from: Utf8PathBuf, to: Utf8PathBuf },
+    /// A copy was written (Copy and Template strategies), a previous copy was backed up first,
+    /// so undoing removes it and the `BackedUp` step before it restores the old content
+    FileWritten { path: Utf8PathBuf },
+}
+
+/// Write-ahead record of a running `doty link`, stored at `.doty/state/<hostname>.journal`
+/// Every step is appended as soon as it is done, so an interrupted run can be rolled back later
+#[derive(Debug)]
+pub struct Journal {
+    path: Utf8PathBuf,
+    pub steps: Vec<JournalStep>,
+}
+
+impl Journal {
+    /// Location of the journal for a host
+    pub fn path_for(state_dir: &Utf8Path, hostname: &str) -> Utf8PathBuf {
+        state_dir.join(format!("{}.journal", hostname))
+    }
+
+    /// Load the journal a previous run left behind, if any
+    pub fn load(state_dir: &Utf8Path, hostname: &str) -> Result<Option<Self>> {
+        let path = Self::path_for(state_dir, hostname);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read journal: {}", path))?;
+        let doc: KdlDocument = content.parse().context("Failed to parse KDL journal")?;
+        let steps = doc
+            .nodes()
+            .iter()
+            .map(Self::parse_step)
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Invalid journal: {}", path))?;
+
+        Ok(Some(Self { path, steps }))
+    }
+
+    /// Start an empty journal for a new run
+    pub fn create(state_dir: &Utf8Path, hostname: &str) -> Result<Self> {
+        fs::create_dir_all(state_dir)
+            .with_context(|| format!("Failed to create state directory: {}", state_dir))?;
+        let journal = Self {
+            path: Self::path_for(state_dir, hostname),
+            steps: Vec::new(),
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    fn parse_step(node: &KdlNode) -> Result<JournalStep> {
+        let prop = |name: &str| {
+            node.get(name)
+                .and_then(|v| v.as_string())
+                .map(Utf8PathBuf::from)
+                .with_context(|| format!("Missing '{}' in {} step", name, node.name().value()))
+        };
+
+        Ok(match node.name().value() {
+            "dirCreated" => JournalStep::DirCreated {
+                path: prop("path")?,
+            },
+            "linkCreated" => JournalStep::LinkCreated {
+                target: prop("target")?,
+            },
+            "linkRemoved" => JournalStep::LinkRemoved {
+                target: prop("target")?,
+                source: prop("source")?,
+            },
+            "backedUp" => JournalStep::BackedUp {
+                path: prop("path")?,
+                id: prop("id")?.into_string(),
+            },
+            "moved" => JournalStep::Moved {
+                from: prop("from")?,
+                to: prop("to")?,
+            },
+            "fileWritten" => JournalStep::FileWritten {
+                path: prop("path")?,
+            },
+            other => anyhow::bail!("Unknown journal step: {}", other),
+        })
+    }
+
+    fn step_to_node(step: &JournalStep) -> KdlNode {
+        let (name, props): (&str, Vec<(&str, &str)>) = match step {
+            JournalStep::DirCreated { path } => ("dirCreated", vec![("path", path.as_str())]),
+            JournalStep::LinkCreated { target } => {
+                ("linkCreated", vec![("target", target.as_str())])
+            }
+            JournalStep::LinkRemoved { target, source } => (
+                "linkRemoved",
+                vec![("target", target.as_str()), ("source", source.as_str())],
+            ),
+            JournalStep::BackedUp { path, id } => (
+                "backedUp",
+                vec![("path", path.as_str()), ("id", id.as_str())],
+            ),
+            JournalStep::Moved { from, to } => {
+                ("moved", vec![("from", from.as_str()), ("to", to.as_str())])
+            }
+            JournalStep::FileWritten { path } => ("fileWritten", vec![("path", path.as_str())]),
+        };
+
+        let mut node = KdlNode::new(name);
+        for (key, value) in props {
+            node.push(KdlEntry::new_prop(key, value));
+        }
+        node
+    }
+
+    /// Rewrite the whole journal file
+    fn save(&self) -> Result<()> {
+        let mut doc = KdlDocument::new();
+        for step in &self.steps {
+            doc.nodes_mut().push(Self::step_to_node(step));
+        }
+        fs::write(&self.path, doc.to_string())
+            .with_context(|| format!("Failed to write journal: {}", self.path))
+    }
+
+    /// Append a completed step and flush it to disk right away
+    pub fn record(&mut self, step: JournalStep) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal: {}", self.path))?;
+        writeln!(file, "{}", Self::step_to_node(&step))?;
+        file.sync_data()?;
+
+        self.steps.push(step);
+        Ok(())
+    }
+
+    /// Undo all recorded steps, newest first
+    /// The journal shrinks as steps are undone, so a failed rollback can be resumed
+    pub fn rollback(&mut self, backup_store: &mut BackupStore) -> Result<usize> {
+        let mut undone = 0;
+        while let Some(step) = self.steps.last().cloned() {
+            Self::undo(&step, backup_store)
+                .with_context(|| format!("Failed to roll back {:?}", step))?;
+            self.steps.pop();
+            self.save()?;
+            undone += 1;
+        }
+        Ok(undone)
+    }
+
+    fn undo(step: &JournalStep, backup_store: &mut BackupStore) -> Result<()> {
+        match step {
+            JournalStep::DirCreated { path } => {
+                // Only remove it if nothing else ended up inside
+                let _ = fs::remove_dir(path);
+            }
+            JournalStep::LinkCreated { target } => {
+                if get_fs_type(target)? == Some(FsType::Symlink) {
+                    remove_symlink(target)?;
+                }
+            }
+            JournalStep::LinkRemoved { target, source } => {
+                if get_fs_type(target)?.is_none() {
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(source, target)?;
+                    #[cfg(windows)]
+                    if source.is_dir() {
+                        std::os::windows::fs::symlink_dir(source, target)?;
+                    } else {
+                        std::os::windows::fs::symlink_file(source, target)?;
+                    }
+                }
+            }
+            JournalStep::BackedUp { id, .. } => {
+                backup_store.restore(id)?;
+            }
+            JournalStep::Moved { from, to } => move_path(to, from)?,
+            JournalStep::FileWritten { path } => {
+                if get_fs_type(path)? == Some(FsType::File) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The run completed (or was rolled back), the journal is no longer needed
+    pub fn finish(self) -> Result<()> {
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove journal: {}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, Utf8PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        (temp_dir, root)
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let (_temp, root) = setup();
+        let state_dir = root.join(".doty/state");
+
+        let mut journal = Journal::create(&state_dir, "test-host").unwrap();
+        let steps = vec![
+            JournalStep::DirCreated {
+                path: root.join("target"),
+            },
+            JournalStep::BackedUp {
+                path: root.join("target/nvim"),
+                id: "20251122-143015-1".to_string(),
+            },
+            JournalStep::LinkCreated {
+                target: root.join("target/nvim"),
+            },
+            JournalStep::LinkRemoved {
+                target: root.join("target/zshrc"),
+                source: root.join("zsh/.zshrc"),
+            },
+            JournalStep::Moved {
+                from: root.join("target/tmux"),
+                to: root.join("tmux"),
+            },
+            JournalStep::FileWritten {
+                path: root.join("target/settings.json"),
+            },
+        ];
+        for step in &steps {
+            journal.record(step.clone()).unwrap();
+        }
+
+        let loaded = Journal::load(&state_dir, "test-host").unwrap().unwrap();
+        assert_eq!(loaded.steps, steps);
+
+        loaded.finish().unwrap();
+        assert!(Journal::load(&state_dir, "test-host").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rollback_restores_previous_state() {
+        let (_temp, root) = setup();
+        let state_dir = root.join(".doty/state");
+        let mut store = BackupStore::load(&root.join(".doty/backups"), "test-host").unwrap();
+        let mut journal = Journal::create(&state_dir, "test-host").unwrap();
+
+        // An existing config gets backed up and replaced by a link
+        fs::create_dir_all(root.join("repo/nvim")).unwrap();
+        let target = root.join("home/nvim");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("init.lua"), "-- local").unwrap();
+
+        let entry = store.back_up(&target).unwrap();
+        journal
+            .record(JournalStep::BackedUp {
+                path: target.clone(),
+                id: entry.id,
+            })
+            .unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("repo/nvim"), &target).unwrap();
+        journal
+            .record(JournalStep::LinkCreated {
+                target: target.clone(),
+            })
+            .unwrap();
+
+        assert_eq!(journal.rollback(&mut store).unwrap(), 2);
+        assert_eq!(get_fs_type(&target).unwrap(), Some(FsType::Directory));
+        assert_eq!(
+            fs::read_to_string(target.join("init.lua")).unwrap(),
+            "-- local"
+        );
+        assert!(store.entries.is_empty());
+        assert!(Journal::load(&state_dir, "test-host")
+            .unwrap()
+            .unwrap()
+            .steps
+            .is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! Doty, a hybrid dotfiles manager with flexible linking strategies
+//!
+//! The `doty` binary is built on this crate, tools that want to link, check or scan a dotfiles
+//! repo without going through the CLI can use the same API:
+//!
+//! ```no_run
+//! use camino::Utf8Path;
+//! use std::rc::Rc;
+//! use doty::reporter::{Reporter, TextReporter};
+//! use doty::{ApplyOptions, Repo};
+//!
+//! # fn main() -> doty::Result<()> {
+//! let reporter: Rc<dyn Reporter> = Rc::new(TextReporter::default());
+//!
+//! // Load doty.kdl, the base path is resolved according to `pathResolution`
+//! let repo = Repo::open(Utf8Path::new("dotfiles/doty.kdl"), &reporter)?;
+//!
+//! // Compute the plan and apply it, the reporter sees every action
+//! let options = ApplyOptions { dry_run: true, ..Default::default() };
+//! let deployment = repo.deployment(options)?;
+//! let actions = deployment.plan(&repo.config)?;
+//! let applied = deployment.apply(actions, "link")?;
+//! println!("{} links would be created", applied.counts.created);
+//!
+//! // Targets that differ from what doty manages
+//! for item in repo.scan()? {
+//!     println!("{:?} {}", item.drift_type, item.target_path);
+//! }
+//!
+//! // Health of every link, like `doty status`
+//! let status = repo.status()?;
+//! println!("{} links need attention", status.unhealthy().count());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `Repo` and `Deployment` return a [`DotyError`] with a stable code and a suggested fix, the
+//! modules underneath use `anyhow`.
+
+pub mod backup;
+pub mod condition;
+pub mod config;
+pub mod dotyignore;
+pub mod error;
+pub mod filter;
+pub mod fs_utils;
+pub mod journal;
+pub mod linker;
+pub mod lockfile;
+pub mod output;
+mod repo;
+pub mod reporter;
+pub mod scanner;
+pub mod template;
+
+pub use config::DotyConfig;
+pub use error::{DotyError, Result};
+pub use linker::{LinkAction, Linker};
+pub use lockfile::Lockfile;
+pub use repo::{
+    Applied, ApplyOptions, Deployment, Drift, Generations, LinkState, PackageStatus, Repo,
+    Restored, RollbackPlan, Status,
+};
+pub use scanner::{DriftItem, Scanner};
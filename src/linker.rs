@@ -1,13 +1,22 @@
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::rc::Rc;
 
-use crate::config::{DotyConfig, LinkStrategy, Package, PathResolution};
+use crate::backup::BackupStore;
+use crate::condition::HostInfo;
+use crate::config::{DotyConfig, LinkStrategy, OnConflict, Package, PathResolution};
+use crate::dotyignore::DOTYIGNORE_FILE;
+use crate::error::DotyError;
 use crate::fs_utils::{
-    get_fs_type, read_symlink_target, resolve_target_path, scan_directory_recursive, FsType,
+    get_fs_type, hash_file, move_path, read_symlink_target, remove_symlink, resolve_target_path,
+    walk_files, FsType, WalkOptions,
 };
+use crate::journal::{Journal, JournalStep};
 use crate::lockfile::Lockfile;
+use crate::reporter::{Event, PlannedAction, QuietReporter, Reporter};
+use crate::template::TemplateContext;
 
 /// Represents the result of a linking operation
 #[derive(Debug, Clone, PartialEq)]
@@ -44,11 +53,87 @@ pub enum LinkAction {
         source: Utf8PathBuf,
         message: String,
     },
+    /// Something Doty doesn't manage exists at the target, resolved by the package's conflict policy
+    /// `hash` is set when the target is a copy (Copy and Template strategies): the content hash it ends up with
+    Conflict {
+        target: Utf8PathBuf,
+        source: Utf8PathBuf,
+        existing: FsType,
+        policy: OnConflict,
+        hash: Option<String>,
+        template: bool,
+    },
+    /// A copy of the source was written or refreshed (Copy strategy),
+    /// or the rendered source when `template` is set (Template strategy)
+    Copied {
+        target: Utf8PathBuf,
+        source: Utf8PathBuf,
+        hash: String,
+        template: bool,
+    },
+    /// An unmodified copy was deleted (Copy strategy)
+    Deleted {
+        target: Utf8PathBuf,
+        source: Utf8PathBuf,
+    },
+}
+
+impl LinkAction {
+    /// Target path the action applies to
+    pub fn target(&self) -> &Utf8PathBuf {
+        match self {
+            LinkAction::Created { target, .. }
+            | LinkAction::Removed { target, .. }
+            | LinkAction::Updated { target, .. }
+            | LinkAction::Skipped { target, .. }
+            | LinkAction::Warning { target, .. }
+            | LinkAction::Pruned { target, .. }
+            | LinkAction::Conflict { target, .. }
+            | LinkAction::Copied { target, .. }
+            | LinkAction::Deleted { target, .. } => target,
+        }
+    }
+}
+
+/// Health of a single link, derived from config, lockfile and filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LinkHealth {
+    /// Symlink exists and points to the configured source
+    InSync,
+    /// Symlink is missing and would be created by `doty link`
+    PendingCreate,
+    /// Source changed in config, symlink would be updated by `doty link`
+    PendingUpdate,
+    /// Something that is not our symlink sits at the target
+    Conflicting,
+    /// Source is gone or the symlink points nowhere
+    Broken,
+    /// Link is in the lockfile but no longer in the config
+    Orphaned,
+    /// Copy was edited locally since doty wrote it
+    Modified,
+}
+
+impl std::fmt::Display for LinkHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkHealth::InSync => write!(f, "in sync"),
+            LinkHealth::PendingCreate => write!(f, "pending create"),
+            LinkHealth::PendingUpdate => write!(f, "pending update"),
+            LinkHealth::Conflicting => write!(f, "conflicting"),
+            LinkHealth::Broken => write!(f, "broken"),
+            LinkHealth::Orphaned => write!(f, "orphaned"),
+            LinkHealth::Modified => write!(f, "modified locally"),
+        }
+    }
 }
 
+/// Everything Doty knows about a single link target
 #[derive(Debug, Clone)]
-struct LinkStatus {
+pub struct LinkStatus {
     // Config (Desired state)
+    config_package: Option<Package>,
+    config_on_conflict: OnConflict,
     config_resolved_source: Option<Utf8PathBuf>,
     config_resolved_target: Option<Utf8PathBuf>,
     config_is_explicit: bool,
@@ -56,50 +141,66 @@ struct LinkStatus {
     // State (Stored cache)
     state_resolved_source: Option<Utf8PathBuf>,
     state_resolved_target: Option<Utf8PathBuf>,
+    state_hash: Option<String>, //only for copies
 
     // Filesystem (Reality)
     source_exists: bool,         //checked via config_resolved_source
     target_exists: bool,         //checked via target_points_to
     target_type: Option<FsType>, //checked via target_points_to
     target_points_to: Option<Utf8PathBuf>,
+    source_hash: Option<String>, //only for copies
+    target_hash: Option<String>, //only for copies whose target is a file
 }
 
 impl LinkStatus {
     fn from_config(
+        package: &Package,
         target: Utf8PathBuf,
         source: Utf8PathBuf,
         is_explicit: bool,
         source_exists: bool,
     ) -> Self {
         Self {
+            config_package: Some(package.clone()),
+            config_on_conflict: OnConflict::default(),
             config_resolved_source: Some(source),
             config_resolved_target: Some(target),
             config_is_explicit: is_explicit,
             state_resolved_source: None,
             state_resolved_target: None,
+            state_hash: None,
             source_exists,
             target_exists: false,
             target_type: None,
             target_points_to: None,
+            source_hash: None,
+            target_hash: None,
         }
     }
 
-    fn from_lockfile(target: Utf8PathBuf, source: Utf8PathBuf) -> Self {
+    fn from_lockfile(target: Utf8PathBuf, source: Utf8PathBuf, hash: Option<String>) -> Self {
         Self {
+            config_package: None,
+            config_on_conflict: OnConflict::default(),
             config_resolved_source: None,
             config_resolved_target: None,
             config_is_explicit: false,
             state_resolved_source: Some(source),
             state_resolved_target: Some(target),
+            state_hash: hash,
             source_exists: false,
             target_exists: false,
             target_type: None,
             target_points_to: None,
+            source_hash: None,
+            target_hash: None,
         }
     }
 
     fn merge(&mut self, other: LinkStatus) {
         if other.config_resolved_source.is_some() {
+            self.config_package = other.config_package;
+            self.config_on_conflict = other.config_on_conflict;
             self.config_resolved_source = other.config_resolved_source;
             self.config_resolved_target = other.config_resolved_target;
             self.config_is_explicit = other.config_is_explicit;
@@ -108,8 +209,46 @@ impl LinkStatus {
         if other.state_resolved_source.is_some() {
             self.state_resolved_source = other.state_resolved_source;
             self.state_resolved_target = other.state_resolved_target;
+            self.state_hash = other.state_hash;
         }
     }
+
+    /// The package this link belongs to (None if it is only in the lockfile)
+    pub fn package(&self) -> Option<&Package> {
+        self.config_package.as_ref()
+    }
+
+    /// Whether this link is recorded in the lockfile
+    pub fn is_recorded(&self) -> bool {
+        self.state_resolved_source.is_some()
+    }
+
+    /// Whether the target is (or should become) a copy rather than a symlink
+    pub fn is_copy(&self) -> bool {
+        match &self.config_package {
+            Some(package) => {
+                matches!(
+                    package.strategy,
+                    LinkStrategy::Copy | LinkStrategy::Template
+                )
+            }
+            None => self.state_hash.is_some(),
+        }
+    }
+
+    /// Whether the copy is rendered from a template
+    pub fn is_template(&self) -> bool {
+        self.config_package
+            .as_ref()
+            .is_some_and(|package| package.strategy == LinkStrategy::Template)
+    }
+
+    /// Whether a recorded copy was edited since doty wrote it
+    pub fn is_modified_copy(&self) -> bool {
+        self.state_hash.is_some()
+            && self.target_hash.is_some()
+            && self.target_hash != self.state_hash
+    }
 }
 
 /// The Linker handles creating and managing symlinks
@@ -119,6 +258,16 @@ pub struct Linker {
     /// Path resolution strategy (retained for potential future features like debugging or per-package overrides)
     #[allow(dead_code)]
     path_resolution: PathResolution,
+    /// Where conflicting originals are moved before doty replaces them
+    backup_store: Option<BackupStore>,
+    /// Records every filesystem change so a failed run can be rolled back
+    journal: Option<Journal>,
+    /// Variables for rendering Template packages
+    templates: Option<TemplateContext>,
+    /// Machine that packages' `when` conditions are checked against
+    host: HostInfo,
+    /// Receives the plan, executed actions and walk warnings
+    reporter: Rc<dyn Reporter>,
 }
 
 impl Linker {
@@ -127,6 +276,74 @@ impl Linker {
         Self {
             config_dir_or_cwd,
             path_resolution,
+            backup_store: None,
+            journal: None,
+            templates: None,
+            host: HostInfo::current(),
+            reporter: Rc::new(QuietReporter),
+        }
+    }
+
+    /// Send the plan, executed actions and warnings to a reporter
+    pub fn with_reporter(mut self, reporter: Rc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Check `when` conditions against another machine than the current one
+    pub fn with_host(mut self, host: HostInfo) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Use these variables to render Template packages
+    pub fn with_templates(mut self, templates: TemplateContext) -> Self {
+        self.templates = Some(templates);
+        self
+    }
+
+    /// Template variables, required as soon as a Template package is involved
+    fn templates(&self) -> Result<&TemplateContext> {
+        self.templates
+            .as_ref()
+            .context("No template variables to render Template packages with")
+    }
+
+    /// Use a backup store for originals replaced while resolving conflicts
+    pub fn with_backup_store(mut self, backup_store: BackupStore) -> Self {
+        self.backup_store = Some(backup_store);
+        self
+    }
+
+    /// Record every change made by execute_action in a journal
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Undo everything recorded in the journal (newest first)
+    pub fn rollback(&mut self) -> Result<usize> {
+        match (self.journal.as_mut(), self.backup_store.as_mut()) {
+            (Some(journal), Some(backup_store)) => journal.rollback(backup_store),
+            (Some(journal), None) if journal.steps.is_empty() => Ok(0),
+            (Some(_), None) => anyhow::bail!("Cannot roll back without a backup store"),
+            (None, _) => Ok(0),
+        }
+    }
+
+    /// Drop the journal once the run is complete
+    pub fn finish_journal(&mut self) -> Result<()> {
+        match self.journal.take() {
+            Some(journal) => journal.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a completed step to the journal, if there is one
+    fn record(&mut self, step: JournalStep) -> Result<()> {
+        match self.journal.as_mut() {
+            Some(journal) => journal.record(step),
+            None => Ok(()),
         }
     }
 
@@ -140,33 +357,42 @@ impl Linker {
         let link_states = self.gather_link_states(config, lockfile)?;
 
         // Determine actions based on gathered statuses
-        Ok({
-            let this = &self;
-            let mut actions = Vec::new();
-            for status in link_states.values() {
-                actions.extend(this.determine_action_for_status(status, force));
+        let mut actions = Vec::new();
+        let mut plan = Vec::new();
+        for status in link_states.values() {
+            for action in self.determine_action_for_status(status, force) {
+                plan.push(PlannedAction {
+                    package: status.config_package.clone(),
+                    action: action.clone(),
+                });
+                actions.push(action);
             }
-            actions
-        })
+        }
+        self.reporter.event(&Event::PlanComputed(plan));
+        Ok(actions)
     }
 
     /// Gather information about all relevant targets from Config, Lockfile, and Filesystem
-    fn gather_link_states(
+    pub fn gather_link_states(
         &self,
         config: &DotyConfig,
         lockfile: &Lockfile,
     ) -> Result<HashMap<Utf8PathBuf, LinkStatus>> {
         // 1. Stream Config Statuses
-        let config_stream = config
-            .packages
-            .iter()
-            .flat_map(|pkg| self.expand_package(pkg));
+        let config_stream = config.packages.iter().flat_map(|pkg| {
+            let on_conflict = config.on_conflict_for(pkg);
+            self.expand_package(pkg)
+                .into_iter()
+                .map(move |(target, mut status)| {
+                    status.config_on_conflict = on_conflict;
+                    (target, status)
+                })
+        });
 
         // 2. Stream Lockfile Statuses
-        let lockfile_stream = lockfile
-            .links
-            .iter()
-            .map(|(target, source)| self.create_link_status_from_lockfile(target, source));
+        let lockfile_stream = lockfile.links.iter().map(|(target, source)| {
+            self.create_link_status_from_lockfile(target, source, lockfile.hashes.get(target))
+        });
 
         // 3. Fold into Map
         let mut map: HashMap<Utf8PathBuf, LinkStatus> = HashMap::new();
@@ -184,8 +410,41 @@ impl Linker {
         Ok(map)
     }
 
+    /// Targets the config wants on this machine, lockfile links to anything else are orphans
+    pub fn desired_targets(&self, config: &DotyConfig) -> HashSet<Utf8PathBuf> {
+        config
+            .packages
+            .iter()
+            .flat_map(|pkg| self.expand_package(pkg))
+            .map(|(target, _)| target)
+            .collect()
+    }
+
+    /// Links the config wants on this machine by target, with their absolute source and the
+    /// package producing them
+    pub fn desired_links(
+        &self,
+        config: &DotyConfig,
+    ) -> HashMap<Utf8PathBuf, (Utf8PathBuf, Package)> {
+        config
+            .packages
+            .iter()
+            .flat_map(|pkg| self.expand_package(pkg))
+            .filter_map(|(target, status)| {
+                let source = self.absolute_source(status.config_resolved_source.as_ref()?);
+                Some((target, (source, status.config_package?)))
+            })
+            .collect()
+    }
+
     /// Expand a package into a stream of LinkStatuses
+    /// Packages whose `when` condition doesn't match this machine expand to nothing,
+    /// so links recorded for them become orphans and are removed
     fn expand_package(&self, package: &Package) -> Vec<(Utf8PathBuf, LinkStatus)> {
+        if !package.is_active(&self.host) {
+            return Vec::new();
+        }
+
         let source_path = self.config_dir_or_cwd.join(&package.source);
         let mut results = Vec::new();
 
@@ -198,6 +457,7 @@ impl Linker {
             results.push((
                 resolved_target.clone(),
                 LinkStatus::from_config(
+                    package,
                     package.target.clone(),
                     package.source.clone(),
                     true,  // explicit
@@ -211,6 +471,7 @@ impl Linker {
             results.push((
                 resolved_target.clone(),
                 LinkStatus::from_config(
+                    package,
                     package.target.clone(),
                     package.source.clone(),
                     true, // explicit
@@ -223,6 +484,7 @@ impl Linker {
                     results.push((
                         resolved_target.clone(),
                         LinkStatus::from_config(
+                            package,
                             package.target.clone(),
                             package.source.clone(),
                             true, // explicit
@@ -230,27 +492,35 @@ impl Linker {
                         ),
                     ));
                 }
-                LinkStrategy::LinkFilesRecursive => {
-                    if let Ok(files) = scan_directory_recursive(&source_path) {
-                        for file in files {
-                            if let Ok(relative) = file.strip_prefix(&source_path) {
-                                let target_path = package.target.join(relative);
-                                let source_rel = package.source.join(relative);
-                                let resolved_target_path =
-                                    resolve_target_path(&target_path, &self.config_dir_or_cwd)
-                                        .unwrap_or_else(|_| {
-                                            self.config_dir_or_cwd.join(&target_path)
-                                        });
-                                results.push((
-                                    resolved_target_path,
-                                    LinkStatus::from_config(
-                                        target_path,
-                                        source_rel,
-                                        false, // implicit
-                                        true,  // exists
-                                    ),
-                                ));
+                // Copies and templates are tracked per file, like LinkFilesRecursive
+                LinkStrategy::LinkFilesRecursive | LinkStrategy::Copy | LinkStrategy::Template => {
+                    let walk = walk_files(&source_path, WalkOptions::default());
+                    for warning in walk.warnings {
+                        self.reporter.event(&Event::Warning(warning));
+                    }
+                    for file in walk.files {
+                        if let Ok(relative) = file.strip_prefix(&source_path) {
+                            // Filtered files are not part of the package, links created
+                            // before a pattern was added become orphans
+                            if relative == DOTYIGNORE_FILE || !package.filter.is_included(relative)
+                            {
+                                continue;
                             }
+                            let target_path = package.target.join(relative);
+                            let source_rel = package.source.join(relative);
+                            let resolved_target_path =
+                                resolve_target_path(&target_path, &self.config_dir_or_cwd)
+                                    .unwrap_or_else(|_| self.config_dir_or_cwd.join(&target_path));
+                            results.push((
+                                resolved_target_path,
+                                LinkStatus::from_config(
+                                    package,
+                                    target_path,
+                                    source_rel,
+                                    false, // implicit
+                                    true,  // exists
+                                ),
+                            ));
                         }
                     }
                 }
@@ -264,10 +534,11 @@ impl Linker {
         &self,
         target: &Utf8PathBuf,
         source: &Utf8PathBuf,
+        hash: Option<&String>,
     ) -> (Utf8PathBuf, LinkStatus) {
         (
             target.clone(),
-            LinkStatus::from_lockfile(target.clone(), source.clone()),
+            LinkStatus::from_lockfile(target.clone(), source.clone(), hash.cloned()),
         )
     }
 
@@ -292,6 +563,21 @@ impl Linker {
                 status.target_points_to = read_symlink_target(&target_path)?;
             }
         }
+
+        // Copies are compared by content, templates by their rendered output
+        if status.is_copy() {
+            if status.target_type == Some(FsType::File) {
+                status.target_hash = Some(hash_file(&target_path)?);
+            }
+            if let (true, Some(source)) = (status.source_exists, &status.config_resolved_source) {
+                let source_path = self.config_dir_or_cwd.join(source);
+                status.source_hash = Some(if status.is_template() {
+                    self.templates()?.render_hash(&source_path)?
+                } else {
+                    hash_file(&source_path)?
+                });
+            }
+        }
         Ok(())
     }
 
@@ -307,6 +593,9 @@ impl Linker {
         // Case 1: Link is in Lockfile but NOT in Config -> Remove it
         if status.config_resolved_source.is_none() {
             if let Some(stored) = &status.state_resolved_source {
+                if status.state_hash.is_some() {
+                    return self.remove_copy_action(status, target, stored, force);
+                }
                 return vec![LinkAction::Removed {
                     target: target.clone(),
                     source: stored.clone(),
@@ -352,9 +641,13 @@ impl Linker {
                 ];
             } else if force && status.state_resolved_source.is_some() {
                 // If forced and we tracked it before, remove it
+                let stored = status.state_resolved_source.as_ref().unwrap();
+                if status.state_hash.is_some() {
+                    return self.remove_copy_action(status, target, stored, force);
+                }
                 return vec![LinkAction::Removed {
                     target: target.clone(),
-                    source: status.state_resolved_source.as_ref().unwrap().clone(),
+                    source: stored.clone(),
                 }];
             } else {
                 // Otherwise warn
@@ -368,6 +661,21 @@ impl Linker {
         }
 
         // Case 3: Link is Configured (and source exists)
+        if status.is_copy() {
+            return self.determine_copy_action(status, target, desired_source, force);
+        }
+
+        // Never replace something Doty doesn't own, let the conflict policy decide
+        if let Some(existing) = self.foreign_target_type(status, desired_source) {
+            return vec![LinkAction::Conflict {
+                target: target.clone(),
+                source: desired_source.clone(),
+                existing,
+                policy: status.config_on_conflict,
+                hash: None,
+                template: false,
+            }];
+        }
 
         // Subcase 3a: Not in Lockfile (New link)
         if status.state_resolved_source.is_none() {
@@ -381,12 +689,7 @@ impl Linker {
 
         // Subcase 3b: In Lockfile, but source path changed
         // Normalize desired_source to absolute for comparison (lockfile stores absolute paths)
-        let desired_abs_source = self
-            .config_dir_or_cwd
-            .join(desired_source)
-            .canonicalize()
-            .map(|p| Utf8PathBuf::from_path_buf(p).unwrap_or_default())
-            .unwrap_or_else(|_| self.config_dir_or_cwd.join(desired_source));
+        let desired_abs_source = self.absolute_source(desired_source);
 
         if desired_abs_source != *stored_source {
             return vec![LinkAction::Updated {
@@ -398,12 +701,7 @@ impl Linker {
 
         // Subcase 3c: In Lockfile, source path same -> Check Reality
         // Calculate absolute desired path for comparison
-        let desired_abs = self
-            .config_dir_or_cwd
-            .join(desired_source)
-            .canonicalize()
-            .map(|p| Utf8PathBuf::from_path_buf(p).unwrap_or_default())
-            .unwrap_or_else(|_| self.config_dir_or_cwd.join(desired_source));
+        let desired_abs = self.absolute_source(desired_source);
 
         let is_correct = if let Some(actual) = &status.target_points_to {
             *actual == desired_abs
@@ -412,20 +710,203 @@ impl Linker {
         };
 
         if is_correct {
-            return vec![LinkAction::Skipped {
+            vec![LinkAction::Skipped {
                 target: target.clone(),
                 source: desired_source.clone(),
-            }];
+            }]
         } else {
-            return vec![LinkAction::Created {
+            vec![LinkAction::Created {
                 target: target.clone(),
                 source: desired_source.clone(),
-            }];
+            }]
+        }
+    }
+
+    /// Determine the action for a configured copy (Copy strategy, source exists)
+    /// Copies doty wrote are refreshed from the source, local edits are never overwritten silently
+    fn determine_copy_action(
+        &self,
+        status: &LinkStatus,
+        target: &Utf8PathBuf,
+        desired_source: &Utf8PathBuf,
+        force: bool,
+    ) -> Vec<LinkAction> {
+        let source_hash = status
+            .source_hash
+            .clone()
+            .expect("Existing copy source must be hashed");
+        let copied = || {
+            vec![LinkAction::Copied {
+                target: target.clone(),
+                source: desired_source.clone(),
+                hash: source_hash.clone(),
+                template: status.is_template(),
+            }]
+        };
+        let conflict = |existing: FsType, policy: OnConflict| {
+            // Adopting keeps the target's content, so that is what the copy ends up with
+            let hash = match policy {
+                OnConflict::Adopt => status.target_hash.clone(),
+                _ => None,
+            };
+            vec![LinkAction::Conflict {
+                target: target.clone(),
+                source: desired_source.clone(),
+                existing,
+                policy,
+                hash: Some(hash.unwrap_or_else(|| source_hash.clone())),
+                template: status.is_template(),
+            }]
+        };
+
+        match status.target_type {
+            None => copied(),
+            Some(FsType::File) => {
+                if status.target_hash.as_ref() == Some(&source_hash) {
+                    let unchanged = status.state_hash.as_ref() == Some(&source_hash)
+                        && status.state_resolved_source.as_ref()
+                            == Some(&self.absolute_source(desired_source));
+                    if unchanged {
+                        vec![LinkAction::Skipped {
+                            target: target.clone(),
+                            source: desired_source.clone(),
+                        }]
+                    } else {
+                        copied()
+                    }
+                } else if !status.is_recorded() {
+                    conflict(FsType::File, status.config_on_conflict)
+                } else if !status.is_modified_copy() {
+                    // Still what doty wrote, the source changed
+                    copied()
+                } else if force {
+                    conflict(FsType::File, OnConflict::Backup)
+                } else {
+                    vec![LinkAction::Warning {
+                        target: target.clone(),
+                        source: desired_source.clone(),
+                        message: "Copy was edited locally, not overwriting (use --force to back it up and replace it)"
+                            .to_string(),
+                    }]
+                }
+            }
+            Some(FsType::Symlink) => {
+                // Our own symlink (e.g. the package switched to Copy) is replaced by the copy
+                let points_to_source =
+                    status.target_points_to.as_ref() == Some(&self.absolute_source(desired_source));
+                if status.is_recorded() || points_to_source {
+                    copied()
+                } else {
+                    conflict(FsType::Symlink, status.config_on_conflict)
+                }
+            }
+            Some(FsType::Directory) => conflict(FsType::Directory, status.config_on_conflict),
+        }
+    }
+
+    /// Determine the action for a copy that should no longer be there
+    /// Unmodified copies are deleted, edited ones are left in place (and forgotten with --force)
+    fn remove_copy_action(
+        &self,
+        status: &LinkStatus,
+        target: &Utf8PathBuf,
+        stored_source: &Utf8PathBuf,
+        force: bool,
+    ) -> Vec<LinkAction> {
+        match status.target_type {
+            Some(FsType::File) if !status.is_modified_copy() => vec![LinkAction::Deleted {
+                target: target.clone(),
+                source: stored_source.clone(),
+            }],
+            Some(FsType::File) if !force => vec![LinkAction::Warning {
+                target: target.clone(),
+                source: stored_source.clone(),
+                message: "Copy was edited locally, left in place (use --force to stop tracking it)"
+                    .to_string(),
+            }],
+            _ => vec![LinkAction::Removed {
+                target: target.clone(),
+                source: stored_source.clone(),
+            }],
+        }
+    }
+
+    /// Determine the health of a single link from the action `doty link` would take for it
+    pub fn link_health(&self, status: &LinkStatus) -> LinkHealth {
+        if status.is_modified_copy() {
+            return LinkHealth::Modified;
+        }
+
+        let actions = self.determine_action_for_status(status, false);
+
+        if actions
+            .iter()
+            .any(|a| matches!(a, LinkAction::Warning { .. } | LinkAction::Pruned { .. }))
+        {
+            return LinkHealth::Broken;
+        }
+
+        match actions.first() {
+            Some(LinkAction::Removed { .. } | LinkAction::Deleted { .. }) => LinkHealth::Orphaned,
+            Some(LinkAction::Updated { .. }) => LinkHealth::PendingUpdate,
+            Some(LinkAction::Conflict { .. }) => LinkHealth::Conflicting,
+            Some(LinkAction::Created { .. }) => LinkHealth::PendingCreate,
+            Some(LinkAction::Copied { .. }) if status.target_exists => LinkHealth::PendingUpdate,
+            Some(LinkAction::Copied { .. }) => LinkHealth::PendingCreate,
+            _ => LinkHealth::InSync,
+        }
+    }
+
+    /// Type of whatever occupies the target if Doty doesn't own it:
+    /// real files and directories, or symlinks that are neither recorded in the lockfile
+    /// nor already pointing to the desired source
+    fn foreign_target_type(
+        &self,
+        status: &LinkStatus,
+        desired_source: &Utf8Path,
+    ) -> Option<FsType> {
+        match status.target_type? {
+            FsType::File | FsType::Directory => status.target_type,
+            FsType::Symlink => {
+                let points_to_source =
+                    status.target_points_to.as_ref() == Some(&self.absolute_source(desired_source));
+                if status.is_recorded() || points_to_source {
+                    None
+                } else {
+                    Some(FsType::Symlink)
+                }
+            }
         }
     }
 
+    /// Resolve a config source path to the absolute path a correct symlink points to
+    fn absolute_source(&self, source: &Utf8Path) -> Utf8PathBuf {
+        self.config_dir_or_cwd
+            .join(source)
+            .canonicalize()
+            .map(|p| Utf8PathBuf::from_path_buf(p).unwrap_or_default())
+            .unwrap_or_else(|_| self.config_dir_or_cwd.join(source))
+    }
+
     /// Execute a single action
-    pub fn execute_action(&self, action: &LinkAction, dry_run: bool) -> Result<()> {
+    pub fn execute_action(&mut self, action: &LinkAction, dry_run: bool) -> Result<()> {
+        self.reporter.event(&Event::ActionStarted(action.clone()));
+        let result = self.apply_action(action, dry_run);
+        self.reporter.event(&match &result {
+            Ok(()) => Event::ActionFinished {
+                action: action.clone(),
+                dry_run,
+            },
+            Err(err) => Event::ActionFailed {
+                action: action.clone(),
+                error: format!("{:#}", err),
+            },
+        });
+        result
+    }
+
+    /// Make the filesystem change of an action (helper for execute_action)
+    fn apply_action(&mut self, action: &LinkAction, dry_run: bool) -> Result<()> {
         match action {
             LinkAction::Created { target, source } => {
                 let source_path = self.config_dir_or_cwd.join(source);
@@ -449,54 +930,227 @@ impl Linker {
                 self.remove_link(&target_path, dry_run)?;
                 self.create_link(&new_source_path, &target_path, dry_run)
             }
+            LinkAction::Conflict {
+                target,
+                source,
+                policy,
+                hash,
+                template,
+                ..
+            } => {
+                let source_path = self.config_dir_or_cwd.join(source);
+                let target_path = resolve_target_path(target, &self.config_dir_or_cwd)?;
+                let copy = hash.is_some().then_some(*template);
+                self.resolve_conflict(&source_path, &target_path, *policy, copy, dry_run)
+            }
+            LinkAction::Copied {
+                target,
+                source,
+                template,
+                ..
+            } => {
+                let source_path = self.config_dir_or_cwd.join(source);
+                let target_path = resolve_target_path(target, &self.config_dir_or_cwd)?;
+                self.write_copy(&source_path, &target_path, *template, dry_run)
+            }
+            LinkAction::Deleted { target, .. } => {
+                let target_path = resolve_target_path(target, &self.config_dir_or_cwd)?;
+                if dry_run || get_fs_type(&target_path)? != Some(FsType::File) {
+                    return Ok(());
+                }
+                // A journaled run keeps the copy in the backup store, so a rollback brings it back
+                if self.journal.is_some() {
+                    self.back_up(&target_path)
+                } else {
+                    fs::remove_file(&target_path)
+                        .with_context(|| format!("Failed to delete copy: {}", target_path))
+                }
+            }
             LinkAction::Warning { .. } | LinkAction::Skipped { .. } => Ok(()),
         }
     }
 
-    /// Create a symlink (helper for execute_action)
-    fn create_link(&self, source: &Utf8Path, target: &Utf8Path, dry_run: bool) -> Result<()> {
-        // Create parent directory if needed
+    /// Clear a conflicting target according to its policy, then link or copy (helper for execute_action)
+    /// `copy` is None for symlinks, otherwise whether the copy is rendered from a template
+    fn resolve_conflict(
+        &mut self,
+        source: &Utf8Path,
+        target: &Utf8Path,
+        policy: OnConflict,
+        copy: Option<bool>,
+        dry_run: bool,
+    ) -> Result<()> {
+        if dry_run {
+            return Ok(());
+        }
+        match policy {
+            OnConflict::Fail => anyhow::bail!(
+                "Refusing to replace {}: it is not managed by doty (onConflict is 'fail')",
+                target
+            ),
+            OnConflict::Skip => return Ok(()),
+            OnConflict::Backup => self.back_up(target)?,
+            OnConflict::Adopt => {
+                // The target wins: it replaces the source in the repo
+                if get_fs_type(source)?.is_some() {
+                    self.back_up(source)?;
+                }
+                move_path(target, source)
+                    .with_context(|| format!("Failed to adopt {} into the repo", target))?;
+                self.record(JournalStep::Moved {
+                    from: target.to_path_buf(),
+                    to: source.to_path_buf(),
+                })?;
+            }
+        }
+
+        match copy {
+            Some(template) => self.write_copy(source, target, template, false),
+            None => self.create_link(source, target, false),
+        }
+    }
+
+    /// Move an original into the backup store (helper for resolve_conflict)
+    fn back_up(&mut self, path: &Utf8Path) -> Result<()> {
+        let store = self
+            .backup_store
+            .as_mut()
+            .with_context(|| format!("No backup store to move {} aside", path))?;
+        let entry = store.back_up(path)?;
+        self.record(JournalStep::BackedUp {
+            path: path.to_path_buf(),
+            id: entry.id,
+        })
+    }
+
+    /// Create missing parent directories of a target, outermost first
+    fn create_parent_dirs(&mut self, target: &Utf8Path, dry_run: bool) -> Result<()> {
         if let Some(parent) = target.parent() {
             if !parent.exists() && !dry_run {
-                fs::create_dir_all(parent)?;
+                let missing: Vec<Utf8PathBuf> = parent
+                    .ancestors()
+                    .take_while(|dir| !dir.exists())
+                    .map(Utf8PathBuf::from)
+                    .collect();
+                for dir in missing.into_iter().rev() {
+                    fs::create_dir(&dir).map_err(|err| {
+                        DotyError::io(&dir, format!("Failed to create directory: {}", dir), err)
+                    })?;
+                    self.record(JournalStep::DirCreated { path: dir })?;
+                }
             }
         }
+        Ok(())
+    }
 
-        // Remove existing target if it exists
-        if target.exists() && !dry_run {
-            if target.is_dir() {
-                fs::remove_dir_all(target)?;
-            } else {
-                fs::remove_file(target)?;
+    /// Create a symlink (helper for execute_action)
+    fn create_link(&mut self, source: &Utf8Path, target: &Utf8Path, dry_run: bool) -> Result<()> {
+        self.create_parent_dirs(target, dry_run)?;
+
+        // Replace an existing symlink, but never delete real files or directories here
+        match get_fs_type(target)? {
+            Some(FsType::Symlink) => self.remove_link(target, dry_run)?,
+            None => {}
+            Some(existing) => {
+                return Err(DotyError::TargetOccupied {
+                    target: target.to_path_buf(),
+                    existing: existing.to_string(),
+                }
+                .into())
             }
         }
 
         if !dry_run {
             self.create_symlink(source, target)?;
+            self.record(JournalStep::LinkCreated {
+                target: target.to_path_buf(),
+            })?;
         }
 
         Ok(())
     }
 
-    /// Remove a symlink (helper for execute_action)
-    fn remove_link(&self, target: &Utf8Path, dry_run: bool) -> Result<()> {
-        if !dry_run {
-            // Use symlink_metadata to handle broken symlinks (exists() returns false for broken symlinks)
-            if let Ok(metadata) = fs::symlink_metadata(target) {
-                if metadata.is_dir() {
-                    fs::remove_dir_all(target)?;
-                } else {
-                    fs::remove_file(target)?;
-                }
-            } else if target.exists() {
-                // Fallback for non-symlink files/directories
-                if target.is_dir() {
-                    fs::remove_dir_all(target)?;
-                } else {
-                    fs::remove_file(target)?;
+    /// Write a copy of a source file, rendered if it is a template (helper for execute_action)
+    /// Replaces our own symlink or copy; the content goes to a temporary sibling first,
+    /// so the target is never left half-written. A previous copy is moved into the backup
+    /// store when the run is journaled, so a rollback puts it back
+    fn write_copy(
+        &mut self,
+        source: &Utf8Path,
+        target: &Utf8Path,
+        template: bool,
+        dry_run: bool,
+    ) -> Result<()> {
+        if dry_run {
+            return Ok(());
+        }
+        // Render before touching anything, a broken template must not leave the target half-replaced
+        let rendered = match template {
+            true => Some(self.templates()?.render(source)?),
+            false => None,
+        };
+        self.create_parent_dirs(target, dry_run)?;
+
+        let previous = get_fs_type(target)?;
+        match previous {
+            Some(FsType::Symlink) => self.remove_link(target, dry_run)?,
+            None | Some(FsType::File) => {}
+            Some(existing) => {
+                return Err(DotyError::TargetOccupied {
+                    target: target.to_path_buf(),
+                    existing: existing.to_string(),
                 }
+                .into())
+            }
+        }
+
+        let file_name = target.file_name().unwrap_or("copy");
+        let temp = target.with_file_name(format!(".{}.doty-tmp", file_name));
+        match rendered {
+            Some(content) => {
+                fs::write(&temp, content).map_err(|err| {
+                    DotyError::io(target, format!("Failed to write rendered {}", target), err)
+                })?;
+                // Keep the template's permissions (e.g. executable scripts)
+                fs::set_permissions(&temp, fs::metadata(source)?.permissions())?;
+            }
+            None => {
+                fs::copy(source, &temp).map_err(|err| {
+                    DotyError::io(
+                        target,
+                        format!("Failed to copy {} to {}", source, target),
+                        err,
+                    )
+                })?;
             }
         }
+        if previous == Some(FsType::File) && self.journal.is_some() {
+            if let Err(err) = self.back_up(target) {
+                let _ = fs::remove_file(&temp);
+                return Err(err);
+            }
+        }
+        fs::rename(&temp, target).map_err(|err| {
+            DotyError::io(target, format!("Failed to write copy: {}", target), err)
+        })?;
+        self.record(JournalStep::FileWritten {
+            path: target.to_path_buf(),
+        })
+    }
+
+    /// Remove a symlink (helper for execute_action)
+    /// A real file or directory at the target is not ours to delete and is left in place
+    fn remove_link(&mut self, target: &Utf8Path, dry_run: bool) -> Result<()> {
+        if !dry_run && get_fs_type(target)? == Some(FsType::Symlink) {
+            let source = fs::read_link(target)
+                .with_context(|| format!("Failed to read symlink: {}", target))?;
+            remove_symlink(target)?;
+            self.record(JournalStep::LinkRemoved {
+                target: target.to_path_buf(),
+                source: Utf8PathBuf::from_path_buf(source)
+                    .map_err(|_| anyhow::anyhow!("Symlink target is not valid UTF-8"))?,
+            })?;
+        }
         Ok(())
     }
 
@@ -507,22 +1161,58 @@ impl Linker {
         for (target, source) in &lockfile.links {
             let target_path = resolve_target_path(target, &self.config_dir_or_cwd)?;
 
-            // Check if the symlink exists (using symlink_metadata to handle broken symlinks)
-            if let Ok(metadata) = fs::symlink_metadata(&target_path) {
-                if !dry_run {
-                    if metadata.is_dir() {
-                        fs::remove_dir_all(&target_path)?;
+            // Use get_fs_type (symlink_metadata) to handle broken symlinks
+            match get_fs_type(&target_path)? {
+                Some(FsType::File) if lockfile.get_hash(target).is_some() => {
+                    // Copies are deleted unless they were edited since doty wrote them
+                    if lockfile.get_hash(target) == Some(&hash_file(&target_path)?) {
+                        if !dry_run {
+                            fs::remove_file(&target_path).map_err(|err| {
+                                let message = format!("Failed to delete copy: {}", target_path);
+                                DotyError::io(&target_path, message, err)
+                            })?;
+                        }
+                        actions.push(LinkAction::Deleted {
+                            target: target.clone(),
+                            source: source.clone(),
+                        });
                     } else {
-                        fs::remove_file(&target_path)?;
+                        actions.push(LinkAction::Warning {
+                            target: target.clone(),
+                            source: source.clone(),
+                            message: "Copy was edited locally, left in place".to_string(),
+                        });
                     }
                 }
-                actions.push(LinkAction::Removed {
-                    target: target.clone(),
-                    source: source.clone(),
-                });
+                Some(FsType::Symlink) => {
+                    if !dry_run {
+                        remove_symlink(&target_path)?;
+                    }
+                    actions.push(LinkAction::Removed {
+                        target: target.clone(),
+                        source: source.clone(),
+                    });
+                }
+                Some(existing) => {
+                    actions.push(LinkAction::Warning {
+                        target: target.clone(),
+                        source: source.clone(),
+                        message: format!(
+                            "Target is a {} now, not a symlink, left in place",
+                            existing
+                        ),
+                    });
+                }
+                None => {}
             }
         }
 
+        for action in &actions {
+            self.reporter.event(&Event::ActionFinished {
+                action: action.clone(),
+                dry_run,
+            });
+        }
         Ok(actions)
     }
 
@@ -542,11 +1232,12 @@ impl Linker {
 
         #[cfg(unix)]
         {
-            std::os::unix::fs::symlink(&absolute_source, target).with_context(|| {
-                format!(
+            std::os::unix::fs::symlink(&absolute_source, target).map_err(|err| {
+                let message = format!(
                     "Failed to create symlink: {} -> {}",
                     target, absolute_source
-                )
+                );
+                DotyError::io(target, message, err)
             })?;
         }
 
@@ -581,6 +1272,7 @@ mod tests {
     use super::*;
     use crate::config::PathResolution;
     use std::fs;
+    use std::str::FromStr;
 
     fn setup_test_fs(test_name: &str) -> Utf8PathBuf {
         let test_dir = format!("tests/tmpfs/{}", test_name);
@@ -648,7 +1340,7 @@ mod tests {
         assert!(!zshrc.exists());
 
         // Clean up
-        let _ = fs::remove_dir_all(format!("tests/tmpfs/test_clean_removes_links"));
+        let _ = fs::remove_dir_all("tests/tmpfs/test_clean_removes_links");
     }
 
     #[test]
@@ -674,6 +1366,334 @@ mod tests {
         assert!(zshrc.exists());
 
         // Clean up
-        let _ = fs::remove_dir_all(format!("tests/tmpfs/test_clean_dry_run"));
+        let _ = fs::remove_dir_all("tests/tmpfs/test_clean_dry_run");
+    }
+
+    #[test]
+    fn test_link_health() {
+        let config_dir_or_cwd = setup_test_fs("test_link_health");
+        let target_dir = config_dir_or_cwd.parent().unwrap().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Sources for four packages
+        for name in ["synced", "pending", "conflict", "gone"] {
+            fs::create_dir_all(config_dir_or_cwd.join(name)).unwrap();
+        }
+
+        // synced: correct symlink recorded in the lockfile
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(config_dir_or_cwd.join("synced"), target_dir.join("synced"))
+            .unwrap();
+        // conflict: a real directory sits at the target
+        fs::create_dir_all(target_dir.join("conflict")).unwrap();
+        // gone: source removed after linking
+        fs::remove_dir_all(config_dir_or_cwd.join("gone")).unwrap();
+
+        let config = DotyConfig {
+            packages: ["synced", "pending", "conflict", "gone"]
+                .iter()
+                .map(|name| Package {
+                    source: Utf8PathBuf::from(*name),
+                    target: target_dir.join(name),
+                    strategy: LinkStrategy::LinkFolder,
+                    on_conflict: None,
+                    when: None,
+                    filter: Default::default(),
+                    detect_ignore: Vec::new(),
+                    detect: None,
+                })
+                .collect(),
+            path_resolution: PathResolution::Config,
+            on_conflict: OnConflict::default(),
+            variables: Default::default(),
+            filter: Default::default(),
+            files: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let mut lockfile = Lockfile::new("test-host".to_string(), config_dir_or_cwd.clone());
+        lockfile.add_link(target_dir.join("synced"), Utf8PathBuf::from("synced"));
+        lockfile.add_link(target_dir.join("gone"), Utf8PathBuf::from("gone"));
+        lockfile.add_link(target_dir.join("orphan"), Utf8PathBuf::from("orphan"));
+
+        let linker = Linker::new(config_dir_or_cwd.clone(), PathResolution::Config);
+        let states = linker.gather_link_states(&config, &lockfile).unwrap();
+        let health = |name: &str| linker.link_health(&states[&target_dir.join(name)]);
+
+        #[cfg(unix)]
+        assert_eq!(health("synced"), LinkHealth::InSync);
+        assert_eq!(health("pending"), LinkHealth::PendingCreate);
+        assert_eq!(health("conflict"), LinkHealth::Conflicting);
+        assert_eq!(health("gone"), LinkHealth::Broken);
+        assert_eq!(health("orphan"), LinkHealth::Orphaned);
+
+        assert!(states[&target_dir.join("orphan")].package().is_none());
+        assert_eq!(
+            states[&target_dir.join("pending")].package(),
+            Some(&config.packages[1])
+        );
+
+        // Clean up
+        let _ = fs::remove_dir_all("tests/tmpfs/test_link_health");
+    }
+
+    #[test]
+    fn test_copy_strategy() {
+        let config_dir_or_cwd = setup_test_fs("test_copy_strategy");
+        let target_dir = config_dir_or_cwd.parent().unwrap().join("target");
+        let source_dir = config_dir_or_cwd.join("vscode");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("settings.json"), "{}").unwrap();
+
+        let config = DotyConfig {
+            packages: vec![Package {
+                source: Utf8PathBuf::from("vscode"),
+                target: target_dir.clone(),
+                strategy: LinkStrategy::Copy,
+                on_conflict: None,
+                when: None,
+                filter: Default::default(),
+                detect_ignore: Vec::new(),
+                detect: None,
+            }],
+            path_resolution: PathResolution::Config,
+            on_conflict: OnConflict::default(),
+            variables: Default::default(),
+            filter: Default::default(),
+            files: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let target = target_dir.join("settings.json");
+        let mut lockfile = Lockfile::new("test-host".to_string(), config_dir_or_cwd.clone());
+        let mut linker = Linker::new(config_dir_or_cwd.clone(), PathResolution::Config);
+
+        let apply = |linker: &mut Linker, lockfile: &mut Lockfile, force: bool| {
+            let actions = linker.calculate_diff(&config, lockfile, force).unwrap();
+            for action in &actions {
+                linker.execute_action(action, false).unwrap();
+                if let LinkAction::Copied {
+                    target,
+                    source,
+                    hash,
+                    ..
+                } = action
+                {
+                    lockfile.add_copy(target.clone(), source.clone(), hash.clone());
+                }
+            }
+            actions
+        };
+
+        // First run writes a real file and records its hash
+        let actions = apply(&mut linker, &mut lockfile, false);
+        assert!(matches!(actions[..], [LinkAction::Copied { .. }]));
+        assert_eq!(get_fs_type(&target).unwrap(), Some(FsType::File));
+        assert_eq!(
+            lockfile.get_hash(&target),
+            Some(&hash_file(&target).unwrap())
+        );
+        assert!(matches!(
+            apply(&mut linker, &mut lockfile, false)[..],
+            [LinkAction::Skipped { .. }]
+        ));
+
+        // Source changes are copied over an unmodified target
+        fs::write(source_dir.join("settings.json"), "{\"a\": 1}").unwrap();
+        assert!(matches!(
+            apply(&mut linker, &mut lockfile, false)[..],
+            [LinkAction::Copied { .. }]
+        ));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "{\"a\": 1}");
+
+        // Local edits are reported, never overwritten without --force
+        fs::write(&target, "{\"local\": true}").unwrap();
+        let states = linker.gather_link_states(&config, &lockfile).unwrap();
+        assert_eq!(linker.link_health(&states[&target]), LinkHealth::Modified);
+        assert!(matches!(
+            apply(&mut linker, &mut lockfile, false)[..],
+            [LinkAction::Warning { .. }]
+        ));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "{\"local\": true}");
+
+        // --force backs the edited copy up before replacing it
+        let actions = linker.calculate_diff(&config, &lockfile, true).unwrap();
+        assert!(matches!(
+            actions[..],
+            [LinkAction::Conflict {
+                policy: OnConflict::Backup,
+                hash: Some(_),
+                ..
+            }]
+        ));
+
+        // Clean deletes unmodified copies only
+        assert!(matches!(
+            linker.clean(&lockfile, false).unwrap()[..],
+            [LinkAction::Warning { .. }]
+        ));
+        fs::write(&target, "{\"a\": 1}").unwrap();
+        assert!(matches!(
+            linker.clean(&lockfile, false).unwrap()[..],
+            [LinkAction::Deleted { .. }]
+        ));
+        assert!(!target.exists());
+
+        // Clean up
+        let _ = fs::remove_dir_all("tests/tmpfs/test_copy_strategy");
+    }
+
+    #[test]
+    fn test_inactive_package_links_become_orphans() {
+        let config_dir_or_cwd = setup_test_fs("test_inactive_package_links_become_orphans");
+        let target_dir = config_dir_or_cwd.parent().unwrap().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(config_dir_or_cwd.join("sway")).unwrap();
+
+        let config = DotyConfig::from_str(&format!(
+            r#"
+            LinkFolder "sway" target="{}" {{
+                when os="linux"
+            }}
+            "#,
+            target_dir.join("sway")
+        ))
+        .unwrap();
+        let host = |os: &str| HostInfo {
+            hostname: "test-host".to_string(),
+            os: os.to_string(),
+        };
+        let lockfile = Lockfile::new("test-host".to_string(), config_dir_or_cwd.clone());
+
+        let linux =
+            Linker::new(config_dir_or_cwd.clone(), PathResolution::Config).with_host(host("linux"));
+        let actions = linux.calculate_diff(&config, &lockfile, false).unwrap();
+        assert!(matches!(actions[..], [LinkAction::Created { .. }]));
+
+        // The same link recorded on a machine where the package doesn't apply is removed
+        let mut lockfile = lockfile;
+        lockfile.add_link(target_dir.join("sway"), config_dir_or_cwd.join("sway"));
+        let macos =
+            Linker::new(config_dir_or_cwd.clone(), PathResolution::Config).with_host(host("macos"));
+        let actions = macos.calculate_diff(&config, &lockfile, false).unwrap();
+        assert!(matches!(actions[..], [LinkAction::Removed { .. }]));
+
+        // Clean up
+        let _ = fs::remove_dir_all("tests/tmpfs/test_inactive_package_links_become_orphans");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_filtered_files_become_orphans() {
+        let config_dir_or_cwd = setup_test_fs("test_filtered_files_become_orphans");
+        let target_dir = config_dir_or_cwd.parent().unwrap().join("target");
+        let source_dir = config_dir_or_cwd.join("zsh");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join(".zshrc"), "zshrc").unwrap();
+        fs::write(source_dir.join("README.md"), "readme").unwrap();
+        fs::write(source_dir.join(".DS_Store"), "").unwrap();
+
+        // Both files were linked before the ignore pattern was added
+        let mut lockfile = Lockfile::new("test-host".to_string(), config_dir_or_cwd.clone());
+        for name in [".zshrc", "README.md"] {
+            std::os::unix::fs::symlink(source_dir.join(name), target_dir.join(name)).unwrap();
+            lockfile.add_link(target_dir.join(name), source_dir.join(name));
+        }
+
+        let config = DotyConfig::from_str(&format!(
+            r#"
+            defaults {{
+                ignore ".DS_Store"
+            }}
+            LinkFilesRecursive "zsh" target="{}" {{
+                ignore "*.md"
+            }}
+            "#,
+            target_dir
+        ))
+        .unwrap();
+        let linker = Linker::new(config_dir_or_cwd.clone(), PathResolution::Config);
+        let actions = linker.calculate_diff(&config, &lockfile, false).unwrap();
+        let removed: Vec<_> = actions
+            .iter()
+            .filter_map(|action| match action {
+                LinkAction::Removed { target, .. } => Some(target.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(removed, vec![target_dir.join("README.md")]);
+        assert_eq!(actions.len(), 2, "{:?}", actions);
+
+        // Clean up
+        let _ = fs::remove_dir_all("tests/tmpfs/test_filtered_files_become_orphans");
+    }
+
+    #[test]
+    fn test_reporter_events() {
+        use crate::reporter::RecordingReporter;
+
+        let config_dir_or_cwd = setup_test_fs("test_reporter_events");
+        let target_dir = config_dir_or_cwd.parent().unwrap().join("target");
+        let source_dir = config_dir_or_cwd.join("zsh");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join(".zshrc"), "zshrc").unwrap();
+
+        // A link of a package that is gone from the config
+        let orphan = target_dir.join(".bashrc");
+        let mut lockfile = Lockfile::new("test-host".to_string(), config_dir_or_cwd.clone());
+        lockfile.add_link(orphan.clone(), config_dir_or_cwd.join("bash/.bashrc"));
+
+        let config = DotyConfig::from_str(&format!(
+            r#"LinkFilesRecursive "zsh" target="{}""#,
+            target_dir
+        ))
+        .unwrap();
+        let reporter = Rc::new(RecordingReporter::default());
+        let mut linker = Linker::new(config_dir_or_cwd.clone(), PathResolution::Config)
+            .with_reporter(reporter.clone());
+        let actions = linker.calculate_diff(&config, &lockfile, false).unwrap();
+
+        // The plan knows which package each action belongs to
+        let [Event::PlanComputed(plan)] = &reporter.events()[..] else {
+            panic!("expected only the plan, got {:?}", reporter.events());
+        };
+        assert_eq!(plan.len(), 2, "{:?}", plan);
+        for planned in plan {
+            if *planned.action.target() == orphan {
+                assert_eq!(planned.package, None);
+            } else {
+                assert_eq!(planned.package.as_ref(), Some(&config.packages[0]));
+            }
+        }
+
+        let created = actions
+            .iter()
+            .find(|action| matches!(action, LinkAction::Created { .. }))
+            .unwrap();
+        linker.execute_action(created, false).unwrap();
+        assert_eq!(
+            reporter.events()[1..],
+            [
+                Event::ActionStarted(created.clone()),
+                Event::ActionFinished {
+                    action: created.clone(),
+                    dry_run: false,
+                },
+            ]
+        );
+
+        // Failures are reported too, with the error
+        let blocked = LinkAction::Created {
+            target: target_dir.join(".zshrc/nested"),
+            source: source_dir.join(".zshrc"),
+        };
+        assert!(linker.execute_action(&blocked, false).is_err());
+        assert!(matches!(
+            reporter.events().last(),
+            Some(Event::ActionFailed { action, .. }) if *action == blocked
+        ));
+
+        // Clean up
+        let _ = fs::remove_dir_all("tests/tmpfs/test_reporter_events");
     }
 }
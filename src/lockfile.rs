@@ -1,10 +1,12 @@
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Local};
 use kdl::{KdlDocument, KdlEntry, KdlNode};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::error::DotyError;
 use crate::fs_utils::resolve_target_path;
 
 /// Represents the lockfile of deployed symlinks on a specific machine
@@ -17,6 +19,8 @@ pub struct Lockfile {
     pub base_path: Utf8PathBuf,
     /// Maps target path -> source path for all managed symlinks
     pub links: HashMap<Utf8PathBuf, Utf8PathBuf>,
+    /// Maps target path -> content hash for links that are copies (Copy strategy)
+    pub hashes: HashMap<Utf8PathBuf, String>,
 }
 
 impl Lockfile {
@@ -27,6 +31,7 @@ impl Lockfile {
             lockfile_version: 1,
             base_path,
             links: HashMap::new(),
+            hashes: HashMap::new(),
         }
     }
 
@@ -42,10 +47,13 @@ impl Lockfile {
             return Ok(Self::new(hostname.to_string(), base_path));
         }
 
-        let content = fs::read_to_string(&lockfile_path)
-            .with_context(|| format!("Failed to read lockfile: {}", lockfile_path.display()))?;
+        let path = Utf8PathBuf::from(lockfile_path.to_string_lossy().as_ref());
+        let content = fs::read_to_string(&lockfile_path).map_err(|err| {
+            DotyError::io(&path, format!("Failed to read lockfile: {}", path), err)
+        })?;
 
         Self::from_str(&content, hostname)
+            .map_err(|source| DotyError::LockfileCorrupt { path, source }.into())
     }
 
     /// Parse lockfile from KDL string
@@ -57,6 +65,7 @@ impl Lockfile {
         let mut lockfile_version = 1; // Default to version 1
         let mut base_path = Utf8PathBuf::from("."); // Default base path
         let mut links = HashMap::new();
+        let mut hashes = HashMap::new();
 
         for node in doc.nodes() {
             match node.name().value() {
@@ -74,6 +83,9 @@ impl Lockfile {
                 }
                 "link" => {
                     let (source, target) = Self::parse_link_node(node)?;
+                    if let Some(hash) = node.get("hash").and_then(|v| v.as_string()) {
+                        hashes.insert(target.clone(), hash.to_string());
+                    }
                     links.insert(target, source);
                 }
                 _ => {}
@@ -84,19 +96,25 @@ impl Lockfile {
         let normalized_links: HashMap<Utf8PathBuf, Utf8PathBuf> = links
             .into_iter()
             .map(|(target, source)| {
-                let abs_target =
-                    Self::normalize_to_absolute(&target, &base_path).unwrap_or_else(|_| target);
-                let abs_source =
-                    Self::normalize_to_absolute(&source, &base_path).unwrap_or_else(|_| source);
+                let abs_target = Self::normalize_to_absolute(&target, &base_path).unwrap_or(target);
+                let abs_source = Self::normalize_to_absolute(&source, &base_path).unwrap_or(source);
                 (abs_target, abs_source)
             })
             .collect();
+        let normalized_hashes: HashMap<Utf8PathBuf, String> = hashes
+            .into_iter()
+            .map(|(target, hash)| {
+                let abs_target = Self::normalize_to_absolute(&target, &base_path).unwrap_or(target);
+                (abs_target, hash)
+            })
+            .collect();
 
         Ok(Lockfile {
             hostname: hostname.to_string(),
             lockfile_version,
             base_path,
             links: normalized_links,
+            hashes: normalized_hashes,
         })
     }
 
@@ -109,10 +127,10 @@ impl Lockfile {
             if let Some(name) = entry.name() {
                 match name.value() {
                     "target" => {
-                        target = entry.value().as_string().map(|s| Utf8PathBuf::from(s));
+                        target = entry.value().as_string().map(Utf8PathBuf::from);
                     }
                     "source" => {
-                        source = entry.value().as_string().map(|s| Utf8PathBuf::from(s));
+                        source = entry.value().as_string().map(Utf8PathBuf::from);
                     }
                     _ => {}
                 }
@@ -141,8 +159,10 @@ impl Lockfile {
             .join(format!("{}.lock.kdl", self.hostname));
 
         let content = self.to_kdl();
-        fs::write(&lockfile_path, content)
-            .with_context(|| format!("Failed to write lockfile: {}", lockfile_path.display()))?;
+        fs::write(&lockfile_path, content).map_err(|err| {
+            let path = Utf8PathBuf::from(lockfile_path.to_string_lossy().as_ref());
+            DotyError::io(&path, format!("Failed to write lockfile: {}", path), err)
+        })?;
 
         Ok(())
     }
@@ -170,6 +190,9 @@ impl Lockfile {
             let mut node = KdlNode::new("link");
             node.push(KdlEntry::new_prop("source", source.as_str()));
             node.push(KdlEntry::new_prop("target", target.as_str()));
+            if let Some(hash) = self.hashes.get(target) {
+                node.push(KdlEntry::new_prop("hash", hash.as_str()));
+            }
             doc.nodes_mut().push(node);
         }
 
@@ -195,25 +218,41 @@ impl Lockfile {
         Ok(base_path.join(path))
     }
 
+    /// Whether both lockfiles record the same links and copy hashes
+    pub fn same_state(&self, other: &Lockfile) -> bool {
+        self.links == other.links && self.hashes == other.hashes
+    }
+
     /// Add a link to the lockfile (paths are normalized to absolute)
     pub fn add_link(&mut self, target: Utf8PathBuf, source: Utf8PathBuf) {
         // Normalize both paths to absolute
-        let abs_target =
-            Self::normalize_to_absolute(&target, &self.base_path).unwrap_or_else(|_| target);
-        let abs_source =
-            Self::normalize_to_absolute(&source, &self.base_path).unwrap_or_else(|_| source);
+        let abs_target = Self::normalize_to_absolute(&target, &self.base_path).unwrap_or(target);
+        let abs_source = Self::normalize_to_absolute(&source, &self.base_path).unwrap_or(source);
         self.links.insert(abs_target, abs_source);
     }
 
+    /// Add a copied file to the lockfile together with the hash of the content written
+    pub fn add_copy(&mut self, target: Utf8PathBuf, source: Utf8PathBuf, hash: String) {
+        let abs_target = Self::normalize_to_absolute(&target, &self.base_path)
+            .unwrap_or_else(|_| target.clone());
+        self.add_link(target, source);
+        self.hashes.insert(abs_target, hash);
+    }
+
     /// Remove a link from the lockfile
     /// Normalizes the target path to absolute before removing
     pub fn remove_link(&mut self, target: &Utf8Path) -> Option<Utf8PathBuf> {
-        if let Ok(abs_target) = Self::normalize_to_absolute(target, &self.base_path) {
-            self.links.remove(&abs_target)
-        } else {
-            // Fallback to direct remove if normalization fails
-            self.links.remove(target)
-        }
+        let abs_target = Self::normalize_to_absolute(target, &self.base_path)
+            .unwrap_or_else(|_| target.to_path_buf());
+        self.hashes.remove(&abs_target);
+        self.links.remove(&abs_target)
+    }
+
+    /// Get the content hash of a copied target (None for symlinks)
+    pub fn get_hash(&self, target: &Utf8Path) -> Option<&String> {
+        let abs_target = Self::normalize_to_absolute(target, &self.base_path)
+            .unwrap_or_else(|_| target.to_path_buf());
+        self.hashes.get(&abs_target)
     }
 
     /// Check if a target is managed by Doty
@@ -229,6 +268,7 @@ impl Lockfile {
 
     /// Get the source path for a target
     /// Normalizes the target path to absolute before looking up
+    #[allow(dead_code)] // Only used in tests so far
     pub fn get_source(&self, target: &Utf8Path) -> Option<&Utf8PathBuf> {
         if let Ok(abs_target) = Self::normalize_to_absolute(target, &self.base_path) {
             self.links.get(&abs_target)
@@ -239,6 +279,149 @@ impl Lockfile {
     }
 }
 
+/// Snapshot of the lockfile after a successful link, clean or rollback
+/// Stored as `.doty/state/generations/<hostname>/<number>.lock.kdl`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub number: u32,
+    pub created: DateTime<Local>,
+    /// Command that produced it, e.g. `link` or `rollback 3`
+    pub command: String,
+    /// Short summary of the applied actions
+    pub summary: String,
+    pub lockfile: Lockfile,
+}
+
+/// Generations kept per host, the oldest ones are deleted when a new one is recorded
+pub const KEEP_GENERATIONS: usize = 50;
+
+impl Generation {
+    fn dir<P: AsRef<Path>>(lockfile_dir: P, hostname: &str) -> PathBuf {
+        lockfile_dir.as_ref().join("generations").join(hostname)
+    }
+
+    /// All generations of a host, oldest first
+    pub fn list<P: AsRef<Path>>(lockfile_dir: P, hostname: &str) -> Result<Vec<Generation>> {
+        let dir = Self::dir(lockfile_dir, hostname);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut generations = Vec::new();
+        for entry in fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read generations: {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(".lock.kdl") {
+                continue;
+            }
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read generation: {}", path.display()))?;
+            generations.push(
+                Self::from_str(&content, hostname)
+                    .with_context(|| format!("Invalid generation: {}", path.display()))?,
+            );
+        }
+
+        generations.sort_by_key(|g| g.number);
+        Ok(generations)
+    }
+
+    /// Write the lockfile as the next generation and prune the oldest beyond KEEP_GENERATIONS
+    /// Returns None if its links and copy hashes are the same as in the latest generation
+    pub fn record<P: AsRef<Path>>(
+        lockfile_dir: P,
+        lockfile: &Lockfile,
+        command: &str,
+        summary: &str,
+    ) -> Result<Option<u32>> {
+        let generations = Self::list(&lockfile_dir, &lockfile.hostname)?;
+        if let Some(latest) = generations.last() {
+            if latest.lockfile.same_state(lockfile) {
+                return Ok(None);
+            }
+        }
+
+        let generation = Generation {
+            number: generations.last().map_or(1, |g| g.number + 1),
+            created: Local::now(),
+            command: command.to_string(),
+            summary: summary.to_string(),
+            lockfile: lockfile.clone(),
+        };
+
+        let dir = Self::dir(&lockfile_dir, &lockfile.hostname);
+        fs::create_dir_all(&dir).with_context(|| {
+            format!("Failed to create generations directory: {}", dir.display())
+        })?;
+        let path = dir.join(format!("{}.lock.kdl", generation.number));
+        fs::write(&path, generation.to_kdl())
+            .with_context(|| format!("Failed to write generation: {}", path.display()))?;
+        Self::prune(&lockfile_dir, &lockfile.hostname, KEEP_GENERATIONS)?;
+
+        Ok(Some(generation.number))
+    }
+
+    /// Delete all but the newest `keep` generations of a host, returns how many were deleted
+    pub fn prune<P: AsRef<Path>>(lockfile_dir: P, hostname: &str, keep: usize) -> Result<usize> {
+        let generations = Self::list(&lockfile_dir, hostname)?;
+        let excess = generations.len().saturating_sub(keep);
+        let dir = Self::dir(&lockfile_dir, hostname);
+        for generation in &generations[..excess] {
+            let path = dir.join(format!("{}.lock.kdl", generation.number));
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete generation: {}", path.display()))?;
+        }
+        Ok(excess)
+    }
+
+    /// Parse a generation file: header nodes followed by a regular lockfile
+    fn from_str(content: &str, hostname: &str) -> Result<Self> {
+        let doc: KdlDocument = content
+            .parse()
+            .context("Failed to parse KDL generation document")?;
+        let value = |name: &str| {
+            doc.get_arg(name)
+                .with_context(|| format!("Missing '{}' in generation", name))
+        };
+
+        let number = value("generation")?
+            .as_integer()
+            .context("'generation' must be a number")? as u32;
+        let created = value("created")?
+            .as_string()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .context("Invalid 'created' timestamp in generation")?
+            .with_timezone(&Local);
+        let text = |name: &str| -> Result<String> {
+            Ok(value(name)?.as_string().unwrap_or_default().to_string())
+        };
+
+        Ok(Generation {
+            number,
+            created,
+            command: text("command")?,
+            summary: text("summary")?,
+            lockfile: Lockfile::from_str(content, hostname)?,
+        })
+    }
+
+    fn to_kdl(&self) -> String {
+        let mut doc = KdlDocument::new();
+        let mut push = |name: &str, entry: KdlEntry| {
+            let mut node = KdlNode::new(name);
+            node.push(entry);
+            doc.nodes_mut().push(node);
+        };
+        push("generation", KdlEntry::new(self.number as i128));
+        push("created", KdlEntry::new(self.created.to_rfc3339()));
+        push("command", KdlEntry::new(self.command.as_str()));
+        push("summary", KdlEntry::new(self.summary.as_str()));
+
+        format!("{}{}", doc, self.lockfile.to_kdl())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -318,6 +501,35 @@ mod tests {
         assert!(lockfile.is_managed(&Utf8PathBuf::from("~/.zshrc")));
     }
 
+    #[test]
+    fn test_copy_hashes() {
+        let mut lockfile = Lockfile::new("test-host".to_string(), Utf8PathBuf::from("/test/base"));
+        lockfile.add_copy(
+            Utf8PathBuf::from("/home/user/.config/Code/settings.json"),
+            Utf8PathBuf::from("vscode/settings.json"),
+            "sha256:abc".to_string(),
+        );
+        lockfile.add_link(
+            Utf8PathBuf::from("~/.zshrc"),
+            Utf8PathBuf::from("zsh/.zshrc"),
+        );
+
+        let kdl = lockfile.to_kdl();
+        assert!(kdl.contains("sha256:abc"));
+
+        let parsed = Lockfile::from_str(&kdl, "test-host").unwrap();
+        assert_eq!(parsed, lockfile);
+        assert_eq!(
+            parsed.get_hash(Utf8Path::new("/home/user/.config/Code/settings.json")),
+            Some(&"sha256:abc".to_string())
+        );
+        assert_eq!(parsed.get_hash(Utf8Path::new("~/.zshrc")), None);
+
+        let mut parsed = parsed;
+        parsed.remove_link(Utf8Path::new("/home/user/.config/Code/settings.json"));
+        assert!(parsed.hashes.is_empty());
+    }
+
     #[test]
     fn test_roundtrip() {
         let mut lockfile = Lockfile::new("test-host".to_string(), Utf8PathBuf::from("/test/base"));
@@ -439,4 +651,86 @@ mod tests {
         // Clean up
         let _ = fs::remove_dir_all(test_dir);
     }
+
+    #[test]
+    fn test_generations_record_and_list() {
+        let test_dir = "tests/tmpfs/test_generations_record_and_list";
+        let _ = fs::remove_dir_all(test_dir);
+        let lockfile_dir = format!("{}/.doty/state", test_dir);
+
+        let mut lockfile = Lockfile::new("test-host".to_string(), Utf8PathBuf::from("/test/base"));
+        lockfile.add_link(
+            Utf8PathBuf::from("~/.config/nvim"),
+            Utf8PathBuf::from("nvim"),
+        );
+        assert_eq!(
+            Generation::record(&lockfile_dir, &lockfile, "link", "1 added").unwrap(),
+            Some(1)
+        );
+
+        // Same links again: no new generation
+        assert_eq!(
+            Generation::record(&lockfile_dir, &lockfile, "link", "no changes").unwrap(),
+            None
+        );
+
+        lockfile.add_link(
+            Utf8PathBuf::from("~/.zshrc"),
+            Utf8PathBuf::from("zsh/.zshrc"),
+        );
+        assert_eq!(
+            Generation::record(&lockfile_dir, &lockfile, "rollback 1", "1 added").unwrap(),
+            Some(2)
+        );
+
+        let generations = Generation::list(&lockfile_dir, "test-host").unwrap();
+        assert_eq!(generations.len(), 2);
+        assert_eq!(generations[0].number, 1);
+        assert_eq!(generations[0].lockfile.links.len(), 1);
+        assert_eq!(generations[1].command, "rollback 1");
+        assert_eq!(generations[1].summary, "1 added");
+        assert_eq!(generations[1].lockfile, lockfile);
+
+        // A copy with new content is a new generation even though the links are the same
+        lockfile.add_copy(
+            Utf8PathBuf::from("~/.gitconfig"),
+            Utf8PathBuf::from("git/config"),
+            "sha256:1".to_string(),
+        );
+        assert_eq!(
+            Generation::record(&lockfile_dir, &lockfile, "link", "1 written").unwrap(),
+            Some(3)
+        );
+        lockfile.add_copy(
+            Utf8PathBuf::from("~/.gitconfig"),
+            Utf8PathBuf::from("git/config"),
+            "sha256:2".to_string(),
+        );
+        assert_eq!(
+            Generation::record(&lockfile_dir, &lockfile, "link", "1 written").unwrap(),
+            Some(4)
+        );
+
+        // Pruning keeps the newest generations, numbers keep counting up
+        assert_eq!(Generation::prune(&lockfile_dir, "test-host", 2).unwrap(), 2);
+        let numbers: Vec<u32> = Generation::list(&lockfile_dir, "test-host")
+            .unwrap()
+            .iter()
+            .map(|g| g.number)
+            .collect();
+        assert_eq!(numbers, [3, 4]);
+        assert_eq!(
+            Generation::record(
+                &lockfile_dir,
+                &Lockfile::new("test-host".to_string(), Utf8PathBuf::from("/test/base")),
+                "clean",
+                "2 removed"
+            )
+            .unwrap(),
+            Some(5)
+        );
+
+        // Clean up
+        let _ = fs::remove_dir_all(test_dir);
+    }
 }
@@ -1,14 +1,16 @@
 mod commands;
-mod config;
-mod fs_utils;
-mod linker;
-mod lockfile;
-mod scanner;
+mod format;
 
 use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand};
 use colored::Colorize;
-use std::env;
+use doty::config::LinkStrategy;
+use doty::fs_utils::current_dir;
+use doty::output::{self, JsonFormat, JsonReporter, Outcome};
+use doty::reporter::{QuietReporter, Reporter, TextReporter};
+use doty::DotyError;
+use format::{say, OutputFormat};
+use std::rc::Rc;
 
 #[derive(Parser)]
 #[command(name = "doty")]
@@ -18,6 +20,12 @@ struct Cli {
     #[arg(short, long, global = true, value_name = "FILE")]
     config: Option<Utf8PathBuf>,
 
+    /// Output format, json and ndjson are supported by link, clean, detect and status
+    ///
+    /// The exit code is the same in every format: 0 success, 1 error, 2 pending changes, 3 drift
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -34,6 +42,10 @@ enum Commands {
         /// Treat warnings as removals (useful for automation)
         #[arg(long)]
         force: bool,
+
+        /// Don't roll back on the first failure, apply everything else and report all failures
+        #[arg(long)]
+        keep_going: bool,
     },
 
     /// Remove all symlinks managed by Doty
@@ -47,7 +59,15 @@ enum Commands {
     /// Import existing local configs into the Doty repo
     Adopt {
         /// Path to the config to adopt
-        path: String,
+        path: Utf8PathBuf,
+
+        /// Link strategy for the new package (prompted if omitted)
+        #[arg(long, value_enum)]
+        strategy: Option<LinkStrategy>,
+
+        /// Destination inside the repo, relative to the repo root (prompted if omitted)
+        #[arg(long, value_name = "PATH")]
+        source: Option<Utf8PathBuf>,
     },
 
     /// Audit targets for untracked files or broken links
@@ -55,75 +75,315 @@ enum Commands {
         /// Run in interactive mode for adoption/cleanup
         #[arg(short = 'i', long)]
         interactive: bool,
+
+        /// Look for symlinks into the repo that are not in the lockfile instead
+        #[arg(long)]
+        stray: bool,
+
+        /// List repo files and directories that no package links instead
+        #[arg(long, conflicts_with_all = ["stray", "interactive"])]
+        unmapped: bool,
+
+        /// Directory to search for stray symlinks (repeatable)
+        #[arg(
+            long = "root",
+            value_name = "DIR",
+            default_value = "~",
+            requires = "stray"
+        )]
+        roots: Vec<Utf8PathBuf>,
     },
 
     /// Show current system health and mapping status
     Status,
+
+    /// List the lockfile generations of this host
+    Generations,
+
+    /// Go back to the links of an earlier generation
+    Rollback {
+        /// Generation number (defaults to the latest one that differs from now)
+        generation: Option<u32>,
+
+        /// Show what would be done without making changes
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't roll back on the first failure, apply everything else and report all failures
+        #[arg(long)]
+        keep_going: bool,
+    },
+
+    /// Put originals that doty moved aside back in place
+    Restore {
+        /// Original path to restore (its most recent backup is used)
+        #[arg(required_unless_present = "all")]
+        path: Option<Utf8PathBuf>,
+
+        /// Restore the most recent backup of every path
+        #[arg(long, conflicts_with = "path")]
+        all: bool,
+    },
+
+    /// Manage the backup store under .doty/backups
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+
+    /// Check doty.kdl and its includes
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Explain an error code like DOTY-E030, or list all codes
+    ExplainError {
+        /// Error code, the DOTY- prefix is optional
+        code: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Parse the config and report errors and ignored settings, without touching any target
+    Check,
+}
+
+#[derive(Subcommand)]
+enum BackupsCommand {
+    /// List all backups of this host
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Delete old backups
+    Prune {
+        /// Number of backups to keep for every path
+        #[arg(long, default_value_t = 1)]
+        keep: usize,
+
+        /// Only delete backups older than this many days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<i64>,
+
+        /// Show what would be done without making changes
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl Commands {
+    /// Command name in json/ndjson output
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Link { .. } => "link",
+            Commands::Clean { .. } => "clean",
+            Commands::Adopt { .. } => "adopt",
+            Commands::Detect { .. } => "detect",
+            Commands::Status => "status",
+            Commands::Generations => "generations",
+            Commands::Rollback { .. } => "rollback",
+            Commands::Restore { .. } => "restore",
+            Commands::Backups { .. } => "backups",
+            Commands::Config { .. } => "config",
+            Commands::ExplainError { .. } => "explain-error",
+        }
+    }
+
+    /// Whether the command reports in json/ndjson (prompts and other commands only print text)
+    fn supports_json(&self) -> bool {
+        match self {
+            Commands::Link { .. } | Commands::Clean { .. } | Commands::Status => true,
+            Commands::Detect { interactive, .. } => !interactive,
+            _ => false,
+        }
+    }
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let cli = Cli::parse();
+    format::set_format(cli.output);
+
+    let reporter: Rc<dyn Reporter> = match cli.output {
+        OutputFormat::Text => Rc::new(TextReporter::default()),
+        OutputFormat::Json => Rc::new(JsonReporter::new(cli.command.name(), JsonFormat::Document)),
+        OutputFormat::Ndjson => Rc::new(JsonReporter::new(cli.command.name(), JsonFormat::Lines)),
+        OutputFormat::Quiet => Rc::new(QuietReporter),
+    };
+    let json = matches!(cli.output, OutputFormat::Json | OutputFormat::Ndjson);
+
+    let result = run(cli, &reporter);
+    reporter.finish(&result);
+
+    // The exit code tells what happened, json/ndjson have the error in the output too
+    let code = match result {
+        Ok(outcome) => outcome.exit_code(),
+        Err(err) => {
+            if !json {
+                print_error(&err);
+            }
+            output::EXIT_ERROR
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Print an error with its causes (like anyhow does) and the suggested fix of its error code
+fn print_error(err: &anyhow::Error) {
+    eprintln!("Error: {:?}", err);
+    let Some(error) = DotyError::find(err) else {
+        return;
+    };
+    if let (Some(code), Some(hint)) = (error.code(), error.hint()) {
+        eprintln!("\n{} {}", format!("hint[{}]:", code).yellow().bold(), hint);
+        eprintln!(
+            "{}",
+            format!("Run 'doty explain-error {}' for more help", code).dimmed()
+        );
+    }
+}
+
+fn run(cli: Cli, reporter: &Rc<dyn Reporter>) -> anyhow::Result<Outcome> {
+    let json = matches!(cli.output, OutputFormat::Json | OutputFormat::Ndjson);
+    if json && !cli.command.supports_json() {
+        anyhow::bail!(
+            "JSON output is only supported by link, clean, status and non-interactive detect"
+        );
+    }
 
     // Determine config file path
-    let config_path = if let Some(config) = cli.config {
-        config
-    } else {
+    let config_path = match cli.config {
+        Some(config) => config,
         // Default to doty.kdl in current directory
-        let cwd = Utf8PathBuf::from_path_buf(env::current_dir()?)
-            .map_err(|_| anyhow::anyhow!("Current directory path is not valid UTF-8"))?;
-        cwd.join("doty.kdl")
+        None => current_dir()?.join("doty.kdl"),
     };
 
-    // Check if config file exists
-    if !config_path.as_std_path().exists() {
-        anyhow::bail!("Config file not found: {}", config_path);
+    // Check if config file exists (explain-error works without one)
+    let needs_config = !matches!(cli.command, Commands::ExplainError { .. });
+    if needs_config && !config_path.as_std_path().exists() {
+        return Err(DotyError::ConfigNotFound { path: config_path }.into());
     }
 
     match cli.command {
-        Commands::Link { dry_run, force } => {
+        Commands::Link {
+            dry_run,
+            force,
+            keep_going,
+        } => {
             if dry_run {
-                println!("\n{} {}", "Linking 🔗".bold(), "[DRY RUN]".yellow().bold());
+                say!("\n{} {}", "Linking 🔗".bold(), "[DRY RUN]".yellow().bold());
             } else {
-                println!("\n{}", "Linking 🔗".bold());
+                say!("\n{}", "Linking 🔗".bold());
             }
             if force {
-                println!(
+                say!(
                     "{} {}",
                     "Mode:".bold(),
                     "FORCE (warnings become removals)".red().bold()
                 );
             }
-            commands::link(config_path, dry_run, force)?;
+            return commands::link(config_path, dry_run, force, keep_going, reporter);
         }
         Commands::Clean { dry_run } => {
             if dry_run {
-                println!("\n{} {}", "Cleaning 🧹".bold(), "[DRY RUN]".yellow().bold());
+                say!("\n{} {}", "Cleaning 🧹".bold(), "[DRY RUN]".yellow().bold());
             } else {
-                println!("\n{}", "Cleaning 🧹".bold());
+                say!("\n{}", "Cleaning 🧹".bold());
             }
-            commands::clean(config_path, dry_run)?;
+            return commands::clean(config_path, dry_run, reporter);
         }
-        Commands::Adopt { path } => {
-            println!("\n{} {}: {}", "Adopting 📦".bold(), "for path".bold(), path);
-            println!("Not yet implemented");
+        Commands::Adopt {
+            path,
+            strategy,
+            source,
+        } => {
+            say!("\n{} {}: {}", "Adopting 📦".bold(), "for path".bold(), path);
+            return commands::adopt(config_path, path, strategy, source, reporter);
         }
-        Commands::Detect { interactive } => {
+        Commands::Detect {
+            interactive,
+            stray,
+            unmapped,
+            roots,
+        } => {
+            let title = if stray {
+                "Detecting stray symlinks 🔍"
+            } else if unmapped {
+                "Detecting unmapped sources 🔍"
+            } else {
+                "Detecting unmonitored files 🔍"
+            };
             if interactive {
-                println!(
+                say!("\n{} {}", title.bold(), "[INTERACTIVE]".yellow().bold());
+            } else {
+                say!("\n{}", title.bold());
+            }
+            return if stray {
+                commands::detect_stray(config_path, &roots, interactive, reporter)
+            } else if unmapped {
+                commands::detect_unmapped(config_path, reporter)
+            } else {
+                commands::detect(config_path, interactive, reporter)
+            };
+        }
+        Commands::Status => {
+            say!("\n{}", "Status 📊".bold());
+            return commands::status(config_path, reporter);
+        }
+        Commands::Generations => {
+            say!("\n{}", "Generations 🕰️".bold());
+            commands::generations(config_path)?;
+        }
+        Commands::Rollback {
+            generation,
+            dry_run,
+            keep_going,
+        } => {
+            if dry_run {
+                say!(
                     "\n{} {}",
-                    "Detecting unmonitored files 🔍".bold(),
-                    "[INTERACTIVE]".yellow().bold()
+                    "Rolling back ⏪".bold(),
+                    "[DRY RUN]".yellow().bold()
                 );
             } else {
-                println!("\n{}", "Detecting unmonitored files 🔍".bold());
+                say!("\n{}", "Rolling back ⏪".bold());
             }
-            commands::detect(config_path, interactive)?;
+            return commands::rollback(config_path, generation, dry_run, keep_going, reporter);
         }
-        Commands::Status => {
-            println!("\n{}", "Status 📊".bold());
-            println!("Not yet implemented");
+        Commands::Restore { path, all } => {
+            say!("\n{}", "Restoring backups ♻️".bold());
+            commands::restore(config_path, path, all)?;
+        }
+        Commands::Backups { command } => match command {
+            BackupsCommand::List => {
+                say!("\n{}", "Backups 🗄️".bold());
+                commands::backups_list(config_path)?;
+            }
+            BackupsCommand::Prune {
+                keep,
+                older_than,
+                dry_run,
+            } => {
+                if dry_run {
+                    say!(
+                        "\n{} {}",
+                        "Pruning backups 🗄️".bold(),
+                        "[DRY RUN]".yellow().bold()
+                    );
+                } else {
+                    say!("\n{}", "Pruning backups 🗄️".bold());
+                }
+                commands::backups_prune(config_path, keep, older_than, dry_run)?;
+            }
+        },
+        Commands::Config {
+            command: ConfigCommand::Check,
+        } => {
+            say!("\n{}", "Checking config 🩺".bold());
+            commands::config_check(config_path)?;
         }
+        Commands::ExplainError { code } => commands::explain_error(code.as_deref())?,
     }
 
-    Ok(())
+    Ok(Outcome::Success)
 }
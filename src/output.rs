@@ -0,0 +1,463 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use crate::error::DotyError;
+use crate::linker::{LinkAction, LinkHealth};
+use crate::reporter::{Event, Reporter};
+use crate::scanner::{DriftItem, DriftType};
+
+/// Version of the JSON output layout, bumped when a field is renamed, removed or changes meaning
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Exit code of a failed command
+pub const EXIT_ERROR: i32 = 1;
+
+/// Layout of the `JsonReporter` (`--output json` or `--output ndjson`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// One JSON document when the command is done
+    Document,
+    /// One JSON object per line, written while the command runs
+    Lines,
+}
+
+/// How a command ended, decides the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing left to do
+    Success,
+    /// Changes `doty link` would make (dry runs, links pending in status)
+    Pending,
+    /// Targets differ from what doty manages (detect, unhealthy links in status)
+    Drift,
+}
+
+impl Outcome {
+    /// Exit code of the command
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Pending => 2,
+            Outcome::Drift => 3,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Pending => "pending",
+            Outcome::Drift => "drift",
+        }
+    }
+}
+
+/// Writes the events of a command run as JSON (`--output json` and `--output ndjson`)
+///
+/// JSON mode writes a single document when the command is done:
+/// `{"schemaVersion": 1, "command": "link", "outcome": "success", "actions": [...], "summary": {...}}`.
+/// NDJSON mode writes every record as its own line right away (`"type": "action"`, ...) and ends
+/// with a `"type": "summary"` or `"type": "error"` line. Every object carries `schemaVersion`.
+pub struct JsonReporter {
+    command: &'static str,
+    format: JsonFormat,
+    fields: RefCell<Map<String, Value>>,
+    lists: RefCell<Map<String, Value>>,
+    summary: RefCell<Map<String, Value>>,
+    warnings: RefCell<HashSet<String>>,
+}
+
+impl JsonReporter {
+    /// Start the report of a command
+    pub fn new(command: &'static str, format: JsonFormat) -> Self {
+        Self {
+            command,
+            format,
+            fields: RefCell::new(Map::new()),
+            lists: RefCell::new(Map::new()),
+            summary: RefCell::new(Map::new()),
+            warnings: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Add a record to the `list` of the JSON document, NDJSON writes it as `"type": kind` now
+    fn record(&self, list: &str, kind: &str, record: impl Serialize) {
+        let value = serde_json::to_value(record).unwrap_or(Value::Null);
+        match self.format {
+            JsonFormat::Lines => self.write_line(kind, value),
+            JsonFormat::Document => {
+                if let Value::Array(items) = self
+                    .lists
+                    .borrow_mut()
+                    .entry(list)
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                {
+                    items.push(value);
+                }
+            }
+        }
+    }
+
+    /// Set fields of the summary
+    fn summary(&self, fields: impl Serialize) {
+        if let Ok(Value::Object(mut fields)) = serde_json::to_value(fields) {
+            self.summary.borrow_mut().append(&mut fields);
+        }
+    }
+
+    /// `schemaVersion` and `command` (and `type` for NDJSON lines) go first in every object
+    fn header(&self, kind: Option<&str>) -> Map<String, Value> {
+        let mut object = Map::new();
+        object.insert("schemaVersion".into(), SCHEMA_VERSION.into());
+        object.insert("command".into(), self.command.into());
+        if let Some(kind) = kind {
+            object.insert("type".into(), kind.into());
+        }
+        object
+    }
+
+    fn write_line(&self, kind: &str, value: Value) {
+        let mut line = self.header(Some(kind));
+        match value {
+            Value::Object(mut fields) => line.append(&mut fields),
+            other => {
+                line.insert("value".into(), other);
+            }
+        }
+        println!("{}", Value::Object(line));
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::RepoLoaded {
+                config_path,
+                base_path,
+            } => {
+                let repo = serde_json::json!({
+                    "config": config_path.as_str(),
+                    "basePath": base_path.as_str(),
+                });
+                match self.format {
+                    JsonFormat::Lines => self.write_line("repo", repo),
+                    JsonFormat::Document => {
+                        if let Value::Object(mut repo) = repo {
+                            self.fields.borrow_mut().append(&mut repo);
+                        }
+                    }
+                }
+            }
+            Event::ActionFinished { action, dry_run } => {
+                let status = if *dry_run { "planned" } else { "applied" };
+                self.record("actions", "action", ActionRecord::new(action, status));
+            }
+            Event::ActionFailed { action, error } => {
+                let mut record = ActionRecord::new(action, "failed");
+                record.error = Some(error.clone());
+                self.record("actions", "action", record);
+            }
+            Event::DriftFound(item) => {
+                self.record("drift", "drift", DriftRecord::from(item.as_ref()))
+            }
+            Event::LinkChecked {
+                target,
+                package,
+                health,
+            } => self.record(
+                "links",
+                "link",
+                LinkRecord {
+                    target: target.as_str(),
+                    package: package.as_ref().map(|p| p.as_str()),
+                    health: health_name(*health),
+                },
+            ),
+            Event::Unmapped {
+                path,
+                directory,
+                suggestion,
+            } => self.record(
+                "unmapped",
+                "unmapped",
+                UnmappedRecord {
+                    path: path.as_str(),
+                    directory: *directory,
+                    suggestion,
+                },
+            ),
+            Event::Warning(message) => {
+                if self.warnings.borrow_mut().insert(message.clone()) {
+                    self.record(
+                        "warnings",
+                        "warning",
+                        serde_json::json!({ "message": message }),
+                    );
+                }
+            }
+            Event::ActionsSummary { counts, dry_run } => {
+                self.summary(counts);
+                self.summary(serde_json::json!({ "dryRun": dry_run }));
+            }
+            Event::Counts(counts) => {
+                let counts: Map<String, Value> = counts
+                    .iter()
+                    .map(|(key, count)| (key.to_string(), Value::from(*count)))
+                    .collect();
+                self.summary(counts);
+            }
+            Event::PlanComputed(_) | Event::ActionStarted(_) => {}
+        }
+    }
+
+    /// Write the JSON document or the last NDJSON line
+    fn finish(&self, result: &anyhow::Result<Outcome>) {
+        let mut end = Map::new();
+        match result {
+            Ok(outcome) => {
+                end.insert("outcome".into(), outcome.as_str().into());
+            }
+            Err(err) => {
+                end.insert("outcome".into(), "error".into());
+                end.insert("error".into(), format!("{:#}", err).into());
+                if let Some(error) = DotyError::find(err) {
+                    end.insert("errorCode".into(), error.code().into());
+                    end.insert("hint".into(), error.hint().into());
+                }
+            }
+        }
+
+        let summary = self.summary.take();
+        match self.format {
+            JsonFormat::Lines => {
+                let kind = if result.is_ok() { "summary" } else { "error" };
+                end.extend(summary);
+                self.write_line(kind, Value::Object(end));
+            }
+            JsonFormat::Document => {
+                let mut document = self.header(None);
+                document.append(&mut self.fields.borrow_mut());
+                document.append(&mut end);
+                document.append(&mut self.lists.borrow_mut());
+                document.insert("summary".into(), Value::Object(summary));
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&document).unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+/// A `LinkAction` with stable field names
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionRecord<'a> {
+    /// `created`, `updated`, `unchanged`, `removed`, `pruned`, `warning`, `conflict`, `copied`
+    /// or `deleted`
+    pub action: &'static str,
+    pub target: &'a str,
+    pub source: &'a str,
+    /// `planned` (dry run or not started), `applied` or `failed`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// What sits at the target of a conflict (`file`, `directory`, `symlink`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub existing: Option<String>,
+    /// onConflict policy of a conflict
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Whether a copy is a rendered template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<bool>,
+    /// Why a failed action failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<'a> ActionRecord<'a> {
+    pub fn new(action: &'a LinkAction, status: &'static str) -> Self {
+        let mut record = Self {
+            action: "",
+            target: action.target().as_str(),
+            source: "",
+            status,
+            old_source: None,
+            message: None,
+            existing: None,
+            policy: None,
+            template: None,
+            error: None,
+        };
+        match action {
+            LinkAction::Created { source, .. } => {
+                record.action = "created";
+                record.source = source.as_str();
+            }
+            LinkAction::Updated {
+                old_source,
+                new_source,
+                ..
+            } => {
+                record.action = "updated";
+                record.source = new_source.as_str();
+                record.old_source = Some(old_source.as_str());
+            }
+            LinkAction::Skipped { source, .. } => {
+                record.action = "unchanged";
+                record.source = source.as_str();
+            }
+            LinkAction::Removed { source, .. } => {
+                record.action = "removed";
+                record.source = source.as_str();
+            }
+            LinkAction::Pruned { source, .. } => {
+                record.action = "pruned";
+                record.source = source.as_str();
+            }
+            LinkAction::Warning {
+                source, message, ..
+            } => {
+                record.action = "warning";
+                record.source = source.as_str();
+                record.message = Some(message.clone());
+            }
+            LinkAction::Conflict {
+                source,
+                existing,
+                policy,
+                template,
+                ..
+            } => {
+                record.action = "conflict";
+                record.source = source.as_str();
+                record.existing = Some(existing.to_string());
+                record.policy = Some(policy.to_string());
+                record.template = Some(*template);
+            }
+            LinkAction::Copied {
+                source, template, ..
+            } => {
+                record.action = "copied";
+                record.source = source.as_str();
+                record.template = Some(*template);
+            }
+            LinkAction::Deleted { source, .. } => {
+                record.action = "deleted";
+                record.source = source.as_str();
+            }
+        }
+        record
+    }
+}
+
+/// A `DriftItem` with stable field names
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftRecord<'a> {
+    /// `untracked`, `broken`, `modified`, `orphaned` or `stray`
+    pub drift: &'static str,
+    pub target: &'a str,
+    /// Source of the package the target belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<&'a str>,
+    /// Repo source the target should match (modified, orphaned) or points to (stray)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'a str>,
+    /// Where the symlink at the target points
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<&'a str>,
+}
+
+impl<'a> From<&'a DriftItem> for DriftRecord<'a> {
+    fn from(item: &'a DriftItem) -> Self {
+        Self {
+            drift: drift_name(&item.drift_type),
+            target: item.target_path.as_str(),
+            package: item.package.as_ref().map(|p| p.source.as_str()),
+            source: item.source_path.as_ref().map(|p| p.as_str()),
+            symlink_target: item.symlink_target.as_ref().map(|p| p.as_str()),
+        }
+    }
+}
+
+/// A link and its health, in `doty status --output json`
+#[derive(Debug, Serialize)]
+struct LinkRecord<'a> {
+    target: &'a str,
+    /// Source of the package the link belongs to, none for orphaned links
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<&'a str>,
+    health: &'static str,
+}
+
+/// A repo path no package links, in `doty detect --unmapped --output json`
+#[derive(Debug, Serialize)]
+struct UnmappedRecord<'a> {
+    path: &'a str,
+    directory: bool,
+    /// Package node to paste into doty.kdl
+    suggestion: &'a str,
+}
+
+/// Stable name of a drift type in JSON
+pub fn drift_name(drift: &DriftType) -> &'static str {
+    match drift {
+        DriftType::Untracked => "untracked",
+        DriftType::Broken => "broken",
+        DriftType::Modified => "modified",
+        DriftType::Orphaned => "orphaned",
+        DriftType::Stray => "stray",
+    }
+}
+
+/// Stable name of a link health in JSON
+pub fn health_name(health: LinkHealth) -> &'static str {
+    match health {
+        LinkHealth::InSync => "inSync",
+        LinkHealth::PendingCreate => "pendingCreate",
+        LinkHealth::PendingUpdate => "pendingUpdate",
+        LinkHealth::Conflicting => "conflicting",
+        LinkHealth::Broken => "broken",
+        LinkHealth::Orphaned => "orphaned",
+        LinkHealth::Modified => "modified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+
+    #[test]
+    fn test_action_record() {
+        let action = LinkAction::Updated {
+            target: Utf8PathBuf::from("/home/me/.zshrc"),
+            old_source: Utf8PathBuf::from("/repo/old/.zshrc"),
+            new_source: Utf8PathBuf::from("/repo/zsh/.zshrc"),
+        };
+        let value = serde_json::to_value(ActionRecord::new(&action, "applied")).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "action": "updated",
+                "target": "/home/me/.zshrc",
+                "source": "/repo/zsh/.zshrc",
+                "status": "applied",
+                "oldSource": "/repo/old/.zshrc",
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_exit_codes() {
+        assert_eq!(Outcome::Success.exit_code(), 0);
+        assert_eq!(Outcome::Pending.exit_code(), 2);
+        assert_eq!(Outcome::Drift.exit_code(), 3);
+        assert_eq!(EXIT_ERROR, 1);
+    }
+}
@@ -0,0 +1,1178 @@
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+use crate::backup::{BackupEntry, BackupStore};
+use crate::condition::HostInfo;
+use crate::config::{DotyConfig, LinkStrategy, OnConflict, Package, PathResolution};
+use crate::error::{DotyError, Result};
+use crate::fs_utils::{current_dir, get_fs_type, remove_symlink, resolve_target_path, FsType};
+use crate::journal::Journal;
+use crate::linker::{LinkAction, LinkHealth, Linker};
+use crate::lockfile::{Generation, Lockfile};
+use crate::output::{drift_name, health_name};
+use crate::reporter::{ActionCounts, Event, QuietReporter, Reporter};
+use crate::scanner::{DriftItem, DriftType, Scanner};
+use crate::template::TemplateContext;
+
+/// Config, host and base path a command works with
+pub struct Repo {
+    pub hostname: String,
+    pub config: DotyConfig,
+    /// Base path sources are relative to (see `pathResolution`)
+    pub config_dir_or_cwd: Utf8PathBuf,
+    reporter: Rc<dyn Reporter>,
+}
+
+impl Repo {
+    /// Load the config and resolve the base path according to its path resolution strategy
+    pub fn load(config_path: &Utf8Path) -> Result<Self> {
+        let hostname = hostname::get()
+            .context("Failed to get the hostname")?
+            .to_string_lossy()
+            .to_string();
+        let config = DotyConfig::from_file(config_path)?;
+        let config_dir_or_cwd = resolve_config_dir_or_cwd(config_path, config.path_resolution)?;
+        Ok(Self {
+            hostname,
+            config,
+            config_dir_or_cwd,
+            reporter: Rc::new(QuietReporter),
+        })
+    }
+
+    /// Load the repo and tell the reporter which config and base path are used, and what in the
+    /// config was ignored
+    ///
+    /// Linkers, scanners and deployments of this repo report into the same reporter.
+    pub fn open(config_path: &Utf8Path, reporter: &Rc<dyn Reporter>) -> Result<Self> {
+        let repo = Self {
+            reporter: reporter.clone(),
+            ..Self::load(config_path)?
+        };
+        reporter.event(&Event::RepoLoaded {
+            config_path: config_path.to_path_buf(),
+            base_path: repo.config_dir_or_cwd.clone(),
+        });
+        for warning in &repo.config.warnings {
+            reporter.event(&Event::Warning(warning.clone()));
+        }
+        Ok(repo)
+    }
+
+    /// Directory of the lockfiles, generations and journals
+    pub fn lockfile_dir(&self) -> Utf8PathBuf {
+        self.config_dir_or_cwd.join(".doty/state")
+    }
+
+    /// Lockfile of this host
+    pub fn load_lockfile(&self) -> Result<Lockfile> {
+        Lockfile::load(
+            self.lockfile_dir(),
+            &self.hostname,
+            self.config_dir_or_cwd.clone(),
+        )
+        .context("Failed to load lockfile")
+        .map_err(DotyError::from)
+    }
+
+    /// Linker without backups or templates, enough to check and clean links
+    pub fn linker(&self) -> Linker {
+        Linker::new(self.config_dir_or_cwd.clone(), self.config.path_resolution)
+            .with_reporter(self.reporter.clone())
+    }
+
+    pub fn scanner(&self) -> Scanner {
+        Scanner::new(self.config_dir_or_cwd.clone()).with_reporter(self.reporter.clone())
+    }
+
+    /// Targets that differ from what the lockfile says doty manages
+    pub fn scan(&self) -> Result<Vec<DriftItem>> {
+        let lockfile = self.load_lockfile()?;
+        Ok(self
+            .scanner()
+            .scan_targets(&self.config, &lockfile)
+            .context("Failed to scan for drift")?)
+    }
+
+    /// Prepare a link run, rolling back an interrupted previous run first
+    pub fn deployment(&self, options: ApplyOptions) -> Result<Deployment> {
+        Deployment::open(self, options)
+    }
+
+    /// Backups of replaced originals on this host
+    pub fn backup_store(&self) -> Result<BackupStore> {
+        Ok(BackupStore::load(
+            &self.config_dir_or_cwd.join(".doty/backups"),
+            &self.hostname,
+        )
+        .context("Failed to load backup store")?)
+    }
+
+    /// Remove every link in the lockfile, then clear it and record a generation
+    /// The linker reports every removal, the counts are left for the caller to report
+    pub fn clean(&self, dry_run: bool) -> Result<Vec<LinkAction>> {
+        let lockfile = self.load_lockfile()?;
+        let actions = self
+            .linker()
+            .clean(&lockfile, dry_run)
+            .context("Failed to clean links")?;
+
+        if !dry_run {
+            let empty_lockfile =
+                Lockfile::new(self.hostname.clone(), self.config_dir_or_cwd.clone());
+            let lockfile_dir = self.lockfile_dir();
+            empty_lockfile
+                .save(&lockfile_dir)
+                .context("Failed to save lockfile")?;
+            let counts = ActionCounts::of(&actions);
+            record_generation(
+                &self.reporter,
+                &lockfile_dir,
+                &empty_lockfile,
+                "clean",
+                &format!("{} removed", counts.removed),
+            );
+        }
+        Ok(actions)
+    }
+
+    /// Health of every link and package, like `doty status`
+    ///
+    /// Uses the same building blocks as link and detect, so status never disagrees with them.
+    /// Every link is reported as `Event::LinkChecked`, the totals as `Event::Counts`.
+    pub fn status(&self) -> Result<Status> {
+        let lockfile = self.load_lockfile()?;
+        let linker = Linker::new(self.config_dir_or_cwd.clone(), self.config.path_resolution)
+            .with_templates(TemplateContext::new(&self.config.variables)?)
+            .with_reporter(self.reporter.clone());
+        let link_states = linker
+            .gather_link_states(&self.config, &lockfile)
+            .context("Failed to gather link states")?;
+        let drift_items = self
+            .scanner()
+            .scan_targets(&self.config, &lockfile)
+            .context("Failed to scan for drift")?;
+
+        let broken_targets: HashSet<&Utf8PathBuf> = drift_items
+            .iter()
+            .filter(|item| item.drift_type == DriftType::Broken)
+            .map(|item| &item.target_path)
+            .collect();
+
+        // Health of every known target, sorted for stable output
+        let mut links: Vec<LinkState> = link_states
+            .iter()
+            .map(|(target, status)| {
+                let mut health = linker.link_health(status);
+                if broken_targets.contains(target) && health != LinkHealth::Orphaned {
+                    health = LinkHealth::Broken;
+                }
+                LinkState {
+                    target: target.clone(),
+                    package: status.package().cloned(),
+                    recorded: status.is_recorded(),
+                    health,
+                }
+            })
+            .collect();
+        links.sort_by(|a, b| a.target.cmp(&b.target));
+        for link in &links {
+            self.reporter.event(&Event::LinkChecked {
+                target: link.target.clone(),
+                package: link.package.as_ref().map(|p| p.source.clone()),
+                health: link.health,
+            });
+        }
+
+        let host = HostInfo::current();
+        let mut packages = Vec::new();
+        for package in &self.config.packages {
+            packages.push(PackageStatus {
+                source: self.config_dir_or_cwd.join(&package.source),
+                target: resolve_target_path(&package.target, &self.config_dir_or_cwd)?,
+                links: links
+                    .iter()
+                    .filter(|link| link.package.as_ref() == Some(package))
+                    .cloned()
+                    .collect(),
+                untracked: drift_items
+                    .iter()
+                    .filter(|item| {
+                        item.drift_type == DriftType::Untracked
+                            && item.package.as_ref() == Some(package)
+                    })
+                    .count(),
+                skipped: match (&package.when, package.is_active(&host)) {
+                    (Some(when), false) => Some(when.to_string()),
+                    _ => None,
+                },
+                package: package.clone(),
+            });
+        }
+
+        let status = Status {
+            packages,
+            links,
+            lockfile_links: lockfile.links.len(),
+            untracked: drift_items
+                .iter()
+                .filter(|item| item.drift_type == DriftType::Untracked)
+                .count(),
+        };
+
+        let mut counts = vec![
+            ("packages", status.packages.len()),
+            ("lockfileLinks", status.lockfile_links),
+        ];
+        for health in [
+            LinkHealth::InSync,
+            LinkHealth::PendingCreate,
+            LinkHealth::PendingUpdate,
+            LinkHealth::Conflicting,
+            LinkHealth::Broken,
+            LinkHealth::Orphaned,
+            LinkHealth::Modified,
+        ] {
+            let count = status.links.iter().filter(|l| l.health == health).count();
+            counts.push((health_name(health), count));
+        }
+        counts.push(("untracked", status.untracked));
+        self.reporter.event(&Event::Counts(counts));
+
+        Ok(status)
+    }
+
+    /// Drift of the targets grouped the way `doty detect` shows it
+    /// The number of items of each drift type is reported as `Event::Counts`
+    pub fn detect(&self) -> Result<Drift> {
+        let items = self.scan()?;
+        self.report_drift_counts(&items);
+
+        let mut drift = Drift::default();
+        for item in items {
+            match item.drift_type {
+                DriftType::Untracked => {
+                    let Some(package) = &item.package else {
+                        continue;
+                    };
+                    match drift.untracked.iter_mut().find(|(p, _)| p == package) {
+                        Some((_, files)) => files.push(item.target_path),
+                        None => drift
+                            .untracked
+                            .push((package.clone(), vec![item.target_path])),
+                    }
+                }
+                DriftType::Broken => drift.broken.push(item),
+                DriftType::Modified => {
+                    let is_copy = item.package.as_ref().is_some_and(|p| {
+                        matches!(p.strategy, LinkStrategy::Copy | LinkStrategy::Template)
+                    });
+                    if is_copy {
+                        drift.modified_copies.push(item);
+                    } else {
+                        drift.replaced_links.push(item);
+                    }
+                }
+                DriftType::Orphaned => drift.orphaned.push(item),
+                // Only reported by `scan_stray`
+                DriftType::Stray => {}
+            }
+        }
+        Ok(drift)
+    }
+
+    /// Symlinks below `roots` that point into the repo but are not in the lockfile, e.g. after
+    /// migrating from stow or losing the lockfile
+    pub fn scan_stray(&self, roots: &[Utf8PathBuf]) -> Result<Vec<DriftItem>> {
+        let lockfile = self.load_lockfile()?;
+        let stray = self
+            .scanner()
+            .scan_stray(&self.config, &lockfile, roots)
+            .context("Failed to scan for stray symlinks")?;
+        self.report_drift_counts(&stray);
+        Ok(stray)
+    }
+
+    /// Repo files and directories no package links, each with a package node for doty.kdl
+    /// Reported as `Event::Unmapped` with their count in `Event::Counts`
+    pub fn unmapped(&self) -> Result<Vec<Package>> {
+        let unmapped = self
+            .scanner()
+            .scan_unmapped(&self.config)
+            .context("Failed to scan for unmapped sources")?;
+        self.reporter
+            .event(&Event::Counts(vec![("unmapped", unmapped.len())]));
+
+        let packages: Vec<Package> = unmapped
+            .iter()
+            .map(|relative| unmapped_package(relative, &self.config, &self.config_dir_or_cwd))
+            .collect();
+        for package in &packages {
+            self.reporter.event(&Event::Unmapped {
+                path: package.source.clone(),
+                directory: package.strategy == LinkStrategy::LinkFolder,
+                suggestion: package.to_kdl_string(),
+            });
+        }
+        Ok(packages)
+    }
+
+    /// Generations of this host, oldest first
+    pub fn generations(&self) -> Result<Generations> {
+        let lockfile = self.load_lockfile()?;
+        let list = Generation::list(self.lockfile_dir(), &self.hostname)?;
+        let current = list
+            .iter()
+            .rev()
+            .find(|g| g.lockfile.same_state(&lockfile))
+            .map(|g| g.number);
+        Ok(Generations { list, current })
+    }
+
+    /// Put the latest backup of `path` (or of every backed up path, if none) back in place
+    ///
+    /// A symlink doty put there makes way for the original and leaves the lockfile, anything
+    /// else blocks the restore of that path.
+    pub fn restore(&self, path: Option<&Utf8Path>) -> Result<Restored> {
+        let mut lockfile = self.load_lockfile()?;
+        let mut store = self.backup_store()?;
+
+        let entries: Vec<BackupEntry> = match path {
+            Some(path) => vec![store
+                .latest(path)
+                .with_context(|| format!("No backup found for {}", path))?
+                .clone()],
+            None => store.latest_per_path().into_iter().cloned().collect(),
+        };
+
+        let mut restored = Restored::default();
+        for entry in entries {
+            match get_fs_type(&entry.path)? {
+                Some(FsType::Symlink) if lockfile.is_managed(&entry.path) => {
+                    remove_symlink(&entry.path)?;
+                    lockfile.remove_link(&entry.path);
+                }
+                Some(existing) => {
+                    restored.blocked.push((entry, existing));
+                    continue;
+                }
+                None => {}
+            }
+
+            store.restore(&entry.id)?;
+            restored.restored.push(entry);
+        }
+
+        lockfile
+            .save(self.lockfile_dir())
+            .context("Failed to save lockfile")?;
+        Ok(restored)
+    }
+
+    /// Report how many drift items of each type a scan found
+    fn report_drift_counts(&self, items: &[DriftItem]) {
+        let counts = [
+            DriftType::Untracked,
+            DriftType::Broken,
+            DriftType::Modified,
+            DriftType::Orphaned,
+            DriftType::Stray,
+        ]
+        .map(|drift| {
+            let count = items.iter().filter(|item| item.drift_type == drift).count();
+            (drift_name(&drift), count)
+        });
+        self.reporter.event(&Event::Counts(counts.to_vec()));
+    }
+}
+
+/// Health of the links and packages of a repo, see `Repo::status`
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// One entry per package, in config order
+    pub packages: Vec<PackageStatus>,
+    /// Every target doty knows about (config and lockfile), sorted
+    pub links: Vec<LinkState>,
+    /// Links recorded in the lockfile
+    pub lockfile_links: usize,
+    /// Untracked files in the targets of all packages
+    pub untracked: usize,
+}
+
+impl Status {
+    /// Links that are not in sync
+    pub fn unhealthy(&self) -> impl Iterator<Item = &LinkState> {
+        self.links
+            .iter()
+            .filter(|link| link.health != LinkHealth::InSync)
+    }
+
+    /// Whether targets differ from what doty manages, beyond changes `doty link` would apply
+    pub fn drifted(&self) -> bool {
+        self.untracked > 0
+            || self.links.iter().any(|link| {
+                !matches!(
+                    link.health,
+                    LinkHealth::InSync | LinkHealth::PendingCreate | LinkHealth::PendingUpdate
+                )
+            })
+    }
+}
+
+/// Health of one target
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkState {
+    pub target: Utf8PathBuf,
+    /// Package that produces the target, none for links only in the lockfile
+    pub package: Option<Package>,
+    /// Whether the lockfile records the target
+    pub recorded: bool,
+    pub health: LinkHealth,
+}
+
+/// Links of one package, see `Repo::status`
+#[derive(Debug, Clone)]
+pub struct PackageStatus {
+    pub package: Package,
+    /// Source and target with the base path applied
+    pub source: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+    /// Targets the package produces
+    pub links: Vec<LinkState>,
+    /// Untracked files in its target
+    pub untracked: usize,
+    /// `when` condition of a package that doesn't apply to this machine
+    pub skipped: Option<String>,
+}
+
+impl PackageStatus {
+    /// Number of links of each health
+    pub fn counts(&self) -> BTreeMap<LinkHealth, usize> {
+        let mut counts = BTreeMap::new();
+        for link in &self.links {
+            *counts.entry(link.health).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Drift found by `Repo::detect`
+#[derive(Debug, Clone, Default)]
+pub struct Drift {
+    /// Untracked files per package, in the order they were found
+    pub untracked: Vec<(Package, Vec<Utf8PathBuf>)>,
+    /// Links pointing to a source that is gone
+    pub broken: Vec<DriftItem>,
+    /// Copies and rendered templates edited since doty wrote them
+    pub modified_copies: Vec<DriftItem>,
+    /// Links replaced by a file, a directory or a link elsewhere
+    pub replaced_links: Vec<DriftItem>,
+    /// Links whose package is no longer in the config
+    pub orphaned: Vec<DriftItem>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.untracked.is_empty()
+            && self.broken.is_empty()
+            && self.modified_copies.is_empty()
+            && self.replaced_links.is_empty()
+            && self.orphaned.is_empty()
+    }
+}
+
+/// Lockfile snapshots of a host, see `Repo::generations`
+#[derive(Debug, Clone)]
+pub struct Generations {
+    /// Oldest first
+    pub list: Vec<Generation>,
+    /// Newest generation with the links and copies of the current lockfile
+    pub current: Option<u32>,
+}
+
+/// What `Repo::restore` did
+#[derive(Debug, Clone, Default)]
+pub struct Restored {
+    /// Backups put back in place
+    pub restored: Vec<BackupEntry>,
+    /// Backups left in the store because something else is at their path
+    pub blocked: Vec<(BackupEntry, FsType)>,
+}
+
+/// What `Deployment::rollback_plan` would bring back
+#[derive(Debug, Clone)]
+pub struct RollbackPlan {
+    pub generation: Generation,
+    pub actions: Vec<LinkAction>,
+    /// Copies whose content will differ from the generation, generations only record the hash
+    /// of a copy so they get the current source content
+    pub stale_copies: Vec<Utf8PathBuf>,
+}
+
+/// How a deployment applies its plan
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplyOptions {
+    /// Only report what would be done
+    pub dry_run: bool,
+    /// Treat warnings as removals (useful for automation)
+    pub force: bool,
+    /// Don't roll back on the first failure, apply everything else and report all failures
+    pub keep_going: bool,
+}
+
+/// What `Deployment::apply` did
+#[derive(Debug, Clone)]
+pub struct Applied {
+    /// Executed actions (planned ones in a dry run), failed actions left out
+    pub actions: Vec<LinkAction>,
+    /// Targets whose action failed, only with `keep_going`
+    pub failures: Vec<Utf8PathBuf>,
+    pub counts: ActionCounts,
+    /// Targets of conflicts without a policy, a real run would abort (dry run only)
+    pub blocking_conflicts: Vec<Utf8PathBuf>,
+    /// Generation recorded for the saved lockfile, none in a dry run or when nothing changed
+    pub generation: Option<u32>,
+}
+
+/// Lockfile, backups and linker of one host, shared by link and rollback
+pub struct Deployment {
+    pub hostname: String,
+    pub lockfile_dir: Utf8PathBuf,
+    pub lockfile: Lockfile,
+    /// Steps of an interrupted previous run that were rolled back (or would be, in a dry run)
+    pub interrupted: Option<usize>,
+    options: ApplyOptions,
+    linker: Linker,
+    reporter: Rc<dyn Reporter>,
+}
+
+impl Deployment {
+    /// Load lockfile and backup store, rolling back an interrupted previous run first
+    fn open(repo: &Repo, options: ApplyOptions) -> Result<Self> {
+        let hostname = repo.hostname.clone();
+        let lockfile_dir = repo.lockfile_dir();
+        let lockfile = repo.load_lockfile()?;
+
+        let mut backup_store = repo.backup_store()?;
+
+        // A journal left behind means a previous run was interrupted, undo it first
+        let mut interrupted = None;
+        if let Some(mut pending) = Journal::load(&lockfile_dir, &hostname)? {
+            if options.dry_run {
+                interrupted = Some(pending.steps.len());
+            } else {
+                let undone = pending
+                    .rollback(&mut backup_store)
+                    .context("Failed to roll back the interrupted previous run")
+                    .map_err(|source| DotyError::RollbackFailed { source })?;
+                pending.finish()?;
+                interrupted = Some(undone);
+            }
+        }
+
+        // Create linker, replaced originals go to the backup store
+        let linker = Linker::new(repo.config_dir_or_cwd.clone(), repo.config.path_resolution)
+            .with_backup_store(backup_store)
+            .with_templates(TemplateContext::new(&repo.config.variables)?)
+            .with_reporter(repo.reporter.clone());
+
+        Ok(Self {
+            hostname,
+            lockfile_dir,
+            lockfile,
+            interrupted,
+            options,
+            linker,
+            reporter: repo.reporter.clone(),
+        })
+    }
+
+    /// Actions that bring back the links and copies of generation `number`
+    ///
+    /// Without a number, the newest generation that differs from what is deployed is used.
+    /// Every recorded link becomes a single-link package of a snapshot config, copies are
+    /// rendered again if their source belongs to a Template package of `config`.
+    pub fn rollback_plan(&self, config: &DotyConfig, number: Option<u32>) -> Result<RollbackPlan> {
+        let generations = Generation::list(&self.lockfile_dir, &self.hostname)?;
+        let generation = match number {
+            Some(n) => generations
+                .into_iter()
+                .find(|g| g.number == n)
+                .with_context(|| format!("Generation {} not found, see 'doty generations'", n))?,
+            None => generations
+                .into_iter()
+                .rev()
+                .find(|g| !g.lockfile.same_state(&self.lockfile))
+                .context("No earlier generation to roll back to, see 'doty generations'")?,
+        };
+
+        let base = self.lockfile.base_path.clone();
+        let is_template = |source: &Utf8Path| {
+            config.packages.iter().any(|package| {
+                package.strategy == LinkStrategy::Template
+                    && source.starts_with(base.join(&package.source))
+            })
+        };
+        let snapshot = DotyConfig {
+            packages: generation
+                .lockfile
+                .links
+                .iter()
+                .map(|(target, source)| Package {
+                    source: source.clone(),
+                    target: target.clone(),
+                    strategy: match generation.lockfile.hashes.contains_key(target) {
+                        false => LinkStrategy::LinkFolder,
+                        true if is_template(source) => LinkStrategy::Template,
+                        true => LinkStrategy::Copy,
+                    },
+                    on_conflict: None,
+                    when: None,
+                    filter: Default::default(),
+                    detect_ignore: Vec::new(),
+                    detect: None,
+                })
+                .collect(),
+            path_resolution: config.path_resolution,
+            on_conflict: config.on_conflict,
+            variables: config.variables.clone(),
+            filter: Default::default(),
+            files: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let actions = self.plan(&snapshot)?;
+
+        let mut stale_copies: Vec<Utf8PathBuf> = generation
+            .lockfile
+            .hashes
+            .iter()
+            .filter(|(target, hash)| {
+                let planned = actions.iter().find_map(|action| match action {
+                    LinkAction::Copied {
+                        target: copied,
+                        hash,
+                        ..
+                    } if copied == *target => Some(hash),
+                    _ => None,
+                });
+                planned.or_else(|| self.lockfile.hashes.get(*target)) != Some(*hash)
+            })
+            .map(|(target, _)| target.clone())
+            .collect();
+        stale_copies.sort();
+
+        Ok(RollbackPlan {
+            generation,
+            actions,
+            stale_copies,
+        })
+    }
+
+    /// Actions that bring the targets in line with `config`, reported as the plan
+    pub fn plan(&self, config: &DotyConfig) -> Result<Vec<LinkAction>> {
+        Ok(self
+            .linker
+            .calculate_diff(config, &self.lockfile, self.options.force)
+            .context("Failed to calculate diff")?)
+    }
+
+    /// Execute and record actions, then save the lockfile and a new generation
+    ///
+    /// `command` is stored with the generation. The linker reports every executed action, the
+    /// counts are left for the caller to report (`Event::ActionsSummary`). With `keep_going`,
+    /// failed actions are returned in `Applied::failures` instead of failing the run.
+    pub fn apply(self, actions: Vec<LinkAction>, command: &str) -> Result<Applied> {
+        let Deployment {
+            hostname,
+            lockfile_dir,
+            mut lockfile,
+            options,
+            mut linker,
+            reporter,
+            ..
+        } = self;
+        let dry_run = options.dry_run;
+
+        // Conflicts without a policy abort the run before anything is touched
+        let blocking_conflicts: Vec<Utf8PathBuf> = actions
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a,
+                    LinkAction::Conflict {
+                        policy: OnConflict::Fail,
+                        ..
+                    }
+                )
+            })
+            .map(|a| a.target().clone())
+            .collect();
+        if !blocking_conflicts.is_empty() && !dry_run {
+            return Err(DotyError::Conflicts {
+                targets: blocking_conflicts,
+            });
+        }
+
+        // Journal every change so a failure can be rolled back
+        if !dry_run {
+            linker = linker.with_journal(Journal::create(&lockfile_dir, &hostname)?);
+        }
+
+        // Execute actions and update state
+        let mut failures = Vec::new();
+        for action in &actions {
+            if let Err(source) = linker.execute_action(action, dry_run) {
+                // The linker reported the error
+                if options.keep_going {
+                    failures.push(action.target().clone());
+                    continue;
+                }
+
+                // Undo what this run did so far, the lockfile stays untouched
+                let rolled_back = linker
+                    .rollback()
+                    .map_err(|source| DotyError::RollbackFailed { source })?;
+                linker.finish_journal()?;
+                return Err(DotyError::ActionFailed {
+                    target: action.target().clone(),
+                    rolled_back,
+                    source,
+                });
+            }
+
+            // Update lockfile
+            if !dry_run {
+                match action {
+                    LinkAction::Created { target, source } => {
+                        lockfile.add_link(target.clone(), source.clone());
+                    }
+                    LinkAction::Updated {
+                        target, new_source, ..
+                    } => {
+                        lockfile.add_link(target.clone(), new_source.clone());
+                    }
+                    LinkAction::Removed { target, .. } | LinkAction::Deleted { target, .. } => {
+                        lockfile.remove_link(target);
+                    }
+                    LinkAction::Pruned { target, .. } => {
+                        lockfile.remove_link(target);
+                    }
+                    LinkAction::Copied {
+                        target,
+                        source,
+                        hash,
+                        ..
+                    } => {
+                        lockfile.add_copy(target.clone(), source.clone(), hash.clone());
+                    }
+                    LinkAction::Conflict {
+                        target,
+                        source,
+                        policy,
+                        hash,
+                        ..
+                    } => {
+                        // Skipped conflicts stay unmanaged
+                        if !matches!(policy, OnConflict::Skip | OnConflict::Fail) {
+                            match hash {
+                                Some(hash) => {
+                                    lockfile.add_copy(target.clone(), source.clone(), hash.clone())
+                                }
+                                None => lockfile.add_link(target.clone(), source.clone()),
+                            }
+                        }
+                    }
+                    LinkAction::Warning { .. } | LinkAction::Skipped { .. } => {
+                        // Don't modify lockfile for warnings or skipped links
+                    }
+                }
+            }
+        }
+
+        // Failed actions were not applied and don't count in the summary
+        let actions: Vec<LinkAction> = actions
+            .into_iter()
+            .filter(|a| !failures.contains(a.target()))
+            .collect();
+
+        // Save lockfile and remember it as a new generation
+        let mut generation = None;
+        if !dry_run {
+            lockfile
+                .save(&lockfile_dir)
+                .context("Failed to save lockfile")?;
+            linker.finish_journal()?;
+            let summary = generation_summary(&actions);
+            generation = record_generation(&reporter, &lockfile_dir, &lockfile, command, &summary);
+        }
+
+        let counts = ActionCounts {
+            failed: failures.len(),
+            ..ActionCounts::of(&actions)
+        };
+        Ok(Applied {
+            actions,
+            failures,
+            counts,
+            blocking_conflicts,
+            generation,
+        })
+    }
+}
+
+/// Record a generation for a saved lockfile, returning its number
+///
+/// The run is committed already when this is called, so a failure is only reported as a
+/// warning: it just leaves 'doty rollback' without a way back to this run.
+fn record_generation(
+    reporter: &Rc<dyn Reporter>,
+    lockfile_dir: &Utf8Path,
+    lockfile: &Lockfile,
+    command: &str,
+    summary: &str,
+) -> Option<u32> {
+    Generation::record(lockfile_dir, lockfile, command, summary).unwrap_or_else(|err| {
+        reporter.event(&Event::Warning(format!(
+            "Failed to record a generation, 'doty rollback' can't return to this run: {:#}",
+            err
+        )));
+        None
+    })
+}
+
+/// A package node for an unmapped repo path, with the target guessed from a package next to
+/// it or else from the name (`.zshrc` → `~/.zshrc`, `helix` → `~/.config/helix`)
+fn unmapped_package(
+    relative: &Utf8Path,
+    config: &DotyConfig,
+    config_dir_or_cwd: &Utf8Path,
+) -> Package {
+    let name = relative.file_name().unwrap_or(relative.as_str());
+    let sibling = relative
+        .parent()
+        .filter(|parent| !parent.as_str().is_empty())
+        .and_then(|parent| {
+            config
+                .packages
+                .iter()
+                .find(|pkg| pkg.source.parent() == Some(parent))
+        });
+    let target = match sibling.and_then(|pkg| pkg.target.parent()) {
+        Some(dir) => dir.join(name),
+        None if name.starts_with('.') => Utf8Path::new("~").join(name),
+        None => Utf8Path::new("~/.config").join(name),
+    };
+    let strategy = if config_dir_or_cwd.join(relative).is_dir() {
+        LinkStrategy::LinkFolder
+    } else {
+        LinkStrategy::LinkFilesRecursive
+    };
+
+    Package {
+        source: relative.to_path_buf(),
+        target,
+        strategy,
+        on_conflict: None,
+        when: None,
+        filter: Default::default(),
+        detect_ignore: Vec::new(),
+        detect: None,
+    }
+}
+
+/// One-line description of applied actions, stored with each generation
+fn generation_summary(actions: &[LinkAction]) -> String {
+    let count = |f: fn(&LinkAction) -> bool| actions.iter().filter(|a| f(a)).count();
+    let parts: Vec<String> = [
+        (
+            count(|a| {
+                matches!(a, LinkAction::Created { .. })
+                    || matches!(a, LinkAction::Conflict { policy, .. }
+                        if !matches!(policy, OnConflict::Skip | OnConflict::Fail))
+            }),
+            "added",
+        ),
+        (
+            count(|a| matches!(a, LinkAction::Updated { .. })),
+            "updated",
+        ),
+        (count(|a| matches!(a, LinkAction::Copied { .. })), "written"),
+        (
+            count(|a| matches!(a, LinkAction::Removed { .. } | LinkAction::Deleted { .. })),
+            "removed",
+        ),
+        (count(|a| matches!(a, LinkAction::Pruned { .. })), "pruned"),
+    ]
+    .into_iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, verb)| format!("{} {}", n, verb))
+    .collect();
+
+    if parts.is_empty() {
+        "no changes".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Determine the repo root (config_dir_or_cwd) based on the path resolution strategy
+fn resolve_config_dir_or_cwd(
+    config_path: &Utf8Path,
+    path_resolution: PathResolution,
+) -> anyhow::Result<Utf8PathBuf> {
+    match path_resolution {
+        PathResolution::Config => {
+            // Resolve relative to config file location
+            let config_dir = config_path.parent().ok_or_else(|| {
+                DotyError::Other(anyhow::anyhow!(
+                    "Config file has no parent directory: {}",
+                    config_path
+                ))
+            })?;
+
+            // Canonicalize to get absolute path
+            if config_dir.as_str().is_empty() || config_dir == "." {
+                current_dir()
+            } else {
+                config_dir.canonicalize_utf8().map_err(|err| {
+                    DotyError::io(config_dir, format!("Failed to resolve {}", config_dir), err)
+                        .into()
+                })
+            }
+        }
+        // Resolve relative to current working directory
+        PathResolution::Cwd => current_dir(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_plan_and_apply() {
+        let test_dir = Utf8PathBuf::from("tests/tmpfs/test_plan_and_apply");
+        let _ = fs::remove_dir_all(&test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir.join("repo/zsh")).unwrap();
+        fs::create_dir_all(test_dir.join("target")).unwrap();
+        fs::write(test_dir.join("repo/zsh/.zshrc"), "zshrc").unwrap();
+        fs::write(test_dir.join("repo/zsh/.zprofile"), "zprofile").unwrap();
+        fs::write(test_dir.join("target/.zprofile"), "local").unwrap();
+        let config_path = test_dir.join("repo/doty.kdl");
+        fs::write(
+            &config_path,
+            r#"LinkFilesRecursive "zsh" target="../target""#,
+        )
+        .unwrap();
+
+        let repo = Repo::load(&config_path).unwrap();
+        assert!(repo.config_dir_or_cwd.is_absolute());
+
+        // A dry run plans everything and reports the conflict, but changes nothing
+        let options = ApplyOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let deployment = repo.deployment(options).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+        let applied = deployment.apply(actions, "link").unwrap();
+        assert_eq!(applied.counts.created, 1);
+        assert_eq!(applied.blocking_conflicts, ["../target/.zprofile"]);
+        assert_eq!(applied.generation, None);
+        assert!(!test_dir.join("target/.zshrc").exists());
+
+        // A real run refuses to start
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+        let error = deployment.apply(actions, "link").unwrap_err();
+        assert!(
+            matches!(&error, DotyError::Conflicts { targets } if targets.len() == 1),
+            "{:?}",
+            error
+        );
+        assert!(!test_dir.join("target/.zshrc").exists());
+
+        // Without the conflict, the lockfile is saved as the first generation
+        fs::remove_file(test_dir.join("target/.zprofile")).unwrap();
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+        let applied = deployment.apply(actions, "link").unwrap();
+        assert_eq!(applied.counts.created, 2);
+        assert_eq!(applied.generation, Some(1));
+        assert_eq!(repo.load_lockfile().unwrap().links.len(), 2);
+        assert!(repo.scan().unwrap().is_empty());
+
+        // Clean up
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_apply_warns_when_generation_fails() {
+        use crate::reporter::RecordingReporter;
+
+        let test_dir = Utf8PathBuf::from("tests/tmpfs/test_apply_warns_when_generation_fails");
+        let _ = fs::remove_dir_all(&test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir.join("repo/zsh")).unwrap();
+        fs::write(test_dir.join("repo/zsh/.zshrc"), "zshrc").unwrap();
+        let config_path = test_dir.join("repo/doty.kdl");
+        fs::write(
+            &config_path,
+            r#"LinkFilesRecursive "zsh" target="../target""#,
+        )
+        .unwrap();
+
+        // A file where the generations directory should go
+        fs::create_dir_all(test_dir.join("repo/.doty/state")).unwrap();
+        fs::write(test_dir.join("repo/.doty/state/generations"), "").unwrap();
+
+        let recording = Rc::new(RecordingReporter::default());
+        let reporter: Rc<dyn Reporter> = recording.clone();
+        let repo = Repo::open(&config_path, &reporter).unwrap();
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+
+        // The run is applied and saved, only the generation is missing
+        let applied = deployment.apply(actions, "link").unwrap();
+        assert_eq!(applied.counts.created, 1);
+        assert_eq!(applied.generation, None);
+        assert_eq!(repo.load_lockfile().unwrap().links.len(), 1);
+        assert!(test_dir.join("target/.zshrc").is_symlink());
+        let warnings = recording.warnings();
+        assert!(
+            matches!(&warnings[..], [warning] if warning.starts_with("Failed to record a generation")),
+            "{:?}",
+            warnings
+        );
+
+        // Clean up
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_rollback_restores_refreshed_copy() {
+        let test_dir = Utf8PathBuf::from("tests/tmpfs/test_rollback_restores_refreshed_copy");
+        let _ = fs::remove_dir_all(&test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir.join("repo/vscode")).unwrap();
+        fs::create_dir_all(test_dir.join("repo/zsh")).unwrap();
+        fs::write(test_dir.join("repo/vscode/settings.json"), "old").unwrap();
+        fs::write(test_dir.join("repo/zsh/.zshrc"), "zshrc").unwrap();
+        let config_path = test_dir.join("repo/doty.kdl");
+        fs::write(
+            &config_path,
+            r#"Copy "vscode" target="../target/vscode"
+LinkFilesRecursive "zsh" target="../target/zsh""#,
+        )
+        .unwrap();
+        let repo = Repo::load(&config_path).unwrap();
+        let run = || {
+            let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+            let actions = deployment.plan(&repo.config).unwrap();
+            deployment.apply(actions, "link")
+        };
+        run().unwrap();
+        let copy = test_dir.join("target/vscode/settings.json");
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "old");
+
+        // The copy is refreshed, then a new link fails because its target got occupied
+        fs::write(test_dir.join("repo/vscode/settings.json"), "new").unwrap();
+        fs::write(test_dir.join("repo/zsh/.zprofile"), "zprofile").unwrap();
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+        fs::write(test_dir.join("target/zsh/.zprofile"), "local").unwrap();
+        let error = deployment.apply(actions, "link").unwrap_err();
+        assert!(
+            matches!(&error, DotyError::ActionFailed { .. }),
+            "{:?}",
+            error
+        );
+
+        // The previous copy is back and no backup is left over
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "old");
+        let backups = BackupStore::load(&test_dir.join("repo/.doty/backups"), &repo.hostname);
+        assert!(backups.unwrap().entries.is_empty());
+
+        // Clean up
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_status_detect_and_restore() {
+        let test_dir = Utf8PathBuf::from("tests/tmpfs/test_status_detect_and_restore");
+        let _ = fs::remove_dir_all(&test_dir); // Clean up any existing test dir
+        fs::create_dir_all(test_dir.join("repo/zsh")).unwrap();
+        fs::create_dir_all(test_dir.join("target")).unwrap();
+        let target = test_dir.canonicalize_utf8().unwrap().join("target");
+        fs::write(test_dir.join("repo/zsh/.zshrc"), "zshrc").unwrap();
+        fs::write(test_dir.join("repo/zsh/.zprofile"), "zprofile").unwrap();
+        fs::write(target.join(".zprofile"), "local").unwrap();
+        let config_path = test_dir.join("repo/doty.kdl");
+        fs::write(
+            &config_path,
+            format!(
+                r#"LinkFilesRecursive "zsh" target="{}" onConflict="backup""#,
+                target
+            ),
+        )
+        .unwrap();
+        let repo = Repo::load(&config_path).unwrap();
+
+        let status = repo.status().unwrap();
+        assert_eq!(status.packages[0].links.len(), 2);
+        assert_eq!(status.unhealthy().count(), 2);
+        assert!(status.drifted(), "The local .zprofile conflicts");
+
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let actions = deployment.plan(&repo.config).unwrap();
+        deployment.apply(actions, "link").unwrap();
+        let generations = repo.generations().unwrap();
+        assert_eq!(generations.list.len(), 1);
+        assert_eq!(generations.current, Some(1));
+        let deployment = repo.deployment(ApplyOptions::default()).unwrap();
+        let error = deployment.rollback_plan(&repo.config, None).unwrap_err();
+        assert!(error.to_string().contains("No earlier generation"));
+
+        // An untracked file is drift, grouped by its package
+        fs::write(target.join(".zlogin"), "untracked").unwrap();
+        let status = repo.status().unwrap();
+        assert_eq!(status.unhealthy().count(), 0);
+        assert_eq!(status.packages[0].untracked, 1);
+        assert!(status.drifted());
+        let drift = repo.detect().unwrap();
+        assert_eq!(drift.untracked.len(), 1);
+        assert_eq!(drift.untracked[0].1, [target.join(".zlogin")]);
+        assert!(drift.broken.is_empty() && drift.orphaned.is_empty());
+
+        // Restoring replaces doty's link with the original and drops it from the lockfile
+        let restored = repo.restore(None).unwrap();
+        assert_eq!(restored.restored.len(), 1);
+        assert!(restored.blocked.is_empty());
+        assert_eq!(
+            fs::read_to_string(target.join(".zprofile")).unwrap(),
+            "local"
+        );
+        assert!(!repo
+            .load_lockfile()
+            .unwrap()
+            .is_managed(&target.join(".zprofile")));
+        let error = repo.restore(Some(&target.join(".zshrc"))).unwrap_err();
+        assert!(error.to_string().contains("No backup found"));
+
+        // Clean up
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_config_error() {
+        let error = Repo::load(Utf8Path::new("tests/tmpfs/missing/doty.kdl"))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&error, DotyError::ConfigNotFound { path } if path == "tests/tmpfs/missing/doty.kdl"),
+            "{:?}",
+            error
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use camino::Utf8PathBuf;
+use colored::Colorize;
+use pluralizer::pluralize;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+use crate::config::Package;
+use crate::linker::{LinkAction, LinkHealth};
+use crate::scanner::DriftItem;
+
+/// Something a command, the `Linker` or the `Scanner` did or found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Config loaded and the base path resolved
+    RepoLoaded {
+        config_path: Utf8PathBuf,
+        base_path: Utf8PathBuf,
+    },
+    /// `calculate_diff` is done, before anything is executed
+    PlanComputed(Vec<PlannedAction>),
+    /// `execute_action` is about to touch the filesystem (or pretend to, in a dry run)
+    ActionStarted(LinkAction),
+    /// An action was executed, or only planned in a dry run
+    ActionFinished { action: LinkAction, dry_run: bool },
+    /// An action failed, nothing of it was applied
+    ActionFailed { action: LinkAction, error: String },
+    /// The scanner found a target that differs from what doty manages
+    DriftFound(Box<DriftItem>),
+    /// Health of one link, as shown by `doty status`
+    LinkChecked {
+        target: Utf8PathBuf,
+        package: Option<Utf8PathBuf>,
+        health: LinkHealth,
+    },
+    /// A repo path no package links, with a package node for doty.kdl
+    Unmapped {
+        path: Utf8PathBuf,
+        directory: bool,
+        suggestion: String,
+    },
+    /// An unreadable directory or a symlink loop, the walk went on without it
+    Warning(String),
+    /// Counts of the actions a link, rollback or clean run executed
+    ActionsSummary { counts: ActionCounts, dry_run: bool },
+    /// Other summary counts (drift types, link health), printed by the command itself in text
+    Counts(Vec<(&'static str, usize)>),
+}
+
+/// An action of the plan and the package it belongs to (none for links only in the lockfile)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedAction {
+    pub package: Option<Package>,
+    pub action: LinkAction,
+}
+
+/// How many actions of each kind a run executed (or planned in a dry run)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ActionCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub copied: usize,
+    pub removed: usize,
+    pub pruned: usize,
+    pub warnings: usize,
+    pub conflicts: usize,
+    pub failed: usize,
+}
+
+impl ActionCounts {
+    /// Count actions by kind, `failed` stays 0
+    pub fn of(actions: &[LinkAction]) -> Self {
+        let mut counts = Self::default();
+        for action in actions {
+            match action {
+                LinkAction::Created { .. } => counts.created += 1,
+                LinkAction::Updated { .. } => counts.updated += 1,
+                LinkAction::Skipped { .. } => counts.unchanged += 1,
+                LinkAction::Copied { .. } => counts.copied += 1,
+                LinkAction::Removed { .. } | LinkAction::Deleted { .. } => counts.removed += 1,
+                LinkAction::Pruned { .. } => counts.pruned += 1,
+                LinkAction::Warning { .. } => counts.warnings += 1,
+                LinkAction::Conflict { .. } => counts.conflicts += 1,
+            }
+        }
+        counts
+    }
+
+    /// Whether anything besides unchanged links was counted
+    pub fn has_changes(&self) -> bool {
+        self.created
+            + self.updated
+            + self.copied
+            + self.removed
+            + self.pruned
+            + self.warnings
+            + self.conflicts
+            > 0
+    }
+}
+
+/// Receives the events of a command run
+///
+/// Commands pass one reporter to the `Linker` and `Scanner` they create (`with_reporter`), so
+/// everything ends up in the same place: colored text, a JSON document, nothing at all, or a
+/// list for tests to look at.
+pub trait Reporter {
+    fn event(&self, event: &Event);
+
+    /// The command is done, write what was collected
+    fn finish(&self, _result: &anyhow::Result<crate::output::Outcome>) {}
+}
+
+/// Drops every event (`--output quiet`, and the default of `Linker` and `Scanner`)
+#[derive(Debug, Default)]
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn event(&self, _event: &Event) {}
+}
+
+/// Keeps every event, for tests
+#[derive(Debug, Default)]
+pub struct RecordingReporter {
+    events: RefCell<Vec<Event>>,
+}
+
+impl RecordingReporter {
+    /// Events received so far
+    pub fn events(&self) -> Vec<Event> {
+        self.events.borrow().clone()
+    }
+
+    /// Messages of the warnings received so far
+    pub fn warnings(&self) -> Vec<String> {
+        self.events
+            .borrow()
+            .iter()
+            .filter_map(|event| match event {
+                Event::Warning(message) => Some(message.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Reporter for RecordingReporter {
+    fn event(&self, event: &Event) {
+        self.events.borrow_mut().push(event.clone());
+    }
+}
+
+/// Colored text for people (`--output text`)
+///
+/// Prints the plan grouped by package, failures and warnings as they happen, and the action
+/// summary. Actions executed without a plan (`doty clean`) are printed as they finish. Drift,
+/// link health and unmapped sources are printed by the commands, next to their prompts.
+#[derive(Debug, Default)]
+pub struct TextReporter {
+    base_path: RefCell<Utf8PathBuf>,
+    planned: Cell<bool>,
+    warnings: RefCell<HashSet<String>>,
+}
+
+impl Reporter for TextReporter {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::RepoLoaded {
+                config_path,
+                base_path,
+            } => {
+                println!("{:<10} {}", "Config:", config_path);
+                println!("{:<10} {}", "BasePath:", base_path);
+                *self.base_path.borrow_mut() = base_path.clone();
+            }
+            Event::PlanComputed(plan) => {
+                self.planned.set(true);
+                self.print_plan(plan);
+            }
+            Event::ActionFinished { action, .. } if !self.planned.get() => {
+                self.print_action(action);
+            }
+            Event::ActionFailed { action, error } => {
+                println!("\n{} {}: {}", "[!]".red().bold(), action.target(), error);
+            }
+            Event::Warning(message) if self.warnings.borrow_mut().insert(message.clone()) => {
+                println!("{} {}", "[!]".yellow().bold(), message);
+            }
+            Event::ActionsSummary { counts, .. } => print_summary(counts),
+            _ => {}
+        }
+    }
+}
+
+impl TextReporter {
+    /// Print actions grouped by package, links only in the lockfile last
+    fn print_plan(&self, plan: &[PlannedAction]) {
+        let mut groups: Vec<(&Package, Vec<&LinkAction>)> = Vec::new();
+        let mut orphaned = Vec::new();
+        for planned in plan {
+            // Do not print skipped links
+            if matches!(planned.action, LinkAction::Skipped { .. }) {
+                continue;
+            }
+            match &planned.package {
+                Some(package) => match groups.iter_mut().find(|(p, _)| *p == package) {
+                    Some((_, actions)) => actions.push(&planned.action),
+                    None => groups.push((package, vec![&planned.action])),
+                },
+                None => orphaned.push(&planned.action),
+            }
+        }
+
+        for (package, actions) in groups {
+            println!(
+                "\n{}",
+                format!(
+                    "{} {} → {}",
+                    package.strategy, package.source, package.target
+                )
+                .bold()
+            );
+            for action in actions {
+                self.print_action(action);
+            }
+        }
+
+        if !orphaned.is_empty() {
+            println!("\n{}", "Orphaned links:".bold());
+            for action in orphaned {
+                self.print_action(action);
+            }
+        }
+    }
+
+    fn print_action(&self, action: &LinkAction) {
+        match action {
+            LinkAction::Created { target, source } => {
+                println!("  {} {} → {}", "[+]".green().bold(), target, source);
+            }
+            LinkAction::Updated {
+                target,
+                old_source,
+                new_source,
+            } => {
+                println!(
+                    "  {} {} → {} {}",
+                    "[~]".yellow().bold(),
+                    target,
+                    new_source,
+                    format!("(was: {})", old_source).dimmed()
+                );
+            }
+            LinkAction::Skipped { .. } => {}
+            LinkAction::Removed { target, source } => {
+                println!("  {} {} → {}", "[-]".red().bold(), target, source);
+            }
+            LinkAction::Pruned { target, source } => {
+                // Make source path relative to basePath if possible
+                let base_path = self.base_path.borrow();
+                let source_display = source
+                    .strip_prefix(base_path.as_path())
+                    .unwrap_or(source.as_path());
+                println!("  {} {} → {}", "[x]".red().bold(), target, source_display);
+                println!("      Pruned: Source is missing, dangling symlink removed");
+            }
+            LinkAction::Warning {
+                target,
+                source,
+                message,
+            } => {
+                println!("  {} {} → {}", "[!]".yellow().bold(), target, source);
+                println!("      Warning: {}", message);
+            }
+            LinkAction::Conflict {
+                target,
+                source,
+                existing,
+                policy,
+                ..
+            } => {
+                println!("  {} {} → {}", "[!]".red().bold(), target, source);
+                println!(
+                    "      Conflict: existing {} is not managed by doty (onConflict: {})",
+                    existing, policy
+                );
+            }
+            LinkAction::Copied {
+                target,
+                source,
+                template,
+                ..
+            } => {
+                println!(
+                    "  {} {} → {} {}",
+                    "[+]".green().bold(),
+                    target,
+                    source,
+                    if *template { "(rendered)" } else { "(copy)" }.dimmed()
+                );
+            }
+            LinkAction::Deleted { target, source } => {
+                println!(
+                    "  {} {} → {} {}",
+                    "[-]".red().bold(),
+                    target,
+                    source,
+                    "(copy)".dimmed()
+                );
+            }
+        }
+    }
+}
+
+fn print_summary(counts: &ActionCounts) {
+    if !counts.has_changes() {
+        if counts.unchanged > 0 {
+            println!(
+                "\n{} All {} already up to date",
+                "✓".green().bold(),
+                pluralize("link", counts.unchanged as isize, true)
+            );
+        }
+        return;
+    }
+
+    println!("\n{}", "Summary:".bold());
+    let lines = [
+        (counts.created, "[+]".green().bold(), "link", "added"),
+        (counts.updated, "[~]".yellow().bold(), "link", "updated"),
+        (counts.copied, "[+]".green().bold(), "file", "written"),
+        (counts.removed, "[-]".red().bold(), "link", "removed"),
+        (counts.pruned, "[x]".red().bold(), "link", "pruned"),
+        (counts.warnings, "[!]".yellow().bold(), "warning", ""),
+        (counts.conflicts, "[!]".red().bold(), "conflict", ""),
+        (counts.unchanged, "[·]".dimmed(), "link", "unchanged"),
+    ];
+    for (count, icon, noun, verb) in lines {
+        if count > 0 {
+            let counted = pluralize(noun, count as isize, true);
+            if verb.is_empty() {
+                println!("  {} {}", icon, counted);
+            } else {
+                println!("  {} {} {}", icon, counted, verb);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_counts() {
+        let link = |target: &str| (Utf8PathBuf::from(target), Utf8PathBuf::from("src"));
+        let (t1, s1) = link("a");
+        let (t2, s2) = link("b");
+        let (t3, s3) = link("c");
+        let counts = ActionCounts::of(&[
+            LinkAction::Created {
+                target: t1,
+                source: s1,
+            },
+            LinkAction::Skipped {
+                target: t2,
+                source: s2,
+            },
+            LinkAction::Deleted {
+                target: t3,
+                source: s3,
+            },
+        ]);
+        assert_eq!(counts.created, 1);
+        assert_eq!(counts.unchanged, 1);
+        assert_eq!(counts.removed, 1);
+        assert!(counts.has_changes());
+        assert!(!ActionCounts {
+            unchanged: 3,
+            ..Default::default()
+        }
+        .has_changes());
+    }
+}
@@ -1,43 +1,86 @@
 use anyhow::{Context, Result};
-use camino::Utf8PathBuf;
-
-use crate::config::{DotyConfig, LinkStrategy, Package};
+use camino::{Utf8Path, Utf8PathBuf};
+use ignore::WalkBuilder;
+use std::collections::{BTreeSet, HashSet};
+use std::rc::Rc;
+
+use crate::condition::HostInfo;
+use crate::config::{DetectPolicy, DotyConfig, LinkStrategy, Package};
+use crate::dotyignore::{DetectIgnore, DOTYIGNORE_FILE};
 use crate::fs_utils::{
-    get_fs_type, is_broken_symlink, resolve_target_path, scan_directory_recursive,
+    find_symlinks, get_fs_type, hash_file, is_broken_symlink, read_symlink_target, resolve_symlink,
+    resolve_target_path, walk_files, FsType, WalkOptions,
 };
+use crate::linker::Linker;
 use crate::lockfile::Lockfile;
+use crate::reporter::{Event, QuietReporter, Reporter};
 
 /// Types of drift detected between filesystem reality and Doty's knowledge
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DriftType {
-    /// File exists in target but not in source (LinkFilesRecursive only)
+    /// File exists in target but not in source (LinkFilesRecursive, Copy and Template only)
     Untracked,
     /// Symlink exists but points nowhere
     Broken,
-    /// Target file modified (not a symlink anymore)
+    /// Link target replaced by a regular file or pointing elsewhere, or a copy that no longer
+    /// matches the content hash recorded in the lockfile
     Modified,
-    /// In state but not in config (already handled by linker, included for completeness)
+    /// In the lockfile but no package produces it anymore (the next `doty link` removes it)
     Orphaned,
+    /// Symlink into the repo that is not in the lockfile, e.g. made by stow or by hand
+    Stray,
 }
 
 /// Represents a drift item detected during scanning
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DriftItem {
     pub target_path: Utf8PathBuf,
     pub drift_type: DriftType,
     pub package: Option<Package>,
     pub symlink_target: Option<Utf8PathBuf>,
+    /// Repo source the target should match (Modified, Orphaned), or the repo path a stray
+    /// link points to
+    pub source_path: Option<Utf8PathBuf>,
 }
 
 /// Scanner for detecting drift between filesystem reality and Doty's knowledge
 pub struct Scanner {
     config_dir_or_cwd: Utf8PathBuf,
+    /// Packages whose `when` condition doesn't match this machine are not scanned
+    host: HostInfo,
+    /// Receives drift items and warnings about unreadable directories and symlink loops
+    reporter: Rc<dyn Reporter>,
 }
 
 impl Scanner {
     /// Create a new Scanner
     pub fn new(config_dir_or_cwd: Utf8PathBuf) -> Self {
-        Self { config_dir_or_cwd }
+        Self {
+            config_dir_or_cwd,
+            host: HostInfo::current(),
+            reporter: Rc::new(QuietReporter),
+        }
+    }
+
+    /// Send drift items and warnings to a reporter
+    pub fn with_reporter(mut self, reporter: Rc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Report directories a walk couldn't read (see `walk_files`)
+    fn warn(&self, warnings: Vec<String>) {
+        for warning in warnings {
+            self.reporter.event(&Event::Warning(warning));
+        }
+    }
+
+    /// Report what a scan found
+    fn found(&self, drift_items: &[DriftItem]) {
+        for item in drift_items {
+            self.reporter
+                .event(&Event::DriftFound(Box::new(item.clone())));
+        }
     }
 
     /// Scan target directories and detect differences between filesystem reality and Doty's knowledge
@@ -45,19 +88,35 @@ impl Scanner {
         let mut drift_items = Vec::new();
 
         // Scan each package for drift
-        for package in &config.packages {
-            let package_drift = self.scan_package(package, config, lockfile)?;
+        let active_packages: Vec<&Package> = config
+            .packages
+            .iter()
+            .filter(|pkg| pkg.is_active(&self.host))
+            .collect();
+        // Lockfile links the config no longer produces are orphans
+        let linker = Linker::new(self.config_dir_or_cwd.clone(), config.path_resolution)
+            .with_host(self.host.clone())
+            .with_reporter(self.reporter.clone());
+        let desired_targets = linker.desired_targets(config);
+
+        for package in &active_packages {
+            let package_drift = self.scan_package(package, &desired_targets, lockfile)?;
             drift_items.extend(package_drift);
         }
+        let mut lockfile_links: Vec<_> = lockfile.links.iter().collect();
+        lockfile_links.sort();
 
         // Check for broken symlinks from lockfile that aren't already covered by package scanning
-        for (lockfile_target, _) in &lockfile.links {
+        for (lockfile_target, lockfile_source) in lockfile_links {
             // Lockfile now stores absolute paths, but resolve_target_path handles both absolute and relative
             // Since lockfile_target is already absolute, resolve_target_path will just return it
             let resolved_target = resolve_target_path(lockfile_target, &self.config_dir_or_cwd)?;
+            if desired_targets.contains(&resolved_target) {
+                continue;
+            }
 
             // Skip if this target is already covered by a package
-            let is_covered_by_package = config.packages.iter().any(|pkg| {
+            let is_covered_by_package = active_packages.iter().any(|pkg| {
                 let pkg_target =
                     resolve_target_path(&pkg.target, &self.config_dir_or_cwd).unwrap_or_default();
                 // For comparison, try to canonicalize both paths
@@ -69,40 +128,175 @@ impl Scanner {
                     .canonicalize_utf8()
                     .unwrap_or_else(|_| pkg_target.clone());
                 // Check if resolved target is within the package target directory
-                resolved_normalized.starts_with(&pkg_normalized) || resolved_normalized == pkg_normalized
+                resolved_normalized.starts_with(&pkg_normalized)
+                    || resolved_normalized == pkg_normalized
             });
 
-            if !is_covered_by_package {
-                // Check if the target exists and is a symlink
-                // Use resolved_target directly (lockfile stores absolute paths)
-                if let Some(fs_type) = get_fs_type(&resolved_target)? {
-                    if fs_type == crate::fs_utils::FsType::Symlink {
-                        if is_broken_symlink(&resolved_target)? {
-                            let symlink_target = std::fs::read_link(&resolved_target)
-                                .ok()
-                                .and_then(|p| Utf8PathBuf::from_path_buf(p).ok());
+            // Check if the target exists and is a symlink
+            // Use resolved_target directly (lockfile stores absolute paths)
+            let is_symlink = get_fs_type(&resolved_target)? == Some(FsType::Symlink);
+            let symlink_target = std::fs::read_link(&resolved_target)
+                .ok()
+                .and_then(|p| Utf8PathBuf::from_path_buf(p).ok());
+            if !is_covered_by_package && is_symlink && is_broken_symlink(&resolved_target)? {
+                drift_items.push(DriftItem {
+                    target_path: resolved_target,
+                    drift_type: DriftType::Broken,
+                    package: None, // We don't know which package this belongs to
+                    symlink_target,
+                    source_path: None,
+                });
+            } else {
+                drift_items.push(DriftItem {
+                    target_path: resolved_target,
+                    drift_type: DriftType::Orphaned,
+                    package: None,
+                    symlink_target,
+                    source_path: Some(lockfile_source.clone()),
+                });
+            }
+        }
 
-                            drift_items.push(DriftItem {
-                                target_path: resolved_target,
-                                drift_type: DriftType::Broken,
-                                package: None, // We don't know which package this belongs to
-                                symlink_target,
-                            });
-                        }
-                    }
+        self.found(&drift_items);
+        Ok(drift_items)
+    }
+
+    /// Find symlinks below `roots` that point into the repo but are not in the lockfile
+    /// A stray link gets the package that produces exactly this link, so it can be recorded in
+    /// the lockfile as it is, links no package produces have no package
+    pub fn scan_stray(
+        &self,
+        config: &DotyConfig,
+        lockfile: &Lockfile,
+        roots: &[Utf8PathBuf],
+    ) -> Result<Vec<DriftItem>> {
+        let repo_root = self
+            .config_dir_or_cwd
+            .canonicalize_utf8()
+            .unwrap_or_else(|_| self.config_dir_or_cwd.clone());
+        let skip = [repo_root.clone(), self.config_dir_or_cwd.clone()];
+        let linker = Linker::new(self.config_dir_or_cwd.clone(), config.path_resolution)
+            .with_host(self.host.clone())
+            .with_reporter(self.reporter.clone());
+        let desired_links = linker.desired_links(config);
+
+        let mut drift_items = Vec::new();
+        for root in roots {
+            let root = resolve_target_path(root, &self.config_dir_or_cwd)?;
+            let walk = find_symlinks(&root, &skip);
+            self.warn(walk.warnings);
+            for link in walk.files {
+                let Some(points_to) = resolve_symlink(&link) else {
+                    continue;
+                };
+                if lockfile.links.contains_key(&link)
+                    || !(points_to.starts_with(&repo_root)
+                        || points_to.starts_with(&self.config_dir_or_cwd))
+                {
+                    continue;
                 }
+
+                let package = desired_links
+                    .get(&link)
+                    .filter(|(source, package)| {
+                        *source == points_to
+                            && !matches!(
+                                package.strategy,
+                                LinkStrategy::Copy | LinkStrategy::Template
+                            )
+                    })
+                    .map(|(_, package)| package.clone());
+                drift_items.push(DriftItem {
+                    target_path: link,
+                    drift_type: DriftType::Stray,
+                    package,
+                    symlink_target: Some(points_to.clone()),
+                    source_path: Some(points_to),
+                });
             }
         }
 
+        // Roots may overlap
+        drift_items.sort_by(|a, b| a.target_path.cmp(&b.target_path));
+        drift_items.dedup_by(|a, b| a.target_path == b.target_path);
+        self.found(&drift_items);
         Ok(drift_items)
     }
 
+    /// Files and directories in the repo that no package source covers, relative to the repo
+    /// A directory without any package source in it is reported once instead of file by file.
+    /// `.git`, `.doty`, the config files, gitignored files and the default `ignore` patterns
+    /// are skipped
+    pub fn scan_unmapped(&self, config: &DotyConfig) -> Result<Vec<Utf8PathBuf>> {
+        let sources: Vec<Utf8PathBuf> = config
+            .packages
+            .iter()
+            .map(|pkg| {
+                let source = pkg
+                    .source
+                    .strip_prefix(&self.config_dir_or_cwd)
+                    .unwrap_or(&pkg.source);
+                // "./nvim" and "nvim" are the same source, "." covers the whole repo
+                source.components().filter(|c| c.as_str() != ".").collect()
+            })
+            .collect();
+
+        let walker = WalkBuilder::new(&self.config_dir_or_cwd)
+            .hidden(false)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(|entry| !matches!(entry.file_name().to_str(), Some(".git" | ".doty")))
+            .build();
+
+        let mut unmapped = BTreeSet::new();
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.reporter.event(&Event::Warning(err.to_string()));
+                    continue;
+                }
+            };
+            if entry.file_type().is_none_or(|t| t.is_dir()) {
+                continue;
+            }
+            let Ok(path) = Utf8PathBuf::from_path_buf(entry.into_path()) else {
+                continue;
+            };
+            let relative = path
+                .strip_prefix(&self.config_dir_or_cwd)
+                .with_context(|| format!("Failed to get relative path for {}", path))?;
+            if config.files.contains(&path)
+                || matches!(relative.file_name(), Some(DOTYIGNORE_FILE | ".gitignore"))
+                || !config.filter.is_included(relative)
+                || sources.iter().any(|source| relative.starts_with(source))
+            {
+                continue;
+            }
+
+            // Report the outermost directory that no package source lies in
+            let mut ancestors: Vec<&Utf8Path> = relative
+                .ancestors()
+                .filter(|a| !a.as_str().is_empty())
+                .collect();
+            ancestors.reverse();
+            if let Some(top) = ancestors
+                .into_iter()
+                .find(|a| !sources.iter().any(|source| source.starts_with(a)))
+            {
+                unmapped.insert(top.to_path_buf());
+            }
+        }
+
+        Ok(unmapped.into_iter().collect())
+    }
+
     /// Scan a single package for drift
     fn scan_package(
         &self,
         package: &Package,
-        _config: &DotyConfig,
-        _lockfile: &Lockfile,
+        desired_targets: &HashSet<Utf8PathBuf>,
+        lockfile: &Lockfile,
     ) -> Result<Vec<DriftItem>> {
         let mut drift_items = Vec::new();
 
@@ -124,14 +318,16 @@ impl Scanner {
                         drift_type: DriftType::Broken,
                         package: Some(package.clone()),
                         symlink_target,
+                        source_path: None,
                     });
                 }
             }
-            LinkStrategy::LinkFilesRecursive => {
+            LinkStrategy::LinkFilesRecursive | LinkStrategy::Copy | LinkStrategy::Template => {
                 // Only scan if source is a directory
                 if source_path.is_dir() {
-                    let _source_files = scan_directory_recursive(&source_path)?;
-                    let target_files = scan_directory_recursive(&target_path)?;
+                    let target_files = self.target_files(package, &source_path, &target_path);
+                    let detect_ignore =
+                        DetectIgnore::for_package(&self.config_dir_or_cwd, package)?;
 
                     for target_file in target_files {
                         let relative_path =
@@ -140,13 +336,19 @@ impl Scanner {
                             })?;
                         let corresponding_source = source_path.join(relative_path);
 
-                        if !corresponding_source.exists() {
+                        // Links doty recorded are orphans when their source is gone, not untracked
+                        if !corresponding_source.exists()
+                            && !lockfile.links.contains_key(&target_file)
+                            && package.filter.is_included(relative_path)
+                            && !detect_ignore.is_ignored(relative_path)
+                        {
                             // File in target but not in source = Untracked
                             drift_items.push(DriftItem {
                                 target_path: target_file,
                                 drift_type: DriftType::Untracked,
                                 package: Some(package.clone()),
                                 symlink_target: None,
+                                source_path: None,
                             });
                         }
                     }
@@ -162,21 +364,134 @@ impl Scanner {
                             drift_type: DriftType::Broken,
                             package: Some(package.clone()),
                             symlink_target,
+                            source_path: None,
                         });
                     }
                 }
             }
         }
 
+        // Copies and rendered templates doty wrote must still have the recorded content
+        if matches!(
+            package.strategy,
+            LinkStrategy::Copy | LinkStrategy::Template
+        ) {
+            let mut copies: Vec<_> = lockfile
+                .hashes
+                .iter()
+                .filter(|(target, _)| {
+                    target.starts_with(&target_path) && desired_targets.contains(*target)
+                })
+                .collect();
+            copies.sort();
+            for (target, hash) in copies {
+                if get_fs_type(target)? == Some(FsType::File) && hash_file(target)? != *hash {
+                    drift_items.push(DriftItem {
+                        target_path: target.clone(),
+                        drift_type: DriftType::Modified,
+                        package: Some(package.clone()),
+                        symlink_target: None,
+                        source_path: lockfile.links.get(target).cloned(),
+                    });
+                }
+            }
+        } else {
+            // Symlinks doty created that an editor replaced with a regular file, or that
+            // point somewhere else now
+            let source_root = self.config_dir_or_cwd.join(&package.source);
+            let mut links: Vec<_> = lockfile
+                .links
+                .iter()
+                .filter(|(target, source)| {
+                    source.starts_with(&source_root)
+                        && !lockfile.hashes.contains_key(*target)
+                        && desired_targets.contains(*target)
+                })
+                .collect();
+            links.sort();
+            for (target, source) in links {
+                if Self::is_replaced_link(target, source)? {
+                    drift_items.push(DriftItem {
+                        target_path: target.clone(),
+                        drift_type: DriftType::Modified,
+                        package: Some(package.clone()),
+                        symlink_target: std::fs::read_link(target)
+                            .ok()
+                            .and_then(|p| Utf8PathBuf::from_path_buf(p).ok()),
+                        source_path: Some(source.clone()),
+                    });
+                }
+            }
+        }
+
         Ok(drift_items)
     }
+
+    /// Files in a package target that are checked for untracked files, per the package's
+    /// detection policy
+    fn target_files(
+        &self,
+        package: &Package,
+        source_path: &Utf8Path,
+        target_path: &Utf8Path,
+    ) -> Vec<Utf8PathBuf> {
+        let (roots, max_depth) = match package.detect_policy(target_path) {
+            DetectPolicy::Full => (vec![target_path.to_path_buf()], None),
+            DetectPolicy::Depth(depth) => (vec![target_path.to_path_buf()], Some(depth)),
+            DetectPolicy::ManagedDirsOnly => {
+                // The target directories the package's files are linked into
+                let source_walk = walk_files(source_path, WalkOptions::default());
+                self.warn(source_walk.warnings);
+                let managed_dirs: BTreeSet<Utf8PathBuf> = source_walk
+                    .files
+                    .iter()
+                    .filter_map(|file| file.strip_prefix(source_path).ok())
+                    .filter(|relative| package.filter.is_included(relative))
+                    .filter_map(|relative| relative.parent())
+                    .map(|dir| target_path.join(dir))
+                    .collect();
+                (managed_dirs.into_iter().collect(), Some(1))
+            }
+        };
+
+        let mut files = Vec::new();
+        for root in roots {
+            let walk = walk_files(
+                &root,
+                WalkOptions {
+                    max_depth,
+                    ..Default::default()
+                },
+            );
+            self.warn(walk.warnings);
+            files.extend(walk.files);
+        }
+        files
+    }
+
+    /// Whether a recorded link target is no longer a symlink to its source
+    /// Missing targets and broken symlinks are not reported here
+    fn is_replaced_link(target: &Utf8Path, source: &Utf8Path) -> Result<bool> {
+        Ok(match get_fs_type(target)? {
+            None => false,
+            Some(FsType::Symlink) => match read_symlink_target(target)? {
+                None => false, // Broken
+                Some(points_to) => {
+                    points_to != source
+                        && source.canonicalize_utf8().ok().as_ref() != Some(&points_to)
+                }
+            },
+            Some(FsType::File) | Some(FsType::Directory) => true,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{DotyConfig, LinkStrategy, Package, PathResolution};
+    use crate::config::{DotyConfig, LinkStrategy, OnConflict, Package, PathResolution};
     use crate::lockfile::Lockfile;
+    use crate::reporter::RecordingReporter;
     use std::fs;
     use tempfile::TempDir;
 
@@ -197,8 +512,18 @@ mod tests {
                 source: "source/test-app".into(),
                 target: "~/.config/test-app".into(),
                 strategy: LinkStrategy::LinkFilesRecursive,
+                on_conflict: None,
+                when: None,
+                filter: Default::default(),
+                detect_ignore: Vec::new(),
+                detect: None,
             }],
             path_resolution: PathResolution::Config,
+            on_conflict: OnConflict::default(),
+            variables: Default::default(),
+            filter: Default::default(),
+            files: Vec::new(),
+            warnings: Vec::new(),
         };
 
         // Create lockfile
@@ -237,6 +562,138 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_scan_filtered_files_not_untracked() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        fs::write(source_dir.join("config.txt"), "source config")?;
+
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        fs::create_dir_all(&target_dir)?;
+        fs::write(target_dir.join(".DS_Store"), "")?; // ignored
+        fs::write(target_dir.join("user-custom.txt"), "custom")?; // untracked
+
+        config.packages[0].target = target_dir.clone();
+        config.packages[0].filter =
+            crate::filter::PathFilter::new(vec![".DS_Store".to_string()], vec![])?;
+
+        let scanner = Scanner::new(temp_path.clone());
+        let drift_items = scanner.scan_targets(&config, &lockfile)?;
+
+        assert_eq!(drift_items.len(), 1);
+        assert!(drift_items[0].target_path.ends_with("user-custom.txt"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_scan_does_not_follow_symlinks() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        fs::write(source_dir.join("config.txt"), "source config")?;
+        let plugins_dir = temp_path.join("source").join("plugins");
+        fs::create_dir_all(&plugins_dir)?;
+        fs::write(plugins_dir.join("plugin.txt"), "plugin")?;
+
+        // A LinkFolder link of another package inside the target, and a symlink loop
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        fs::create_dir_all(&target_dir)?;
+        std::os::unix::fs::symlink(&plugins_dir, target_dir.join("plugins"))?;
+        lockfile.add_link(target_dir.join("plugins"), plugins_dir.clone());
+        std::os::unix::fs::symlink(&target_dir, target_dir.join("loop"))?;
+        config.packages[0].target = target_dir.clone();
+
+        let reporter = Rc::new(RecordingReporter::default());
+        let scanner = Scanner::new(temp_path.clone()).with_reporter(reporter.clone());
+        let drift_items = scanner.scan_targets(&config, &lockfile)?;
+
+        // Only the loop link itself is untracked, nothing below either link is scanned
+        let untracked: Vec<_> = drift_items
+            .iter()
+            .filter(|item| item.drift_type == DriftType::Untracked)
+            .map(|item| item.target_path.clone())
+            .collect();
+        assert_eq!(untracked, vec![target_dir.join("loop")]);
+        assert!(reporter.warnings().is_empty());
+        let found: Vec<Event> = drift_items
+            .into_iter()
+            .map(|item| Event::DriftFound(Box::new(item)))
+            .collect();
+        assert!(reporter.events().ends_with(&found));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_detect_policy() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(source_dir.join("conf.d"))?;
+        fs::write(source_dir.join("conf.d").join("main.conf"), "main")?;
+
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        for file in [
+            "top.txt",
+            "conf.d/local.conf",
+            "conf.d/deep/cache.bin",
+            "other/file.txt",
+        ] {
+            let path = target_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, "untracked")?;
+        }
+        config.packages[0].target = target_dir.clone();
+
+        let untracked = |config: &DotyConfig| -> Result<Vec<Utf8PathBuf>> {
+            let scanner = Scanner::new(temp_path.clone());
+            Ok(scanner
+                .scan_targets(config, &lockfile)?
+                .into_iter()
+                .map(|item| {
+                    item.target_path
+                        .strip_prefix(&target_dir)
+                        .unwrap()
+                        .to_path_buf()
+                })
+                .collect())
+        };
+
+        assert_eq!(untracked(&config)?.len(), 4);
+        config.packages[0].detect = Some(DetectPolicy::Depth(2));
+        assert_eq!(
+            untracked(&config)?,
+            vec![
+                Utf8PathBuf::from("conf.d/local.conf"),
+                Utf8PathBuf::from("other/file.txt"),
+                Utf8PathBuf::from("top.txt"),
+            ]
+        );
+        config.packages[0].detect = Some(DetectPolicy::ManagedDirsOnly);
+        assert_eq!(
+            untracked(&config)?,
+            vec![Utf8PathBuf::from("conf.d/local.conf")]
+        );
+
+        // A package linked into the home directory only looks at managed directories by default
+        config.packages[0].detect = None;
+        assert_eq!(
+            config.packages[0].detect_policy(Utf8Path::new(&std::env::var("HOME")?)),
+            DetectPolicy::ManagedDirsOnly
+        );
+        assert_eq!(
+            config.packages[0].detect_policy(&target_dir),
+            DetectPolicy::Full
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_scan_link_folder_no_untracked_detection() -> Result<()> {
         let (_temp_dir, temp_path, mut config, lockfile) = setup_test_env()?;
@@ -271,7 +728,7 @@ mod tests {
 
     #[test]
     fn test_scan_broken_symlinks() -> Result<()> {
-        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+        let (_temp_dir, temp_path, config, mut lockfile) = setup_test_env()?;
 
         // Create source file
         let source_file = temp_path.join("source").join("test-app.txt");
@@ -338,6 +795,11 @@ mod tests {
             source: "source/another-app".into(),
             target: "~/.config/another-app".into(),
             strategy: LinkStrategy::LinkFolder,
+            on_conflict: None,
+            when: None,
+            filter: Default::default(),
+            detect_ignore: Vec::new(),
+            detect: None,
         });
 
         // Create source files for first package
@@ -403,6 +865,205 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_scan_copy_modified() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        fs::write(source_dir.join("config.txt"), "source config")?;
+        fs::write(source_dir.join("settings.json"), "{}")?;
+
+        // Both files were copied, then one was edited in place
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        fs::create_dir_all(&target_dir)?;
+        for name in ["config.txt", "settings.json"] {
+            let target = target_dir.join(name);
+            fs::copy(source_dir.join(name), &target)?;
+            lockfile.add_copy(target.clone(), source_dir.join(name), hash_file(&target)?);
+        }
+        fs::write(target_dir.join("settings.json"), "{\"edited\": true}")?;
+
+        config.packages[0].strategy = LinkStrategy::Copy;
+        config.packages[0].target = target_dir.clone();
+
+        let scanner = Scanner::new(temp_path.clone());
+        let drift_items = scanner.scan_targets(&config, &lockfile)?;
+
+        assert_eq!(drift_items.len(), 1);
+        assert_eq!(drift_items[0].drift_type, DriftType::Modified);
+        assert_eq!(drift_items[0].target_path, target_dir.join("settings.json"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_scan_replaced_links() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        fs::create_dir_all(&target_dir)?;
+        for name in ["intact.txt", "saved.txt", "moved.txt"] {
+            fs::write(source_dir.join(name), "source")?;
+            std::os::unix::fs::symlink(source_dir.join(name), target_dir.join(name))?;
+            lockfile.add_link(target_dir.join(name), source_dir.join(name));
+        }
+
+        // An editor saved atomically over one link, another link was pointed elsewhere
+        fs::remove_file(target_dir.join("saved.txt"))?;
+        fs::write(target_dir.join("saved.txt"), "edited")?;
+        fs::write(temp_path.join("elsewhere.txt"), "elsewhere")?;
+        fs::remove_file(target_dir.join("moved.txt"))?;
+        std::os::unix::fs::symlink(
+            temp_path.join("elsewhere.txt"),
+            target_dir.join("moved.txt"),
+        )?;
+
+        config.packages[0].target = target_dir.clone();
+        let scanner = Scanner::new(temp_path.clone());
+        let drift_items = scanner.scan_targets(&config, &lockfile)?;
+
+        let modified: Vec<_> = drift_items
+            .iter()
+            .filter(|item| item.drift_type == DriftType::Modified)
+            .collect();
+        assert_eq!(modified.len(), 2);
+        assert_eq!(modified[0].target_path, target_dir.join("moved.txt"));
+        assert!(modified[0].symlink_target.is_some());
+        assert_eq!(modified[1].target_path, target_dir.join("saved.txt"));
+        assert_eq!(modified[1].source_path, Some(source_dir.join("saved.txt")));
+        assert!(modified[1].symlink_target.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_scan_orphaned_links() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        let target_dir = temp_path.join("target").join(".config").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        fs::create_dir_all(&target_dir)?;
+        fs::write(source_dir.join("config.txt"), "config")?;
+        fs::write(temp_path.join("source").join("old.txt"), "old")?;
+        config.packages[0].target = target_dir.clone();
+
+        // A link whose package was removed from the config, and a link whose source file
+        // was deleted from a package that is still there
+        let old_link = temp_path.join("target").join("old.txt");
+        std::os::unix::fs::symlink(temp_path.join("source").join("old.txt"), &old_link)?;
+        lockfile.add_link(old_link.clone(), temp_path.join("source").join("old.txt"));
+        let gone = target_dir.join("gone.txt");
+        fs::write(&gone, "gone")?;
+        lockfile.add_link(gone.clone(), source_dir.join("gone.txt"));
+
+        let scanner = Scanner::new(temp_path.clone());
+        let drift_items = scanner.scan_targets(&config, &lockfile)?;
+
+        assert_eq!(drift_items.len(), 2, "{:?}", drift_items);
+        assert!(drift_items
+            .iter()
+            .all(|item| item.drift_type == DriftType::Orphaned));
+        let old = drift_items
+            .iter()
+            .find(|item| item.target_path == old_link)
+            .unwrap();
+        assert_eq!(
+            old.source_path,
+            Some(temp_path.join("source").join("old.txt"))
+        );
+        assert!(drift_items.iter().any(|item| item.target_path == gone));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_scan_stray_links() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, mut lockfile) = setup_test_env()?;
+
+        let source_dir = temp_path.join("source").join("test-app");
+        fs::create_dir_all(&source_dir)?;
+        fs::write(source_dir.join("config.txt"), "config")?;
+        fs::write(temp_path.join("source").join("notes.txt"), "notes")?;
+        let home = TempDir::new()?;
+        let home = Utf8PathBuf::from_path_buf(home.path().to_path_buf()).unwrap();
+        let target_dir = home.join(".config").join("test-app");
+        fs::create_dir_all(&target_dir)?;
+        config.packages[0].target = target_dir.clone();
+
+        // Left behind by stow: one link the package produces, one no package knows, one
+        // doty recorded and one pointing outside the repo
+        std::os::unix::fs::symlink(source_dir.join("config.txt"), target_dir.join("config.txt"))?;
+        std::os::unix::fs::symlink(temp_path.join("source/notes.txt"), home.join("notes.txt"))?;
+        std::os::unix::fs::symlink(source_dir.join("config.txt"), home.join("recorded.txt"))?;
+        lockfile.add_link(home.join("recorded.txt"), source_dir.join("config.txt"));
+        std::os::unix::fs::symlink(&target_dir, home.join("elsewhere"))?;
+
+        let scanner = Scanner::new(temp_path.clone());
+        let drift_items =
+            scanner.scan_stray(&config, &lockfile, &[home.clone(), home.join(".config")])?;
+
+        assert_eq!(drift_items.len(), 2, "{:?}", drift_items);
+        assert!(drift_items
+            .iter()
+            .all(|item| item.drift_type == DriftType::Stray));
+        assert_eq!(drift_items[0].target_path, target_dir.join("config.txt"));
+        assert_eq!(drift_items[0].package, Some(config.packages[0].clone()));
+        assert_eq!(drift_items[1].target_path, home.join("notes.txt"));
+        assert!(drift_items[1].package.is_none());
+        assert_eq!(
+            drift_items[1].source_path,
+            Some(temp_path.join("source/notes.txt").canonicalize_utf8()?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_unmapped() -> Result<()> {
+        let (_temp_dir, temp_path, mut config, _) = setup_test_env()?;
+
+        for file in [
+            "doty.kdl",
+            ".gitignore",
+            ".git/HEAD",
+            ".doty/state/test-host.lock.kdl",
+            "source/test-app/config.txt",
+            "source/helix/config.toml",
+            "source/helix/themes/dark.toml",
+            "source/.zshrc",
+            "source/.DS_Store",
+            "build/output.txt",
+        ] {
+            let path = temp_path.join(file);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, "")?;
+        }
+        fs::write(temp_path.join(".gitignore"), "build/\n")?;
+        config.files = vec![temp_path.join("doty.kdl")];
+        config.filter = crate::filter::PathFilter::new(vec![".DS_Store".to_string()], vec![])?;
+
+        let scanner = Scanner::new(temp_path.clone());
+        let unmapped = scanner.scan_unmapped(&config)?;
+
+        // target/ is empty, source/ holds a package so only its unmapped entries are listed
+        assert_eq!(
+            unmapped,
+            vec![
+                Utf8PathBuf::from("source/.zshrc"),
+                Utf8PathBuf::from("source/helix"),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_scan_broken_symlinks_relative_path_different_cwd() -> Result<()> {
         let (_temp_dir, temp_path, config, mut lockfile) = setup_test_env()?;
@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use minijinja::{Environment, UndefinedBehavior};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::fs_utils::hash_bytes;
+
+/// Variables available to Template packages
+/// Built-ins: `hostname`, `os`, `arch`, `username` and `home`, user variables come from the
+/// `variables` block in doty.kdl and win over built-ins of the same name
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateContext {
+    variables: BTreeMap<String, String>,
+}
+
+impl TemplateContext {
+    /// Collect the built-in variables for this machine and add the user variables
+    pub fn new(user_variables: &BTreeMap<String, String>) -> Result<Self> {
+        let mut variables = BTreeMap::new();
+        variables.insert(
+            "hostname".to_string(),
+            hostname::get()?.to_string_lossy().to_string(),
+        );
+        variables.insert("os".to_string(), std::env::consts::OS.to_string());
+        variables.insert("arch".to_string(), std::env::consts::ARCH.to_string());
+        variables.insert(
+            "username".to_string(),
+            std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default(),
+        );
+        variables.insert(
+            "home".to_string(),
+            std::env::var("HOME").context("HOME environment variable not set")?,
+        );
+        variables.extend(user_variables.clone());
+
+        Ok(Self { variables })
+    }
+
+    /// Create a context with exactly the given variables (no built-ins)
+    #[cfg(test)]
+    pub fn from_variables(variables: BTreeMap<String, String>) -> Self {
+        Self { variables }
+    }
+
+    /// Render a template string, undefined variables are an error
+    pub fn render_str(&self, name: &str, template: &str) -> Result<String> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_keep_trailing_newline(true);
+        env.render_named_str(name, template, &self.variables)
+            .map_err(|err| anyhow::anyhow!("{:#}", err))
+    }
+
+    /// Render a template file
+    pub fn render(&self, source: &Utf8Path) -> Result<String> {
+        let template = fs::read_to_string(source)
+            .with_context(|| format!("Failed to read template: {}", source))?;
+        self.render_str(source.as_str(), &template)
+            .with_context(|| format!("Failed to render template: {}", source))
+    }
+
+    /// Hash of the rendered output, as recorded in the lockfile
+    pub fn render_hash(&self, source: &Utf8Path) -> Result<String> {
+        Ok(hash_bytes(self.render(source)?.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext::from_variables(BTreeMap::from([
+            ("hostname".to_string(), "work-laptop".to_string()),
+            ("email".to_string(), "me@work.example".to_string()),
+        ]))
+    }
+
+    #[test]
+    fn test_render_str() {
+        let rendered = context()
+            .render_str(
+                ".gitconfig",
+                "[user]\n\temail = {{ email }}\n{% if hostname == \"work-laptop\" %}[core]\n\tautocrlf = input\n{% endif %}",
+            )
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "[user]\n\temail = me@work.example\n[core]\n\tautocrlf = input\n"
+        );
+    }
+
+    #[test]
+    fn test_render_undefined_variable() {
+        let err = context()
+            .render_str(".gitconfig", "email = {{ emial }}")
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("undefined"));
+    }
+
+    #[test]
+    fn test_builtin_variables() {
+        let user = BTreeMap::from([("os".to_string(), "plan9".to_string())]);
+        let context = TemplateContext::new(&user).unwrap();
+        let rendered = context
+            .render_str("t", "{{ arch }} {{ os }} {{ home }}")
+            .unwrap();
+        assert_eq!(
+            rendered,
+            format!(
+                "{} plan9 {}",
+                std::env::consts::ARCH,
+                std::env::var("HOME").unwrap()
+            )
+        );
+    }
+}
@@ -3,12 +3,13 @@ use std::path::Path;
 
 mod test_lib;
 use test_lib::cli_test_utils::{
-    is_symlink_to, run_doty_link, run_doty_link_dry_run, write_logfile,
+    is_symlink_to, run_doty, run_doty_link, run_doty_link_dry_run, write_logfile,
 };
 
 /// Test case: Link one folder (source/dummy) to another folder (target/dummy)
 /// Context:
 /// - no lockfile is present
+///
 /// Approved by: bjesuiter
 #[test]
 fn test_01_link_folder_simple() {
@@ -30,7 +31,7 @@ fn test_01_link_folder_simple() {
         fs::remove_dir_all(&lockfile_dir).ok();
     }
     // Clean up the file content in source/dummy/dummy.txt
-    fs::write(&source_dir.join("dummy/dummy.txt"), "Hello World").unwrap();
+    fs::write(source_dir.join("dummy/dummy.txt"), "Hello World").unwrap();
 
     // Ensure target directory exists and is empty
     if target_dir.exists() {
@@ -64,7 +65,7 @@ fn test_01_link_folder_simple() {
     );
 
     // Validate: changing the source file should update the target file
-    fs::write(&source_dir.join("dummy/dummy.txt"), "Hello World 2").unwrap();
+    fs::write(source_dir.join("dummy/dummy.txt"), "Hello World 2").unwrap();
     assert!(
         fs::read_to_string(&expected_file).unwrap() == "Hello World 2",
         "dummy.txt in target/dummy should contain 'Hello World 2'"
@@ -76,6 +77,7 @@ fn test_01_link_folder_simple() {
 /// - Starts with final state of the "simple" test (symlink exists, source exists)
 /// - Source directory is then deleted
 /// - Tests that doty handles missing source gracefully
+///
 /// Approved by: bjesuiter
 #[test]
 fn test_02_link_folder_src_gone() {
@@ -100,8 +102,8 @@ fn test_02_link_folder_src_gone() {
 
     // Reset source file content to known state
     // Ensure source directory exists
-    fs::create_dir_all(&source_dir.join("dummy")).expect("Failed to create source/dummy directory");
-    fs::write(&source_dir.join("dummy/dummy.txt"), "Hello World").unwrap();
+    fs::create_dir_all(source_dir.join("dummy")).expect("Failed to create source/dummy directory");
+    fs::write(source_dir.join("dummy/dummy.txt"), "Hello World").unwrap();
 
     // Step 3: Prepare target directory
     if target_dir.exists() {
@@ -124,7 +126,7 @@ fn test_02_link_folder_src_gone() {
     );
 
     // Step 6: Delete source directory (the key difference from simple test)
-    fs::remove_dir_all(&source_dir.join("dummy")).expect("Failed to remove source/dummy");
+    fs::remove_dir_all(source_dir.join("dummy")).expect("Failed to remove source/dummy");
 
     // Step 7: Validate symlink is now broken
     // Use symlink_metadata to check if symlink exists even when broken
@@ -205,7 +207,255 @@ fn test_02_link_folder_src_gone() {
 
     // Step 13: Validate symlink was removed
     assert!(
-        !fs::symlink_metadata(&expected_symlink).is_ok(),
+        fs::symlink_metadata(&expected_symlink).is_err(),
         "Symlink 'dummy' should be removed after running doty link"
     );
 }
+
+/// Prepares a conflict test case: a real directory sits where the symlink should go
+fn setup_conflict_case(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let test_case_dir = Path::new("tests/01_link_folder")
+        .join(name)
+        .canonicalize()
+        .unwrap();
+    let lockfile_dir = test_case_dir.join(".doty/state");
+    if lockfile_dir.exists() {
+        fs::remove_dir_all(&lockfile_dir).ok();
+    }
+    let target_dir = test_case_dir.join("target");
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir).expect("Failed to clean target directory");
+    }
+    fs::create_dir_all(target_dir.join("dummy")).expect("Failed to create target/dummy");
+    fs::write(target_dir.join("dummy/local.txt"), "Local changes").unwrap();
+    (test_case_dir, target_dir)
+}
+
+/// Test case: Target is occupied by a real directory, default onConflict policy
+/// Context:
+/// - no lockfile is present
+/// - target/dummy is a plain directory not managed by doty
+///
+/// Expected: link aborts without touching the directory
+#[test]
+fn test_03_link_folder_conflict_fail() {
+    let (test_case_dir, target_dir) = setup_conflict_case("conflict_fail");
+    let config_path = test_case_dir.join("doty.kdl");
+
+    let dry_run_output = run_doty_link_dry_run(&config_path)
+        .expect("doty link --dry-run should report conflicts without failing");
+    assert!(
+        dry_run_output.contains("Conflict: existing directory is not managed by doty"),
+        "Dry run should report the conflict"
+    );
+
+    let err = run_doty_link(&config_path).expect_err("doty link should fail on conflict");
+    write_logfile(&test_case_dir, "real-run.log", &err).expect("Failed to write log file");
+    assert!(
+        err.contains("1 conflict at link targets"),
+        "Error should mention the conflict"
+    );
+
+    let occupied = target_dir.join("dummy");
+    assert!(
+        !occupied.is_symlink(),
+        "target/dummy should still be a real directory"
+    );
+    assert_eq!(
+        fs::read_to_string(occupied.join("local.txt")).unwrap(),
+        "Local changes"
+    );
+}
+
+/// Test case: Target is occupied by a real directory, onConflict "backup"
+/// Context:
+/// - no lockfile is present
+/// - target/dummy is a plain directory not managed by doty
+///
+/// Expected: the directory is moved into the backup store and the symlink is created,
+/// `doty restore --all` puts the directory back
+#[test]
+fn test_04_link_folder_conflict_backup() {
+    let (test_case_dir, target_dir) = setup_conflict_case("conflict_backup");
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source_dir = test_case_dir.join("source");
+    let backups_dir = test_case_dir.join(".doty/backups");
+    if backups_dir.exists() {
+        fs::remove_dir_all(&backups_dir).ok();
+    }
+
+    let output = run_doty_link(&config_path).expect("doty link should succeed");
+    write_logfile(&test_case_dir, "real-run.log", &output).expect("Failed to write log file");
+
+    let occupied = target_dir.join("dummy");
+    assert!(
+        is_symlink_to(&occupied, &source_dir.join("dummy")),
+        "target/dummy should now point to source/dummy"
+    );
+
+    let list_output = run_doty(&["backups", "list", "--config", config_arg])
+        .expect("doty backups list should succeed");
+    write_logfile(&test_case_dir, "backups-list.log", &list_output)
+        .expect("Failed to write log file");
+    assert!(
+        list_output.contains("target/dummy") && list_output.contains("directory"),
+        "The previous directory should be listed as a backup"
+    );
+
+    let restore_output = run_doty(&["restore", "--all", "--config", config_arg])
+        .expect("doty restore should succeed");
+    write_logfile(&test_case_dir, "restore.log", &restore_output)
+        .expect("Failed to write log file");
+
+    assert!(
+        !occupied.is_symlink(),
+        "target/dummy should be a real directory again"
+    );
+    assert_eq!(
+        fs::read_to_string(occupied.join("local.txt")).unwrap(),
+        "Local changes",
+        "The previous directory should be restored"
+    );
+}
+
+/// Prepares a test case where one of two links cannot be created
+fn setup_failing_case(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let test_case_dir = Path::new("tests/01_link_folder")
+        .join(name)
+        .canonicalize()
+        .unwrap();
+    let doty_dir = test_case_dir.join(".doty");
+    if doty_dir.exists() {
+        fs::remove_dir_all(&doty_dir).ok();
+    }
+    let target_dir = test_case_dir.join("target");
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir).expect("Failed to clean target directory");
+    }
+    fs::create_dir_all(&target_dir).expect("Failed to create target directory");
+    fs::write(target_dir.join("blocker"), "Not a directory").unwrap();
+    (test_case_dir, target_dir)
+}
+
+/// Test case: One link fails midway
+/// Context:
+/// - no lockfile is present
+/// - target/blocker is a file, so target/blocker/other cannot be created
+///
+/// Expected: link fails, everything it already did is rolled back, no lockfile or journal is left
+#[test]
+fn test_05_link_folder_rollback() {
+    let (test_case_dir, target_dir) = setup_failing_case("rollback");
+    let config_path = test_case_dir.join("doty.kdl");
+
+    let err = run_doty_link(&config_path).expect_err("doty link should fail");
+    write_logfile(&test_case_dir, "real-run.log", &err).expect("Failed to write log file");
+    assert!(
+        err.contains("rolled back"),
+        "Error should mention the rollback"
+    );
+
+    assert!(
+        fs::symlink_metadata(target_dir.join("dummy")).is_err(),
+        "The link that succeeded should be rolled back"
+    );
+    let state_dir = test_case_dir.join(".doty/state");
+    let leftovers: Vec<_> = fs::read_dir(&state_dir)
+        .map(|entries| entries.flatten().map(|e| e.file_name()).collect())
+        .unwrap_or_default();
+    assert!(
+        leftovers.is_empty(),
+        "Neither a lockfile nor a journal should be left: {:?}",
+        leftovers
+    );
+}
+
+/// Test case: One link fails midway, with --keep-going
+/// Context:
+/// - no lockfile is present
+/// - target/blocker is a file, so target/blocker/other cannot be created
+///
+/// Expected: the other link is created and recorded, the failure is reported at the end
+#[test]
+fn test_06_link_folder_keep_going() {
+    let (test_case_dir, target_dir) = setup_failing_case("keep_going");
+    let config_path = test_case_dir.join("doty.kdl");
+    let source_dir = test_case_dir.join("source");
+
+    let err = run_doty(&[
+        "link",
+        "--keep-going",
+        "--config",
+        config_path.to_str().unwrap(),
+    ])
+    .expect_err("doty link should report the failure");
+    write_logfile(&test_case_dir, "real-run.log", &err).expect("Failed to write log file");
+    assert!(
+        err.contains("1 link failed"),
+        "Error should count the failure"
+    );
+
+    assert!(
+        is_symlink_to(&target_dir.join("dummy"), &source_dir.join("dummy")),
+        "The other link should still be created"
+    );
+    let state_dir = test_case_dir.join(".doty/state");
+    let lockfile = fs::read_dir(&state_dir)
+        .unwrap()
+        .flatten()
+        .find(|e| e.file_name().to_string_lossy().ends_with(".lock.kdl"))
+        .expect("Lockfile should be saved");
+    let content = fs::read_to_string(lockfile.path()).unwrap();
+    assert!(content.contains("target/dummy"));
+    assert!(!content.contains("target/blocker/other"));
+    assert!(
+        !fs::read_dir(&state_dir)
+            .unwrap()
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().ends_with(".journal")),
+        "The journal should be removed"
+    );
+}
+
+/// Test case: Targets with a literal `$` and with a variable whose value contains `$`
+/// Context:
+/// - no lockfile is present
+/// - source/dummy targets "target/cost$$/dummy", source/other targets "target/$price/other"
+///   with `price "cost$1"` in the variables block
+///
+/// Expected: paths are expanded exactly once, so the links land in target/cost$ and
+/// target/cost$1, and relinking, status and clean work with the recorded paths
+#[test]
+fn test_07_link_folder_dollar_paths() {
+    let test_case_dir = Path::new("tests/01_link_folder/variables")
+        .canonicalize()
+        .unwrap();
+    for dir in ["target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source_dir = test_case_dir.join("source");
+    let dummy_link = test_case_dir.join("target/cost$/dummy");
+    let other_link = test_case_dir.join("target/cost$1/other");
+
+    let output = run_doty_link(&config_path).expect("doty link should succeed");
+    write_logfile(&test_case_dir, "link.log", &output).expect("Failed to write log file");
+    assert!(is_symlink_to(&dummy_link, &source_dir.join("dummy")));
+    assert!(is_symlink_to(&other_link, &source_dir.join("other")));
+
+    // The lockfile paths are read back without being expanded again
+    let output = run_doty_link(&config_path).expect("doty link should succeed again");
+    write_logfile(&test_case_dir, "relink.log", &output).expect("Failed to write log file");
+    let output = run_doty(&["status", "--config", config_arg]).expect("doty status should succeed");
+    write_logfile(&test_case_dir, "status.log", &output).expect("Failed to write log file");
+    assert!(output.contains("cost$1/other"));
+
+    run_doty(&["clean", "--config", config_arg]).expect("doty clean should succeed");
+    assert!(fs::symlink_metadata(&dummy_link).is_err());
+    assert!(fs::symlink_metadata(&other_link).is_err());
+}
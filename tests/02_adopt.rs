@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{is_symlink_to, run_doty, write_logfile};
+
+/// Reset a test case directory to its seed state
+/// (doty.kdl is rewritten by adopt, so every run starts from doty.seed.kdl)
+fn reset_test_case(test_case_dir: &Path) {
+    for dir in ["source", "target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    fs::copy(
+        test_case_dir.join("doty.seed.kdl"),
+        test_case_dir.join("doty.kdl"),
+    )
+    .expect("Failed to reset doty.kdl from seed");
+}
+
+/// Test case: Adopt an existing config folder with the LinkFolder strategy
+/// Context:
+/// - target/alacritty is a real directory with two files
+/// - doty.kdl contains no packages yet
+#[test]
+fn test_01_adopt_link_folder() {
+    let test_case_dir = Path::new("tests/02_adopt/link_folder")
+        .canonicalize()
+        .unwrap();
+    reset_test_case(&test_case_dir);
+
+    let config_path = test_case_dir.join("doty.kdl");
+    let target_dir = test_case_dir.join("target/alacritty");
+    fs::create_dir_all(target_dir.join("themes")).unwrap();
+    fs::write(target_dir.join("alacritty.toml"), "[window]").unwrap();
+    fs::write(target_dir.join("themes/dark.toml"), "[colors]").unwrap();
+
+    let output = run_doty(&[
+        "adopt",
+        target_dir.to_str().unwrap(),
+        "--strategy",
+        "link-folder",
+        "--source",
+        "source/alacritty",
+        "--config",
+        config_path.to_str().unwrap(),
+    ])
+    .expect("doty adopt should succeed");
+    write_logfile(&test_case_dir, "adopt.log", &output).expect("Failed to write adopt log file");
+
+    // Files were moved into the repo
+    let source_dir = test_case_dir.join("source/alacritty");
+    assert_eq!(
+        fs::read_to_string(source_dir.join("alacritty.toml")).unwrap(),
+        "[window]"
+    );
+    assert_eq!(
+        fs::read_to_string(source_dir.join("themes/dark.toml")).unwrap(),
+        "[colors]"
+    );
+
+    // The target is now a symlink to the adopted folder
+    assert!(
+        is_symlink_to(&target_dir, &source_dir),
+        "target/alacritty should be a symlink to source/alacritty"
+    );
+
+    // doty.kdl keeps its comments and gained the new package
+    let config = fs::read_to_string(&config_path).unwrap();
+    assert!(config.contains("// Packages get appended below by `doty adopt`"));
+    assert!(config.contains(r#"LinkFolder "source/alacritty" target="target/alacritty""#));
+
+    // A second link run has nothing left to do
+    let output = run_doty(&["link", "--config", config_path.to_str().unwrap()])
+        .expect("doty link should succeed");
+    assert!(output.contains("already up to date"));
+
+    // A file below the linked folder is in the repo already
+    let err = run_doty(&[
+        "adopt",
+        target_dir.join("alacritty.toml").to_str().unwrap(),
+        "--source",
+        "source/alacritty.toml",
+        "--config",
+        config_path.to_str().unwrap(),
+    ])
+    .expect_err("adopting a file below a LinkFolder target should fail");
+    assert!(
+        err.contains("already managed by package LinkFolder"),
+        "{}",
+        err
+    );
+}
+
+/// Test case: Adopt a single file (strategy defaults to LinkFilesRecursive)
+/// Context:
+/// - target/.zshrc is a regular file
+#[test]
+fn test_02_adopt_single_file() {
+    let test_case_dir = Path::new("tests/02_adopt/single_file")
+        .canonicalize()
+        .unwrap();
+    reset_test_case(&test_case_dir);
+
+    let config_path = test_case_dir.join("doty.kdl");
+    let target_file = test_case_dir.join("target/.zshrc");
+    fs::create_dir_all(target_file.parent().unwrap()).unwrap();
+    fs::write(&target_file, "export EDITOR=nvim").unwrap();
+
+    run_doty(&[
+        "adopt",
+        target_file.to_str().unwrap(),
+        "--source",
+        "source/zsh/.zshrc",
+        "--config",
+        config_path.to_str().unwrap(),
+    ])
+    .expect("doty adopt should succeed");
+
+    let source_file = test_case_dir.join("source/zsh/.zshrc");
+    assert_eq!(
+        fs::read_to_string(&source_file).unwrap(),
+        "export EDITOR=nvim"
+    );
+    assert!(is_symlink_to(&target_file, &source_file));
+
+    let config = fs::read_to_string(&config_path).unwrap();
+    assert!(config.contains(r#"LinkFilesRecursive "source/zsh/.zshrc" target="target/.zshrc""#));
+
+    // Adopting the same path again is refused
+    let result = run_doty(&[
+        "adopt",
+        target_file.to_str().unwrap(),
+        "--source",
+        "source/zsh/.zshrc2",
+        "--config",
+        config_path.to_str().unwrap(),
+    ]);
+    assert!(result.is_err(), "adopting a managed symlink should fail");
+}
+
+/// Test case: A failed link after moving the files undoes the whole adopt
+/// Context:
+/// - doty.kdl has a package whose target/git/config is an unmanaged file (onConflict fail)
+/// - target/.zshrc is a regular file
+///
+/// Expected: adopt fails, target/.zshrc is back in place and doty.kdl is unchanged
+#[test]
+fn test_03_adopt_undone_on_link_failure() {
+    let test_case_dir = Path::new("tests/02_adopt/link_failure")
+        .canonicalize()
+        .unwrap();
+    reset_test_case(&test_case_dir);
+
+    let config_path = test_case_dir.join("doty.kdl");
+    fs::create_dir_all(test_case_dir.join("source/git")).unwrap();
+    fs::write(test_case_dir.join("source/git/config"), "[user]").unwrap();
+    fs::create_dir_all(test_case_dir.join("target/git")).unwrap();
+    fs::write(test_case_dir.join("target/git/config"), "[core]").unwrap();
+    let target_file = test_case_dir.join("target/.zshrc");
+    fs::write(&target_file, "export EDITOR=nvim").unwrap();
+
+    let err = run_doty(&[
+        "adopt",
+        target_file.to_str().unwrap(),
+        "--source",
+        "source/zsh/.zshrc",
+        "--config",
+        config_path.to_str().unwrap(),
+    ])
+    .expect_err("adopt should fail when linking fails");
+    assert!(err.contains("DOTY-E030"), "{}", err);
+
+    assert_eq!(
+        fs::read_to_string(&target_file).unwrap(),
+        "export EDITOR=nvim"
+    );
+    assert!(!target_file.is_symlink());
+    assert!(!test_case_dir.join("source/zsh/.zshrc").exists());
+    assert_eq!(
+        fs::read_to_string(&config_path).unwrap(),
+        fs::read_to_string(test_case_dir.join("doty.seed.kdl")).unwrap()
+    );
+}
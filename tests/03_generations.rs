@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{is_symlink_to, run_doty, run_doty_link, write_logfile};
+
+const ONE_PACKAGE: &str = r#"defaults {
+    pathResolution "config"
+}
+
+LinkFolder "source/dummy" target="target/dummy"
+"#;
+
+const TWO_PACKAGES: &str = r#"defaults {
+    pathResolution "config"
+}
+
+LinkFolder "source/dummy" target="target/dummy"
+LinkFolder "source/other" target="target/other"
+"#;
+
+/// Test case: Every link writes a generation, rollback goes back to the previous one
+/// Context:
+/// - doty.kdl is written by the test, first with one package, then with two
+///
+/// Expected: two generations, `doty rollback` removes the second link and records generation 3
+#[test]
+fn test_01_generations_rollback() {
+    let test_case_dir = Path::new("tests/03_generations/rollback")
+        .canonicalize()
+        .unwrap();
+    for dir in ["target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source_dir = test_case_dir.join("source");
+    let target_dir = test_case_dir.join("target");
+
+    fs::write(&config_path, ONE_PACKAGE).unwrap();
+    let output = run_doty_link(&config_path).expect("first doty link should succeed");
+    assert!(output.contains("(generation 1)"));
+
+    fs::write(&config_path, TWO_PACKAGES).unwrap();
+    let output = run_doty_link(&config_path).expect("second doty link should succeed");
+    assert!(output.contains("(generation 2)"));
+
+    // Nothing changed, so no new generation
+    let output = run_doty_link(&config_path).expect("third doty link should succeed");
+    assert!(!output.contains("(generation"));
+
+    let list_output =
+        run_doty(&["generations", "--config", config_arg]).expect("doty generations should work");
+    write_logfile(&test_case_dir, "generations.log", &list_output)
+        .expect("Failed to write log file");
+    assert!(list_output.contains("2 generations"));
+    assert!(list_output.contains("1 added"));
+
+    let rollback_output =
+        run_doty(&["rollback", "--config", config_arg]).expect("doty rollback should succeed");
+    write_logfile(&test_case_dir, "rollback.log", &rollback_output)
+        .expect("Failed to write log file");
+    assert!(rollback_output.contains("generation 1"));
+    assert!(rollback_output.contains("(generation 3)"));
+
+    assert!(
+        is_symlink_to(&target_dir.join("dummy"), &source_dir.join("dummy")),
+        "The link from generation 1 should stay"
+    );
+    assert!(
+        fs::symlink_metadata(target_dir.join("other")).is_err(),
+        "The link added in generation 2 should be removed"
+    );
+
+    // Rolling forward works the same way
+    run_doty(&["rollback", "2", "--config", config_arg]).expect("doty rollback 2 should succeed");
+    assert!(is_symlink_to(
+        &target_dir.join("other"),
+        &source_dir.join("other")
+    ));
+}
+
+/// Test case: A copy with new content is a new generation, rolling it back keeps the current content
+/// Context:
+/// - doty.kdl (written by the test) copies source/git/config to target/git/config
+/// - the test changes the source between two links
+///
+/// Expected: generations 1 and 2 with the same link, `doty rollback` warns that the copy keeps the
+/// current source content (so nothing changes and no generation is recorded)
+#[test]
+fn test_02_copy_generations() {
+    let test_case_dir = Path::new("tests/03_generations/copy")
+        .canonicalize()
+        .unwrap();
+    for dir in ["source", "target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source_file = test_case_dir.join("source/git/config");
+    fs::create_dir_all(source_file.parent().unwrap()).unwrap();
+    fs::write(&source_file, "[user]").unwrap();
+    fs::write(&config_path, "Copy \"source/git\" target=\"target/git\"\n").unwrap();
+
+    let output = run_doty_link(&config_path).expect("first doty link should succeed");
+    assert!(output.contains("(generation 1)"));
+
+    fs::write(&source_file, "[user]\nname = doty").unwrap();
+    let output = run_doty_link(&config_path).expect("second doty link should succeed");
+    assert!(output.contains("(generation 2)"), "{}", output);
+
+    let rollback_output =
+        run_doty(&["rollback", "--config", config_arg]).expect("doty rollback should succeed");
+    write_logfile(&test_case_dir, "rollback.log", &rollback_output)
+        .expect("Failed to write log file");
+    assert!(rollback_output.contains("generation 1"));
+    assert!(rollback_output.contains("not the content of generation 1"));
+    assert_eq!(
+        fs::read_to_string(test_case_dir.join("target/git/config")).unwrap(),
+        "[user]\nname = doty"
+    );
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{run_doty_link, run_doty_with_code, write_logfile};
+
+/// Test case: Copy writes real files, refreshes them and keeps local edits
+/// Context:
+/// - source/ is written by the test, doty.kdl has a single Copy package
+///
+/// Expected: settings.json is a regular file, a source change is copied over, a local edit is
+/// reported by `doty detect` and left in place by `doty link`
+#[test]
+fn test_01_copy() {
+    let test_case_dir = Path::new("tests/04_copy/copy").canonicalize().unwrap();
+    for dir in ["target", ".doty", "source"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source = test_case_dir.join("source/app/settings.json");
+    let target = test_case_dir.join("target/app/settings.json");
+
+    fs::create_dir_all(source.parent().unwrap()).unwrap();
+    fs::write(&source, "{}").unwrap();
+
+    let output = run_doty_link(&config_path).expect("doty link should succeed");
+    write_logfile(&test_case_dir, "link.log", &output).expect("Failed to write log file");
+    assert!(output.contains("(copy)"));
+    assert!(
+        fs::symlink_metadata(&target).unwrap().file_type().is_file(),
+        "The target should be a real file, not a symlink"
+    );
+    assert_eq!(fs::read_to_string(&target).unwrap(), "{}");
+
+    let lockfile = fs::read_dir(test_case_dir.join(".doty/state"))
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().ends_with(".lock.kdl"))
+        .expect("lockfile should exist");
+    assert!(fs::read_to_string(lockfile.path())
+        .unwrap()
+        .contains("hash="));
+
+    // A source change is copied over the untouched target
+    fs::write(&source, r#"{"theme": "dark"}"#).unwrap();
+    run_doty_link(&config_path).expect("doty link should refresh the copy");
+    assert_eq!(fs::read_to_string(&target).unwrap(), r#"{"theme": "dark"}"#);
+
+    // A local edit is reported and not overwritten
+    fs::write(&target, r#"{"theme": "light"}"#).unwrap();
+    let (code, detect_output) = run_doty_with_code(&["detect", "--config", config_arg]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &detect_output).expect("Failed to write log file");
+    assert!(detect_output.contains("Modified copies"));
+
+    let output = run_doty_link(&config_path).expect("doty link should warn, not fail");
+    assert!(output.contains("edited locally"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        r#"{"theme": "light"}"#
+    );
+}
+
+/// Test case: Template renders built-in and user variables into the target
+/// Context:
+/// - source/ is written by the test, doty.kdl has a `variables` block and a single Template package
+///
+/// Expected: .gitconfig is rendered with the user email and the hostname, a hand edit is reported
+/// by `doty detect`
+#[test]
+fn test_02_template() {
+    let test_case_dir = Path::new("tests/04_copy/template").canonicalize().unwrap();
+    for dir in ["target", ".doty", "source"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let source = test_case_dir.join("source/git/.gitconfig");
+    let target = test_case_dir.join("target/git/.gitconfig");
+
+    fs::create_dir_all(source.parent().unwrap()).unwrap();
+    fs::write(
+        &source,
+        "[user]\n\temail = {{ email }}\n# {{ hostname }} ({{ os }})\n",
+    )
+    .unwrap();
+
+    let output = run_doty_link(&config_path).expect("doty link should succeed");
+    write_logfile(&test_case_dir, "link.log", &output).expect("Failed to write log file");
+    assert!(output.contains("(rendered)"));
+
+    let hostname = hostname::get().unwrap().to_string_lossy().to_string();
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        format!(
+            "[user]\n\temail = me@example.com\n# {} ({})\n",
+            hostname,
+            std::env::consts::OS
+        )
+    );
+
+    // Linking again renders the same output, nothing to do
+    let output = run_doty_link(&config_path).expect("second doty link should succeed");
+    assert!(output.contains("already up to date"));
+
+    fs::write(&target, "[user]\n\temail = edited@example.com\n").unwrap();
+    let (code, detect_output) = run_doty_with_code(&["detect", "--config", config_arg]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &detect_output).expect("Failed to write log file");
+    assert!(detect_output.contains("Modified copies"));
+}
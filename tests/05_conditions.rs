@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{is_symlink_to, run_doty, run_doty_link, write_logfile};
+
+/// Test case: A package with a `when` condition is only linked where it matches
+/// Context:
+/// - doty.kdl links source/sway only when DOTY_TEST_SESSION=wayland (and not on Windows)
+///
+/// Expected: linked while the variable is set, removed as an orphan once it no longer matches,
+/// and `doty status` shows the package as skipped
+#[test]
+fn test_01_env_condition() {
+    let test_case_dir = Path::new("tests/05_conditions/env").canonicalize().unwrap();
+    for dir in ["target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let target = test_case_dir.join("target/sway");
+
+    // The doty child processes inherit the environment of the test
+    std::env::set_var("DOTY_TEST_SESSION", "wayland");
+    run_doty_link(&config_path).expect("doty link should succeed");
+    assert!(is_symlink_to(&target, &test_case_dir.join("source/sway")));
+
+    std::env::set_var("DOTY_TEST_SESSION", "x11");
+    let output = run_doty_link(&config_path).expect("doty link should succeed");
+    write_logfile(&test_case_dir, "link.log", &output).expect("Failed to write log file");
+    assert!(
+        fs::symlink_metadata(&target).is_err(),
+        "The link of a package that no longer matches should be removed"
+    );
+
+    let status_output =
+        run_doty(&["status", "--config", config_arg]).expect("doty status should succeed");
+    write_logfile(&test_case_dir, "status.log", &status_output).expect("Failed to write log file");
+    assert!(status_output
+        .contains("skipped (when all(env=DOTY_TEST_SESSION=wayland, not(os=windows)))"));
+}
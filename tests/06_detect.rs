@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{run_doty, run_doty_link, run_doty_with_code, write_logfile};
+
+/// Remove what earlier runs left behind in a test case directory
+fn clean_test_case(test_case_dir: &Path) {
+    for dir in ["target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+}
+
+/// Test case: `.dotyignore` files and `detectIgnore` hide untracked files from `doty detect`
+/// Context:
+/// - .dotyignore in the repo root ignores fish_variables in every package
+/// - source/fish/.dotyignore ignores conf.d/local.fish
+/// - the package ignores *.cache with detectIgnore
+///
+/// Expected: only notes.txt is reported as untracked, and the package's .dotyignore is not linked
+#[test]
+fn test_01_dotyignore() {
+    let test_case_dir = Path::new("tests/06_detect/dotyignore")
+        .canonicalize()
+        .unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let target = test_case_dir.join("target/fish");
+
+    run_doty_link(&config_path).expect("doty link should succeed");
+    assert!(target.join("config.fish").exists());
+    assert!(!target.join(".dotyignore").exists());
+
+    fs::create_dir_all(target.join("conf.d")).unwrap();
+    for file in [
+        "fish_variables",
+        "completions.cache",
+        "conf.d/local.fish",
+        "notes.txt",
+    ] {
+        fs::write(target.join(file), "untracked").unwrap();
+    }
+
+    let (code, output) = run_doty_with_code(&["detect", "--config", config_path.to_str().unwrap()]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &output).expect("Failed to write log file");
+    assert!(output.contains("notes.txt"));
+    for ignored in ["fish_variables", "completions.cache", "local.fish"] {
+        assert!(!output.contains(ignored), "{} should be ignored", ignored);
+    }
+}
+
+/// Test case: A link that an editor replaced with a regular file is reported as modified
+/// Context:
+/// - doty.kdl links source/git/config into target/git
+/// - the test replaces the symlink with an edited regular file
+///
+/// Expected: `doty detect` lists it under "Replaced links" with a diff against the source
+#[test]
+fn test_02_replaced_link() {
+    let test_case_dir = Path::new("tests/06_detect/replaced")
+        .canonicalize()
+        .unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let target = test_case_dir.join("target/git/config");
+
+    run_doty_link(&config_path).expect("doty link should succeed");
+    fs::remove_file(&target).unwrap();
+    fs::write(&target, "[user]\n\tname = Someone Else\n").unwrap();
+
+    let (code, output) = run_doty_with_code(&["detect", "--config", config_path.to_str().unwrap()]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &output).expect("Failed to write log file");
+    assert!(output.contains("Replaced links"));
+    assert!(output.contains("replaced by a regular file"));
+    assert!(output.contains("-\tname = Doty"));
+    assert!(output.contains("+\tname = Someone Else"));
+}
+
+/// Test case: Links whose package was removed from doty.kdl are reported as orphaned
+/// Context:
+/// - the test writes doty.kdl with two packages, links them, then removes the kitty package
+///
+/// Expected: `doty detect` lists the kitty link under "Orphaned links" with its current state,
+/// and the next `doty link` removes it
+#[test]
+fn test_03_orphaned_link() {
+    let test_case_dir = Path::new("tests/06_detect/orphaned")
+        .canonicalize()
+        .unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+
+    fs::write(
+        &config_path,
+        "LinkFolder \"source/alacritty\" target=\"target/alacritty\"\n\
+         LinkFolder \"source/kitty\" target=\"target/kitty\"\n",
+    )
+    .unwrap();
+    run_doty_link(&config_path).expect("doty link should succeed");
+    fs::write(
+        &config_path,
+        "LinkFolder \"source/alacritty\" target=\"target/alacritty\"\n",
+    )
+    .unwrap();
+
+    let (code, output) = run_doty_with_code(&["detect", "--config", config_arg]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &output).expect("Failed to write log file");
+    assert!(output.contains("Orphaned links"));
+    assert!(output.contains("target/kitty (still linked)"));
+    assert!(!output.contains("target/alacritty"));
+
+    run_doty_link(&config_path).expect("doty link should succeed");
+    assert!(fs::symlink_metadata(test_case_dir.join("target/kitty")).is_err());
+    let output = run_doty(&["detect", "--config", config_arg]).expect("doty detect should succeed");
+    assert!(output.contains("No drift detected"));
+}
+
+/// Test case: Symlinks into the repo that are not in the lockfile are reported as stray
+/// Context:
+/// - doty.kdl links source/nvim into target/nvim, but doty never ran
+/// - the test links target/nvim like stow would and target/notes.txt to a file no package uses
+///
+/// Expected: `doty detect --stray` lists target/nvim as matching the package and notes.txt as
+/// unknown, the regular `doty detect` doesn't report them
+#[test]
+#[cfg(unix)]
+fn test_04_stray_links() {
+    let test_case_dir = Path::new("tests/06_detect/stray").canonicalize().unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let config_arg = config_path.to_str().unwrap();
+    let target = test_case_dir.join("target");
+
+    fs::create_dir_all(&target).unwrap();
+    std::os::unix::fs::symlink("../source/nvim", target.join("nvim")).unwrap();
+    std::os::unix::fs::symlink(
+        test_case_dir.join("source/notes.txt"),
+        target.join("notes.txt"),
+    )
+    .unwrap();
+
+    let (code, output) = run_doty_with_code(&[
+        "detect",
+        "--config",
+        config_arg,
+        "--stray",
+        "--root",
+        target.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &output).expect("Failed to write log file");
+    let (matching, unknown) = output
+        .split_once("Stray links no package produces")
+        .expect("unknown stray links should be listed");
+    assert!(matching.contains("Stray links matching a package"));
+    assert!(matching.contains("target/nvim (LinkFolder source/nvim)"));
+    assert!(unknown.contains("target/notes.txt → source/notes.txt"));
+
+    let output = run_doty(&["detect", "--config", config_arg]).expect("doty detect should succeed");
+    assert!(!output.contains("notes.txt"));
+}
+
+/// Test case: Repo files and directories no package links are reported with a package node
+/// Context:
+/// - doty.kdl links config/nvim, config/helix and .zshrc have no package
+///
+/// Expected: `doty detect --unmapped` lists config/helix (target guessed from config/nvim) and
+/// .zshrc (target in home), but not doty.kdl, .gitignore or the mapped config/nvim
+#[test]
+fn test_05_unmapped_sources() {
+    let test_case_dir = Path::new("tests/06_detect/unmapped")
+        .canonicalize()
+        .unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+
+    let (code, output) = run_doty_with_code(&[
+        "detect",
+        "--config",
+        config_path.to_str().unwrap(),
+        "--unmapped",
+    ]);
+    assert_eq!(code, 3, "doty detect should exit with 3 (drift found)");
+    write_logfile(&test_case_dir, "detect.log", &output).expect("Failed to write log file");
+    assert!(output.contains("config/helix/"));
+    assert!(output.contains("LinkFolder \"config/helix\" target=\"target/helix\""));
+    assert!(output.contains("LinkFilesRecursive \".zshrc\" target=\"~/.zshrc\""));
+    let listed: Vec<&str> = output.lines().filter(|l| l.contains("[?]")).collect();
+    assert_eq!(listed.len(), 2, "{:?}", listed);
+    for mapped in ["nvim", "doty.kdl", ".gitignore"] {
+        assert!(
+            !listed.iter().any(|l| l.contains(mapped)),
+            "{} should not be listed",
+            mapped
+        );
+    }
+}
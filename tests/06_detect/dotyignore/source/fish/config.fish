@@ -0,0 +1 @@
+set -g fish_greeting
@@ -0,0 +1 @@
+vim.o.number = true
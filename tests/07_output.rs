@@ -0,0 +1,185 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+mod test_lib;
+use test_lib::cli_test_utils::{run_doty_with_code, write_logfile};
+
+/// Remove what earlier runs left behind in a test case directory
+fn clean_test_case(test_case_dir: &Path) {
+    for dir in ["target", ".doty"] {
+        let path = test_case_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("Failed to clean test case directory");
+        }
+    }
+}
+
+/// Run doty with `--output json` and parse the document
+fn run_json(test_case_dir: &Path, args: &[&str], logfile_name: &str) -> (i32, Value) {
+    let config_path = test_case_dir.join("doty.kdl");
+    let mut args = args.to_vec();
+    args.extend([
+        "--config",
+        config_path.to_str().unwrap(),
+        "--output",
+        "json",
+    ]);
+    let (code, output) = run_doty_with_code(&args);
+    write_logfile(test_case_dir, logfile_name, &output).expect("Failed to write log file");
+    let document = serde_json::from_str(&output).expect("stdout should be one JSON document");
+    (code, document)
+}
+
+/// Test case: link, status, detect and clean report in JSON with a schema version and exit codes
+/// Context:
+/// - doty.kdl links source/fish/config.fish into target/fish
+/// - the test adds an untracked file to target/fish before running detect
+///
+/// Expected: exit code 2 for the dry run (pending), 0 after linking, 3 for drift (in text mode too)
+/// and 1 for errors, with every action, link and drift item in the document
+#[test]
+fn test_01_json_output() {
+    let test_case_dir = Path::new("tests/07_output/basic").canonicalize().unwrap();
+    clean_test_case(&test_case_dir);
+
+    let (code, dry_run) = run_json(&test_case_dir, &["link", "--dry-run"], "dry-run.json");
+    assert_eq!(code, 2);
+    assert_eq!(dry_run["schemaVersion"], 1);
+    assert_eq!(dry_run["command"], "link");
+    assert_eq!(dry_run["outcome"], "pending");
+    assert_eq!(dry_run["actions"][0]["action"], "created");
+    assert_eq!(dry_run["actions"][0]["status"], "planned");
+    assert_eq!(dry_run["summary"]["created"], 1);
+
+    let (code, link) = run_json(&test_case_dir, &["link"], "link.json");
+    assert_eq!(code, 0);
+    assert_eq!(link["outcome"], "success");
+    assert_eq!(link["actions"][0]["status"], "applied");
+    assert!(link["actions"][0]["target"]
+        .as_str()
+        .unwrap()
+        .ends_with("target/fish/config.fish"));
+
+    let (code, status) = run_json(&test_case_dir, &["status"], "status.json");
+    assert_eq!(code, 0);
+    assert_eq!(status["links"][0]["health"], "inSync");
+    assert_eq!(status["summary"]["inSync"], 1);
+
+    fs::write(test_case_dir.join("target/fish/notes.txt"), "untracked").unwrap();
+    let (code, detect) = run_json(&test_case_dir, &["detect"], "detect.json");
+    assert_eq!(code, 3);
+    assert_eq!(detect["outcome"], "drift");
+    assert_eq!(detect["drift"][0]["drift"], "untracked");
+    assert_eq!(detect["drift"][0]["package"], "source/fish");
+    assert_eq!(detect["summary"]["untracked"], 1);
+
+    // Text mode exits with the same code
+    let config_path = test_case_dir.join("doty.kdl");
+    let (code, _) = run_doty_with_code(&["detect", "--config", config_path.to_str().unwrap()]);
+    assert_eq!(code, 3);
+
+    let (code, interactive) = run_json(&test_case_dir, &["detect", "-i"], "interactive.json");
+    assert_eq!(code, 1);
+    assert_eq!(interactive["outcome"], "error");
+    assert!(interactive["error"].is_string());
+
+    let (code, clean) = run_json(&test_case_dir, &["clean"], "clean.json");
+    assert_eq!(code, 0);
+    assert_eq!(clean["actions"][0]["action"], "removed");
+    assert_eq!(clean["summary"]["removed"], 1);
+}
+
+/// Test case: NDJSON writes one object per action and ends with the summary
+/// Context: doty.kdl links source/fish/config.fish into target/fish, nothing is linked yet
+///
+/// Expected: every line is a JSON object with schemaVersion and type, the last one is the summary
+#[test]
+fn test_02_ndjson_output() {
+    let test_case_dir = Path::new("tests/07_output/ndjson").canonicalize().unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let (code, output) = run_doty_with_code(&[
+        "link",
+        "--dry-run",
+        "--config",
+        config_path.to_str().unwrap(),
+        "--output",
+        "ndjson",
+    ]);
+    write_logfile(&test_case_dir, "link.ndjson", &output).expect("Failed to write log file");
+
+    let lines: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every line should be JSON"))
+        .collect();
+    assert!(lines.len() >= 2);
+    assert!(lines.iter().all(|line| line["schemaVersion"] == 1));
+    assert!(lines
+        .iter()
+        .any(|line| line["type"] == "action" && line["action"] == "created"));
+    let summary = lines.last().unwrap();
+    assert_eq!(summary["type"], "summary");
+    assert_eq!(summary["outcome"], "pending");
+    assert_eq!(code, 2);
+}
+
+/// Test case: quiet output prints nothing and leaves the outcome to the exit code
+/// Context: doty.kdl links source/fish/config.fish into target/fish, nothing is linked yet
+///
+/// Expected: empty stdout with exit code 2 for the dry run and 0 after linking
+#[test]
+fn test_03_quiet_output() {
+    let test_case_dir = Path::new("tests/07_output/quiet").canonicalize().unwrap();
+    clean_test_case(&test_case_dir);
+    let config_path = test_case_dir.join("doty.kdl");
+    let run = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend([
+            "--config",
+            config_path.to_str().unwrap(),
+            "--output",
+            "quiet",
+        ]);
+        run_doty_with_code(&args)
+    };
+
+    assert_eq!(run(&["link", "--dry-run"]), (2, String::new()));
+    assert_eq!(run(&["link"]), (0, String::new()));
+    assert!(test_case_dir.join("target/fish/config.fish").is_symlink());
+    assert_eq!(run(&["status"]), (0, String::new()));
+}
+
+/// Test case: errors carry a stable code and a hint, `explain-error` prints the long help
+/// Context:
+/// - doty.kdl links source/fish/config.fish into target/fish without an onConflict policy
+/// - the test writes target/fish/config.fish by hand before linking
+///
+/// Expected: link fails with exit code 1 and DOTY-E030 in the document, nothing is replaced, and
+/// `doty explain-error` knows the code
+#[test]
+fn test_04_error_codes() {
+    let test_case_dir = Path::new("tests/07_output/errors").canonicalize().unwrap();
+    clean_test_case(&test_case_dir);
+    let target = test_case_dir.join("target/fish/config.fish");
+    fs::create_dir_all(target.parent().unwrap()).unwrap();
+    fs::write(&target, "# written by hand").unwrap();
+
+    let (code, link) = run_json(&test_case_dir, &["link"], "link.json");
+    assert_eq!(code, 1);
+    assert_eq!(link["outcome"], "error");
+    assert_eq!(link["errorCode"], "DOTY-E030");
+    assert!(link["hint"].as_str().unwrap().contains("onConflict"));
+    assert!(!target.is_symlink());
+
+    let (code, explanation) = run_doty_with_code(&["explain-error", "e030"]);
+    assert_eq!(code, 0);
+    assert!(explanation.starts_with("DOTY-E030"));
+
+    let (code, list) = run_doty_with_code(&["explain-error"]);
+    assert_eq!(code, 0);
+    assert!(list.contains("DOTY-E002") && list.contains("DOTY-E040"));
+
+    let (code, _) = run_doty_with_code(&["explain-error", "DOTY-E999"]);
+    assert_eq!(code, 1);
+}
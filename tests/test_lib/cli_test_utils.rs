@@ -31,6 +31,23 @@ pub fn run_doty(args: &[impl AsRef<str>]) -> Result<String, String> {
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
+/// Helper function to run doty and keep its exit code, for commands that are expected to fail
+/// ## Parameters
+/// args: array of arguments to pass to the doty command
+/// ## Returns
+/// The exit code and stdout
+pub fn run_doty_with_code(args: &[impl AsRef<str>]) -> (i32, String) {
+    let output = Command::new(get_doty_binary())
+        .args(args.iter().map(|arg| arg.as_ref()))
+        .output()
+        .expect("Failed to execute doty");
+
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+    )
+}
+
 /// Helper function to run doty link command
 pub fn run_doty_link(config_path: &Path) -> Result<String, String> {
     let binary = get_doty_binary();
@@ -50,6 +67,7 @@ pub fn run_doty_link(config_path: &Path) -> Result<String, String> {
 }
 
 /// Helper function to run doty link command with --dry-run flag
+/// Exit code 2 (changes pending) counts as success, a dry run doesn't change anything
 pub fn run_doty_link_dry_run(config_path: &Path) -> Result<String, String> {
     let binary = get_doty_binary();
     let output = Command::new(binary)
@@ -60,7 +78,7 @@ pub fn run_doty_link_dry_run(config_path: &Path) -> Result<String, String> {
         .output()
         .map_err(|e| format!("Failed to execute doty: {}", e))?;
 
-    if !output.status.success() {
+    if !matches!(output.status.code(), Some(0 | 2)) {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(format!("doty link --dry-run failed: {}", stderr));
     }
@@ -1 +1,4 @@
+// Every integration test binary compiles its own copy of these helpers and only uses a subset
+#![allow(dead_code)]
+
 pub mod cli_test_utils;